@@ -1,13 +1,22 @@
-async function fetchQueue(){ const r = await fetch('/api/queue', {cache:'no-store'}); return await r.json(); }
+function currentTarget(){ const sel = document.getElementById('target'); return sel ? sel.value : ''; }
+async function fetchQueue(){ const t = currentTarget(); const r = await fetch('/api/queue' + (t ? '?target=' + encodeURIComponent(t) : ''), {cache:'no-store'}); return await r.json(); }
+async function loadTargets(){
+  const sel = document.getElementById('target');
+  const targets = await (await fetch('/api/targets', {cache:'no-store'})).json();
+  sel.innerHTML = '';
+  for(const t of targets){ const o = document.createElement('option'); o.value = t.name; o.textContent = t.name + (t.mode === 'mirror' ? ' (mirrors ' + t.rotation + ')' : ''); sel.appendChild(o); }
+  sel.style.display = targets.length > 1 ? '' : 'none';
+}
 async function removeItem(id){
-  const res = await fetch('/api/admin/remove', { method:'POST', headers:{'Content-Type':'application/x-www-form-urlencoded'}, body: new URLSearchParams({ id:String(id) }) });
+  const res = await fetch('/api/admin/remove', { method:'POST', headers:adminHeaders(), body: new URLSearchParams({ id:String(id) }) });
   if(!res.ok){ alert('Remove failed: '+await res.text()); }
   await render();
 }
 function li(item, label){
   const li = document.createElement('li');
   const sw = document.createElement('span'); sw.className='swatch'; sw.style.background = item.color || '#ffd700'; li.appendChild(sw);
-  const t = document.createElement('span'); t.className='text'; t.textContent = item.text; li.appendChild(t);
+  const t = document.createElement('span'); t.className='text'; t.textContent = item.text + (item.name ? ' — ' + item.name : ''); li.appendChild(t);
+  if(item.eta_seconds != null){ const e = document.createElement('span'); e.className='tag'; e.textContent = 'in ' + Math.round(item.eta_seconds) + 's'; li.appendChild(e); }
   if(label){ const tag = document.createElement('span'); tag.className='tag'; tag.textContent = label; li.appendChild(tag); }
   const btn = document.createElement('button'); btn.className='danger'; btn.textContent='Remove'; btn.onclick = ()=> removeItem(item.id); li.appendChild(btn);
   return li;
@@ -15,14 +24,201 @@ function li(item, label){
 async function render(){
   const data = await fetchQueue();
   const ul = document.getElementById('list'); ul.innerHTML='';
-  if(data.current){ ul.appendChild(li(data.current, 'Current')); }
+  held = !!data.held;
+  document.getElementById('hold').textContent = held ? 'Resume' : 'Pause';
+  if(data.current){ ul.appendChild(li(data.current, held ? 'Current • paused' : 'Current')); }
   for(const it of data.items||[]){ ul.appendChild(li(it)); }
+  document.getElementById('policy').textContent = data.policy ? 'Order: ' + data.policy : '';
   if(!data.current && (!data.items || data.items.length===0)){
     const e = document.createElement('li'); e.textContent = 'Queue is empty'; ul.appendChild(e);
   }
 }
+let held = false;
+async function sendAdminMessage(){
+  const text = document.getElementById('admin-text');
+  const res = await postForm('/api/admin/message', { target:currentTarget(), text:text.value, name:document.getElementById('admin-name').value, weight:document.getElementById('admin-weight').value });
+  if(res.ok){ text.value=''; }
+  await render();
+}
+async function skip(){
+  const res = await fetch('/api/admin/skip', { method:'POST', headers:{'Content-Type':'application/x-www-form-urlencoded'}, body: new URLSearchParams({ target:currentTarget() }) });
+  if(!res.ok){ alert('Skip failed: '+await res.text()); }
+  await render();
+}
+async function toggleHold(){
+  const res = await fetch('/api/admin/hold', { method:'POST', headers:{'Content-Type':'application/x-www-form-urlencoded'}, body: new URLSearchParams({ target:currentTarget(), held:String(!held) }) });
+  if(!res.ok){ alert('Pause failed: '+await res.text()); }
+  await render();
+}
+async function rediscover(target){
+  const res = await fetch('/api/admin/rediscover', { method:'POST', headers:{'Content-Type':'application/x-www-form-urlencoded'}, body: new URLSearchParams({ target }) });
+  if(!res.ok){ alert('Rediscover failed: '+await res.text()); }
+  await renderStatus();
+}
+async function renderStatus(){
+  const data = await (await fetch('/api/status', {cache:'no-store'})).json();
+  const ul = document.getElementById('status'); ul.innerHTML='';
+  for(const t of data.targets||[]){
+    const li = document.createElement('li');
+    const caps = t.capabilities;
+    const name = document.createElement('span'); name.className='text'; name.textContent = t.name + ' • ' + t.transport + (caps && caps.version ? ' • WLED ' + caps.version : ''); li.appendChild(name);
+    const check = t.last_check;
+    if(check && !check.ok){ const w = document.createElement('span'); w.className='warn'; w.textContent = check.mismatches.join('; '); li.appendChild(w); }
+    const tag = document.createElement('span'); tag.className='tag'; tag.textContent = t.online ? 'online' : 'offline'; li.appendChild(tag);
+    const btn = document.createElement('button'); btn.textContent='Rediscover'; btn.onclick = ()=> rediscover(t.name); li.appendChild(btn);
+    ul.appendChild(li);
+  }
+}
+// who is at the keyboard, for the audit log
+function adminHeaders(){ return {'Content-Type':'application/x-www-form-urlencoded'}; }
+async function postForm(url, params){
+  const res = await fetch(url, { method:'POST', headers:adminHeaders(), body: new URLSearchParams(params) });
+  if(!res.ok){ alert('Request failed: '+await res.text()); }
+  return res;
+}
+async function renderPresets(){
+  const t = currentTarget();
+  const ul = document.getElementById('presets'); ul.innerHTML='';
+  const r = await fetch('/api/admin/presets' + (t ? '?target=' + encodeURIComponent(t) : ''), {cache:'no-store'});
+  if(!r.ok){ const e = document.createElement('li'); e.textContent = 'Presets unavailable: ' + await r.text(); ul.appendChild(e); return; }
+  const data = await r.json();
+  for(const p of data.presets||[]){
+    const li = document.createElement('li');
+    const text = document.createElement('input'); text.type='radio'; text.name='text-preset'; text.value=String(p.id); text.title='Text preset'; text.checked = data.text_preset_id === p.id; li.appendChild(text);
+    const scene = document.createElement('input'); scene.type='checkbox'; scene.className='scene'; scene.value=String(p.id); scene.title='Idle scene'; scene.checked = (data.scenes||[]).includes(p.id); li.appendChild(scene);
+    const name = document.createElement('span'); name.className='text'; name.textContent = p.id + ' • ' + (p.name || '(unnamed)'); li.appendChild(name);
+    if(p.playlist){ const tag = document.createElement('span'); tag.className='tag'; tag.textContent='playlist'; li.appendChild(tag); }
+    const prev = document.createElement('button'); prev.textContent='Preview'; prev.onclick = ()=> postForm('/api/admin/presets/preview', { target:t, id:String(p.id) }); li.appendChild(prev);
+    const act = document.createElement('button'); act.textContent='Activate'; act.onclick = ()=> postForm('/api/admin/presets/activate', { target:t, id:String(p.id) }); li.appendChild(act);
+    ul.appendChild(li);
+  }
+  if(!data.presets || data.presets.length===0){ const e = document.createElement('li'); e.textContent = 'No presets stored on this device'; ul.appendChild(e); }
+}
+async function renderAccess(){
+  const data = await (await fetch('/api/admin/access', {cache:'no-store'})).json();
+  const ul = document.getElementById('access'); ul.innerHTML='';
+  for(const c of data.codes||[]){
+    const li = document.createElement('li');
+    const name = document.createElement('span'); name.className='text'; name.textContent = (c.label || '(no label)') + ' • ' + c.code; li.appendChild(name);
+    const tag = document.createElement('span'); tag.className='tag';
+    tag.textContent = c.revoked ? 'revoked' : !c.active ? 'expired' : c.expires ? 'until ' + new Date(c.expires*1000).toLocaleString() : 'active';
+    li.appendChild(tag);
+    const qr = document.createElement('a'); qr.href = '/api/admin/access/qr?code=' + encodeURIComponent(c.code); qr.target='_blank'; qr.textContent='QR'; li.appendChild(qr);
+    const png = document.createElement('a'); png.href = '/api/admin/access/qr?format=png&code=' + encodeURIComponent(c.code); png.download = 'qr-' + c.code + '.png'; png.textContent='PNG'; li.appendChild(png);
+    const cards = document.createElement('a'); cards.href = '/admin/access/cards?code=' + encodeURIComponent(c.code); cards.target='_blank'; cards.textContent='Table cards'; li.appendChild(cards);
+    if(c.active){ const btn = document.createElement('button'); btn.className='danger'; btn.textContent='Revoke'; btn.onclick = async ()=>{ if(confirm('Revoke ' + c.code + '? Guests who came in with it are locked out.')){ await postForm('/api/admin/access/revoke', { code:c.code }); await renderAccess(); } }; li.appendChild(btn); }
+    ul.appendChild(li);
+  }
+  if(!data.codes || data.codes.length===0){ const e = document.createElement('li'); e.textContent = 'No codes: the guest page is open to anyone with the link'; ul.appendChild(e); }
+}
+async function createAccessCode(){
+  const params = { label: document.getElementById('access-label').value };
+  const hours = document.getElementById('access-hours').value;
+  if(hours) params.hours = hours;
+  await postForm('/api/admin/access', params);
+  await renderAccess();
+}
+const SETTINGS = ['display_seconds','brightness','max_text_len','max_name_len'];
+async function renderSettings(){
+  const data = await (await fetch('/api/admin/settings', {cache:'no-store'})).json();
+  for(const k of SETTINGS){ document.getElementById('set-'+k).value = data.settings[k]; }
+  const theme = data.settings.theme || {};
+  for(const k of THEME){ document.getElementById('theme-'+k).value = theme[k] || ''; }
+  document.getElementById('theme-photo-name').textContent = theme.photo || 'no photo';
+  const ul = document.getElementById('settings-audit'); ul.innerHTML='';
+  for(const e of (data.audit||[]).slice(0, 20)){
+    const c = e.detail;
+    const li = document.createElement('li');
+    const t = document.createElement('span'); t.className='text'; t.textContent = c.key + ': ' + JSON.stringify(c.old) + ' → ' + JSON.stringify(c.new); li.appendChild(t);
+    const tag = document.createElement('span'); tag.className='tag'; tag.textContent = e.actor + ' • ' + new Date(e.at*1000).toLocaleTimeString(); li.appendChild(tag);
+    ul.appendChild(li);
+  }
+  if(!data.audit || data.audit.length===0){ const e = document.createElement('li'); e.textContent = 'No changes yet'; ul.appendChild(e); }
+}
+async function saveSettings(){
+  const params = {};
+  for(const k of SETTINGS){ params[k] = document.getElementById('set-'+k).value; }
+  await postForm('/api/admin/settings', params);
+  await renderSettings();
+}
+const THEME = ['couple','date','accent','background','welcome','footer'];
+async function saveTheme(){
+  const params = {};
+  for(const k of THEME){ params[k] = document.getElementById('theme-'+k).value; }
+  await postForm('/api/admin/theme', params);
+  await renderSettings();
+}
+async function uploadThemePhoto(){
+  const file = document.getElementById('theme-photo').files[0];
+  if(!file){ alert('Pick a photo first'); return; }
+  const body = new FormData(); body.append('photo', file);
+  const res = await fetch('/api/admin/theme/photo', { method:'POST', body });
+  if(!res.ok){ alert('Upload failed: '+await res.text()); }
+  await renderSettings();
+}
+async function removeThemePhoto(){
+  await postForm('/api/admin/theme', { photo:'' });
+  await renderSettings();
+}
+async function savePresetSelection(){
+  const text = document.querySelector('input[name=text-preset]:checked');
+  const scenes = [...document.querySelectorAll('input.scene:checked')].map(c => c.value).join(',');
+  await postForm('/api/admin/presets/settings', { target:currentTarget(), text_preset_id: text ? text.value : '', scenes });
+  renderSettings().catch(()=>{});
+  await renderPresets();
+}
+function auditQuery(){
+  const q = new URLSearchParams();
+  for(const k of ['action','actor','message_id']){ const v = document.getElementById('audit-'+k).value.trim(); if(v) q.set(k, v); }
+  return q;
+}
+async function renderAudit(){
+  const q = auditQuery();
+  document.getElementById('audit-export').href = '/api/admin/audit/export?' + q;
+  q.set('limit', '100');
+  const res = await fetch('/api/admin/audit?' + q, {cache:'no-store'});
+  const ul = document.getElementById('audit'); ul.innerHTML='';
+  if(!res.ok){ const e = document.createElement('li'); e.textContent = await res.text(); ul.appendChild(e); return; }
+  const data = await res.json();
+  for(const e of data.entries){
+    const li = document.createElement('li');
+    const what = e.action + (e.message_id != null ? ' #' + e.message_id : '') + (e.target ? ' on ' + e.target : '');
+    const detail = e.detail ? ' ' + JSON.stringify(e.detail) : '';
+    const t = document.createElement('span'); t.className='text'; t.textContent = what + detail; li.appendChild(t);
+    const tag = document.createElement('span'); tag.className='tag'; tag.textContent = e.actor + (e.ip ? ' (' + e.ip + ')' : '') + ' • ' + new Date(e.at*1000).toLocaleTimeString(); li.appendChild(tag);
+    ul.appendChild(li);
+  }
+  if(data.entries.length===0){ const e = document.createElement('li'); e.textContent = 'Nothing logged'; ul.appendChild(e); }
+}
+async function generateTextPreset(){
+  await postForm('/api/admin/presets/text', { target:currentTarget() });
+  renderSettings().catch(()=>{});
+  await renderPresets();
+}
 document.addEventListener('DOMContentLoaded', () => {
   document.getElementById('refresh').onclick = render;
-  render();
+  document.getElementById('skip').onclick = skip;
+  document.getElementById('hold').onclick = toggleHold;
+  document.getElementById('admin-send').onclick = sendAdminMessage;
+  let preview = null;
+  document.getElementById('target').onchange = ()=>{ render(); renderPresets().catch(()=>{}); if(preview) preview.connect(); };
+  document.getElementById('presets-refresh').onclick = ()=> renderPresets().catch(()=>{});
+  document.getElementById('presets-generate').onclick = generateTextPreset;
+  document.getElementById('presets-save').onclick = savePresetSelection;
+  document.getElementById('access-create').onclick = createAccessCode;
+  document.getElementById('settings-save').onclick = saveSettings;
+  document.getElementById('theme-save').onclick = saveTheme;
+  document.getElementById('theme-upload').onclick = uploadThemePhoto;
+  document.getElementById('theme-photo-remove').onclick = removeThemePhoto;
+  renderSettings().catch(()=>{});
+  document.getElementById('audit-load').onclick = ()=> renderAudit().catch(()=>{});
+  renderAudit().catch(()=>{});
+  renderAccess().catch(()=>{});
+  loadTargets().catch(()=>{}).then(()=>{
+    preview = new CurtainPreview(document.getElementById('preview'), currentTarget, '/api/admin/preview/ws');
+    render(); renderPresets().catch(()=>{});
+  });
+  renderStatus().catch(()=>{});
+  setInterval(()=>{ renderStatus().catch(()=>{}); }, 10000);
 });
 
@@ -3,42 +3,84 @@
     nl: {
       subtitle: 'Laat je felicitatie schitteren op het LED gordijn ✨',
       message_label: 'Jouw bericht',
+      name_label: 'Jouw naam (optioneel)',
       color_label: 'Kleur',
+      target_label: 'Scherm',
       submit_btn: 'Stuur naar gordijn',
       note: 'Max 64 tekens. Houd het lief en feestelijk 💛',
       queue_title: 'Berichten wachtrij',
       queue_empty: 'Geen berichten in de wachtrij…',
       footer: 'Met liefde gemaakt • Wens fijn en respectvol 💐',
       error_prefix: 'Mislukt:',
-      placeholder_text: 'Liefde, geluk en een lang leven samen!'
+      placeholder_text: 'Liefde, geluk en een lang leven samen!',
+      preview_title: 'Nu op het gordijn',
+      preview_btn: 'Voorbeeld',
+      mine_title: 'Mijn berichten',
+      sent_queued: 'Je bericht staat op plek {pos}, over ongeveer {min} min op het gordijn.',
+      sent_waiting: 'Je bericht staat op plek {pos} in de wachtrij.',
+      live_now: 'Je bericht staat nu op het gordijn! 🎉',
+      live_hint: 'Kijk snel naar het gordijn',
+      sent_now: 'Je bericht staat nu op het gordijn!',
+      status_showing: 'Nu te zien',
+      edit_btn: 'Wijzig',
+      cancel_btn: 'Annuleer',
+      edit_prompt: 'Pas je bericht aan:'
     },
     fr: {
       subtitle: 'Faites briller votre félicitation sur le rideau LED ✨',
       message_label: 'Votre message',
+      name_label: 'Votre nom (facultatif)',
       color_label: 'Couleur',
+      target_label: 'Écran',
       submit_btn: 'Envoyer au rideau',
       note: '64 caractères max. Restez gentil et festif 💛',
       queue_title: 'File d’attente des messages',
       queue_empty: 'Aucun message dans la file d’attente…',
       footer: 'Fait avec amour • Souhaitez avec gentillesse 💐',
       error_prefix: 'Échec :',
-      placeholder_text: 'Amour, bonheur et une longue vie ensemble !'
+      placeholder_text: 'Amour, bonheur et une longue vie ensemble !',
+      preview_title: 'En ce moment sur le rideau',
+      preview_btn: 'Aperçu',
+      mine_title: 'Mes messages',
+      sent_queued: 'Votre message est en position {pos}, sur le rideau dans environ {min} min.',
+      sent_waiting: 'Votre message est en position {pos} dans la file.',
+      live_now: 'Votre message est sur le rideau ! 🎉',
+      live_hint: 'Regardez vite le rideau',
+      sent_now: 'Votre message est sur le rideau !',
+      status_showing: 'À l’écran',
+      edit_btn: 'Modifier',
+      cancel_btn: 'Annuler',
+      edit_prompt: 'Modifiez votre message :'
     },
     de: {
       subtitle: 'Lass deine Glückwünsche auf dem LED‑Vorhang erstrahlen ✨',
       message_label: 'Deine Nachricht',
+      name_label: 'Dein Name (optional)',
       color_label: 'Farbe',
+      target_label: 'Anzeige',
       submit_btn: 'An den Vorhang senden',
       note: 'Max. 64 Zeichen. Bitte lieb und festlich 💛',
       queue_title: 'Nachrichten‑Warteschlange',
       queue_empty: 'Keine Nachrichten in der Warteschlange…',
       footer: 'Mit Liebe gemacht • Wünsche freundlich und respektvoll 💐',
       error_prefix: 'Fehlgeschlagen:',
-      placeholder_text: 'Liebe, Glück und ein langes gemeinsames Leben!'
+      placeholder_text: 'Liebe, Glück und ein langes gemeinsames Leben!',
+      preview_title: 'Jetzt auf dem Vorhang',
+      preview_btn: 'Vorschau',
+      mine_title: 'Meine Nachrichten',
+      sent_queued: 'Deine Nachricht ist auf Platz {pos}, in etwa {min} Min. auf dem Vorhang.',
+      sent_waiting: 'Deine Nachricht ist auf Platz {pos} der Warteschlange.',
+      live_now: 'Deine Nachricht ist jetzt auf dem Vorhang! 🎉',
+      live_hint: 'Schnell zum Vorhang schauen',
+      sent_now: 'Deine Nachricht ist jetzt auf dem Vorhang!',
+      status_showing: 'Jetzt zu sehen',
+      edit_btn: 'Ändern',
+      cancel_btn: 'Abbrechen',
+      edit_prompt: 'Ändere deine Nachricht:'
     }
   };
   function getLang(){ return localStorage.getItem('lang') || 'nl'; }
-  function setLang(l){ localStorage.setItem('lang', l); applyTranslations(); markActiveLang(); refreshQueue().catch(()=>{}); }
+  function setLang(l){ localStorage.setItem('lang', l); applyTranslations(); markActiveLang(); refreshQueue().catch(()=>{}); refreshMine().catch(()=>{}); }
   function tr(key){ const lang = getLang(); return (I18N[lang] && I18N[lang][key]) || (I18N['nl'] && I18N['nl'][key]) || null; }
   function applyTranslations(){
     document.querySelectorAll('[data-i18n]').forEach(el=>{
@@ -47,6 +89,11 @@
       if(v != null) el.textContent = v; // only replace when we have a translation
     });
     const input = document.getElementById('text'); if(input){ const ph = tr('placeholder_text'); if(ph!=null) input.placeholder = ph; }
+    // the wedding date, written out in the chosen language
+    document.querySelectorAll('time[datetime]').forEach(el=>{
+      const d = new Date(el.getAttribute('datetime') + 'T12:00:00');
+      if(!isNaN(d)) el.textContent = d.toLocaleDateString(getLang(), { day:'numeric', month:'long', year:'numeric' });
+    });
   }
   function markActiveLang(){ const sel = document.getElementById('langSelector'); if(!sel) return; const cur = getLang(); sel.querySelectorAll('button[data-lang]').forEach(btn=>{ btn.classList.toggle('active', btn.getAttribute('data-lang')===cur); }); }
 
@@ -54,11 +101,82 @@
     ev.preventDefault();
     const fd = new FormData(ev.target);
     const res = await fetch('/api/message', { method: 'POST', body: new URLSearchParams(fd) });
-    if(res.ok){ ev.target.reset(); try{ await refreshQueue(); }catch(e){} }
+    if(res.ok){
+      const tgt = getTarget(); const name = ev.target.elements.name.value; ev.target.reset(); setTarget(tgt); ev.target.elements.name.value = name;
+      showSent(await res.json());
+      listenLive();
+      if('Notification' in window && Notification.permission === 'default'){ Notification.requestPermission().catch(()=>{}); }
+      try{ await refreshQueue(); }catch(e){}
+      refreshMine().catch(()=>{});
+    }
     else { const tt = await res.text(); const pref = tr('error_prefix') || 'Mislukt:'; alert(pref+' '+tt); }
   }
   window.submitMessage = submitMessage;
 
+  function fmt(key, vars){ return (tr(key) || '').replace(/\{(\w+)\}/g, (_, k)=> String(vars[k])); }
+  function showSent(res){
+    const el = document.getElementById('sentNote'); if(!el) return;
+    if(res.position === 0) el.textContent = tr('sent_now');
+    else if(res.eta_seconds == null) el.textContent = fmt('sent_waiting', { pos: res.position });
+    else el.textContent = fmt('sent_queued', { pos: res.position, min: Math.max(1, Math.round(res.eta_seconds/60)) });
+    el.style.display = '';
+  }
+  // "your message is on the curtain now": server-sent events for this browser's messages
+  let live = null;
+  function listenLive(){
+    if(live || !window.EventSource) return;
+    live = new EventSource('/api/my/events');
+    live.addEventListener('live', (ev)=>{
+      const m = JSON.parse(ev.data);
+      showLive(m);
+      refreshMine().catch(()=>{}); refreshQueue().catch(()=>{});
+    });
+  }
+  function showLive(m){
+    const el = document.getElementById('liveBanner');
+    if(el){
+      el.querySelector('.live-title').textContent = tr('live_now');
+      el.querySelector('.live-text').textContent = '“' + m.text + '”';
+      el.style.display = '';
+      clearTimeout(showLive.timer); showLive.timer = setTimeout(()=>{ el.style.display = 'none'; }, 60000);
+    }
+    if(navigator.vibrate) navigator.vibrate([200, 100, 200]);
+    if('Notification' in window && Notification.permission === 'granted' && document.hidden){
+      try{ new Notification(tr('live_now'), { body: m.text + ' — ' + tr('live_hint') }); }catch(e){}
+    }
+  }
+  async function postMine(action, params){
+    const res = await fetch('/api/my/messages/' + action, { method: 'POST', body: new URLSearchParams(params) });
+    if(!res.ok){ alert((tr('error_prefix') || 'Mislukt:') + ' ' + await res.text()); }
+    await refreshMine(); refreshQueue().catch(()=>{});
+  }
+  async function refreshMine(){
+    const box = document.getElementById('mineBox'); const ul = document.getElementById('mineList');
+    if(!box || !ul) return;
+    const data = await (await fetch('/api/my/messages', { cache: 'no-store' })).json();
+    const items = data.items || [];
+    box.style.display = items.length ? '' : 'none';
+    if(items.length) listenLive();
+    ul.innerHTML = '';
+    for(const m of items){
+      const li = document.createElement('li'); li.className = 'queue-item' + (m.status === 'showing' ? ' current' : '');
+      const sw = document.createElement('span'); sw.className='swatch'; sw.style.background = m.color || '#ffd700'; li.appendChild(sw);
+      const text = document.createElement('span'); text.className='text'; text.textContent = m.text; li.appendChild(text);
+      const tag = document.createElement('span'); tag.className='eta';
+      tag.textContent = m.status === 'showing' ? tr('status_showing') : '#' + m.position + (m.eta_seconds != null ? ' • ≈ ' + Math.max(1, Math.round(m.eta_seconds/60)) + ' min' : '');
+      li.appendChild(tag);
+      if(m.status === 'waiting'){
+        const edit = document.createElement('button'); edit.type='button'; edit.className='secondary small'; edit.textContent = tr('edit_btn');
+        edit.onclick = ()=>{ const t = prompt(tr('edit_prompt'), m.text); if(t && t.trim()) postMine('edit', { id: String(m.id), text: t, color: m.color || '' }); };
+        li.appendChild(edit);
+        const cancel = document.createElement('button'); cancel.type='button'; cancel.className='secondary small'; cancel.textContent = tr('cancel_btn');
+        cancel.onclick = ()=> postMine('cancel', { id: String(m.id) });
+        li.appendChild(cancel);
+      }
+      ul.appendChild(li);
+    }
+  }
+
   function hsvToRgb(h, s, v){
     const c = v * s;
     const x = c * (1 - Math.abs(((h / 60) % 2) - 1));
@@ -154,10 +272,22 @@
     window.addEventListener('touchend', ()=> dragging=false);
     moveDotTo(hidden.value || '#ffd700');
   }
+  function getTarget(){ const sel = document.getElementById('target'); return sel ? sel.value : ''; }
+  function setTarget(name){ const sel = document.getElementById('target'); if(sel && name) sel.value = name; }
+  async function loadTargets(){
+    const sel = document.getElementById('target'); const row = document.getElementById('targetRow');
+    if(!sel || !row) return;
+    const r = await fetch('/api/targets', { cache: 'no-store' });
+    const targets = await r.json();
+    sel.innerHTML = '';
+    for(const t of targets){ const o = document.createElement('option'); o.value = t.name; o.textContent = t.name; sel.appendChild(o); }
+    row.style.display = targets.length > 1 ? '' : 'none';
+  }
   async function refreshQueue(){
     const ul = document.getElementById('queueList');
     if(!ul) return;
-    const r = await fetch('/api/queue', { cache: 'no-store' });
+    const tgt = getTarget();
+    const r = await fetch('/api/queue' + (tgt ? '?target=' + encodeURIComponent(tgt) : ''), { cache: 'no-store' });
     const data = await r.json();
     ul.innerHTML = '';
     if(!data.current && (!data.items || data.items.length===0)){
@@ -166,8 +296,9 @@
     const renderItem = (item, isCurrent, elapsed) => {
       const li = document.createElement('li'); li.className='queue-item'+(isCurrent?' current':'');
       const sw = document.createElement('span'); sw.className='swatch'; sw.style.background = item.color || '#ffd700'; li.appendChild(sw);
-      const text = document.createElement('span'); text.className='text'; text.textContent=item.text; li.appendChild(text);
-      if(isCurrent){ const t = document.createElement('span'); t.className='timer'; const s = Math.max(0, Math.min(60, Math.floor(elapsed||0))); t.textContent = '⏱ ' + String(s).padStart(2,'0')+'s'; t.setAttribute('data-elapsed', String(s)); li.appendChild(t); }
+      const text = document.createElement('span'); text.className='text'; text.textContent=item.text + (item.name ? ' — ' + item.name : ''); li.appendChild(text);
+      if(!isCurrent && item.eta_seconds != null){ const e = document.createElement('span'); e.className='eta'; e.textContent = '≈ ' + Math.max(1, Math.round(item.eta_seconds/60)) + ' min'; li.appendChild(e); }
+      if(isCurrent){ const t = document.createElement('span'); t.className='timer'; const s = Math.max(0, Math.min(60, Math.floor(elapsed||0))); t.textContent = (data.paused ? '⏸ ' : '⏱ ') + String(s).padStart(2,'0')+'s'; t.setAttribute('data-elapsed', String(s)); if(data.paused) t.setAttribute('data-paused', '1'); li.appendChild(t); }
       return li;
     };
     if(data.current){ ul.appendChild(renderItem(data.current, true, data.elapsed_seconds)); }
@@ -176,7 +307,7 @@
 
   function tickTimer(){
     const t = document.querySelector('.queue-item.current .timer');
-    if(!t) return; let s = parseInt(t.getAttribute('data-elapsed')||'0',10); s = Math.min(60, s+1); t.setAttribute('data-elapsed', String(s)); t.textContent='⏱ ' + String(s).padStart(2,'0')+'s';
+    if(!t || t.hasAttribute('data-paused')) return; let s = parseInt(t.getAttribute('data-elapsed')||'0',10); s = Math.min(60, s+1); t.setAttribute('data-elapsed', String(s)); t.textContent='⏱ ' + String(s).padStart(2,'0')+'s';
   }
 
   function boot(){
@@ -186,8 +317,18 @@
     if(sel){ sel.addEventListener('click', (e)=>{ const btn = e.target.closest('button[data-lang]'); if(btn){ setLang(btn.getAttribute('data-lang')); }}); }
     applyTranslations();
     markActiveLang();
-    refreshQueue();
-    setInterval(()=>{ refreshQueue().catch(()=>{}); }, 5000);
+    const tsel = document.getElementById('target');
+    let preview = null;
+    if(tsel){ tsel.addEventListener('change', ()=>{ refreshQueue().catch(()=>{}); if(preview) preview.connect(); }); }
+    loadTargets().catch(()=>{}).then(()=>{
+      const canvas = document.getElementById('preview');
+      if(canvas && window.CurtainPreview){ preview = new window.CurtainPreview(canvas, getTarget); }
+      return refreshQueue();
+    });
+    const pbtn = document.getElementById('previewBtn');
+    if(pbtn){ pbtn.addEventListener('click', ()=>{ if(preview) preview.simulate(document.getElementById('text').value, document.getElementById('color').value); }); }
+    refreshMine().catch(()=>{});
+    setInterval(()=>{ refreshQueue().catch(()=>{}); refreshMine().catch(()=>{}); }, 5000);
     setInterval(tickTimer, 1000);
   }
 
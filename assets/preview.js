@@ -0,0 +1,82 @@
+// Live curtain preview: draws frames from /api/preview/ws (or the admin page's own socket) onto
+// a canvas, and can simulate a message scrolling across the curtain before it is sent.
+(function(){
+  function CurtainPreview(canvas, getTarget, path){
+    this.canvas = canvas;
+    this.getTarget = getTarget || (()=> '');
+    this.path = path || '/api/preview/ws';
+    this.w = 32; this.h = 16;
+    this.frame = null;
+    this.offline = false;
+    this.sim = null;
+    this.ws = null;
+    this.connect();
+    setInterval(()=> this.draw(), 80);
+  }
+  CurtainPreview.prototype.connect = function(){
+    if(this.ws){ this.ws.onclose = null; this.ws.close(); }
+    const t = this.getTarget();
+    const proto = location.protocol === 'https:' ? 'wss:' : 'ws:';
+    const ws = new WebSocket(proto + '//' + location.host + this.path + (t ? '?target=' + encodeURIComponent(t) : ''));
+    ws.onmessage = (ev)=>{
+      const f = JSON.parse(ev.data);
+      this.offline = !!f.offline;
+      if(!f.offline){ this.frame = f; this.w = f.w; this.h = f.h; }
+    };
+    ws.onclose = ()=>{ setTimeout(()=> this.connect(), 3000); };
+    this.ws = ws;
+  };
+  // Scrolls `text` in `color` across the preview for a few passes instead of the live view.
+  CurtainPreview.prototype.simulate = function(text, color){
+    if(!text) return;
+    const h = this.h;
+    const off = document.createElement('canvas');
+    const ctx = off.getContext('2d');
+    ctx.font = 'bold ' + h + 'px monospace';
+    const tw = Math.ceil(ctx.measureText(text).width);
+    off.width = tw; off.height = h;
+    ctx.font = 'bold ' + h + 'px monospace';
+    ctx.textBaseline = 'middle';
+    ctx.fillStyle = '#fff';
+    ctx.fillText(text, 0, h/2);
+    this.sim = { img: ctx.getImageData(0, 0, tw, h), tw, color: color || '#ffd700', start: performance.now() };
+  };
+  CurtainPreview.prototype.pixels = function(){
+    const w = this.w, h = this.h;
+    if(this.sim){
+      const s = this.sim;
+      const span = s.tw + w;
+      const shift = Math.floor((performance.now() - s.start) / 60);
+      if(shift > span * 2){ this.sim = null; return this.pixels(); }
+      const x0 = (shift % span) - w;
+      const out = new Array(w*h).fill('#000');
+      for(let y=0;y<h;y++) for(let x=0;x<w;x++){
+        const sx = x + x0;
+        if(sx < 0 || sx >= s.tw) continue;
+        if(s.img.data[(y*s.tw + sx)*4 + 3] > 100) out[y*w + x] = s.color;
+      }
+      return out;
+    }
+    if(this.frame && !this.offline) return this.frame.leds.map(c => '#' + c);
+    return null;
+  };
+  CurtainPreview.prototype.draw = function(){
+    const c = this.canvas, ctx = c.getContext('2d');
+    const px = this.pixels();
+    ctx.fillStyle = '#050507'; ctx.fillRect(0, 0, c.width, c.height);
+    if(!px){
+      ctx.fillStyle = '#777'; ctx.font = '14px system-ui'; ctx.textAlign = 'center';
+      ctx.fillText(this.offline ? 'offline' : '…', c.width/2, c.height/2);
+      return;
+    }
+    const cell = Math.min(c.width / this.w, c.height / this.h);
+    const ox = (c.width - cell*this.w)/2, oy = (c.height - cell*this.h)/2;
+    for(let y=0;y<this.h;y++) for(let x=0;x<this.w;x++){
+      const col = px[y*this.w + x];
+      if(!col || col === '#000' || col === '#000000') continue;
+      ctx.fillStyle = col;
+      ctx.beginPath(); ctx.arc(ox + (x+0.5)*cell, oy + (y+0.5)*cell, cell*0.4, 0, Math.PI*2); ctx.fill();
+    }
+  };
+  window.CurtainPreview = CurtainPreview;
+})();
@@ -0,0 +1,488 @@
+//! Event access codes: once any code exists, the guest page and its API only answer browsers
+//! that arrived through a valid code, e.g. by scanning the QR code on their table card.
+//!
+//! A code in the URL (`/?code=…`) is swapped for a cookie. Codes can expire or be revoked from
+//! the admin page; they are kept in `access-codes.json` in the data directory.
+
+use std::{
+    net::SocketAddr,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::{ConnectInfo, Query, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+    Form,
+};
+use qrcode::{render::svg, QrCode};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+use crate::{
+    audit::{Action, Client},
+    metrics, session, AppState,
+};
+
+const COOKIE: &str = "gordijn_access";
+/// Longest a browser stays let in without coming back through a code.
+const MAX_AGE_SECS: u64 = 7 * 24 * 3600;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AccessCode {
+    pub code: String,
+    /// What it is for, e.g. "tables" or "family".
+    pub label: String,
+    /// Unix seconds.
+    pub created: u64,
+    pub expires: Option<u64>,
+    #[serde(default)]
+    pub revoked: bool,
+}
+
+impl AccessCode {
+    fn usable(&self, now: u64) -> bool {
+        !self.revoked && self.expires.is_none_or(|e| now < e)
+    }
+}
+
+pub struct AccessCodes {
+    path: PathBuf,
+    codes: Mutex<Vec<AccessCode>>,
+}
+
+impl AccessCodes {
+    /// Reads the stored codes and adds configured (`ACCESS_CODES`) ones not stored yet.
+    pub fn load(path: PathBuf, configured: &[(String, String)]) -> anyhow::Result<Self> {
+        let mut codes: Vec<AccessCode> = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(anyhow::anyhow!("{}: {e}", path.display())),
+        };
+        if let Some(bad) = codes.iter().find(|c| !valid_code(&c.code)) {
+            anyhow::bail!("{}: invalid code '{}'", path.display(), bad.code);
+        }
+        for (label, code) in configured {
+            if !codes.iter().any(|c| &c.code == code) {
+                codes.push(AccessCode {
+                    code: code.clone(),
+                    label: label.clone(),
+                    created: now(),
+                    expires: None,
+                    revoked: false,
+                });
+            }
+        }
+        Ok(AccessCodes {
+            path,
+            codes: Mutex::new(codes),
+        })
+    }
+
+    pub async fn all(&self) -> Vec<AccessCode> {
+        self.codes.lock().await.clone()
+    }
+
+    /// Whether guests need a code at all.
+    pub async fn required(&self) -> bool {
+        !self.codes.lock().await.is_empty()
+    }
+
+    /// The code, if it lets guests in now.
+    async fn check(&self, code: &str) -> Option<AccessCode> {
+        let now = now();
+        self.codes
+            .lock()
+            .await
+            .iter()
+            .find(|c| c.code == code && c.usable(now))
+            .cloned()
+    }
+
+    pub async fn add(&self, code: AccessCode) -> anyhow::Result<()> {
+        let mut codes = self.codes.lock().await;
+        if codes.iter().any(|c| c.code == code.code) {
+            anyhow::bail!("code already exists");
+        }
+        codes.push(code);
+        self.save(&codes).await
+    }
+
+    /// Returns the revoked code's label; None if there is no such code.
+    async fn revoke(&self, code: &str) -> anyhow::Result<Option<String>> {
+        let mut codes = self.codes.lock().await;
+        let Some(c) = codes.iter_mut().find(|c| c.code == code) else {
+            return Ok(None);
+        };
+        c.revoked = true;
+        let label = c.label.clone();
+        self.save(&codes).await?;
+        Ok(Some(label))
+    }
+
+    async fn save(&self, codes: &[AccessCode]) -> anyhow::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, serde_json::to_vec_pretty(codes)?).await?;
+        tokio::fs::rename(&tmp, &self.path).await?;
+        Ok(())
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Codes end up in URLs and cookies, so keep them to URL-safe characters.
+pub fn valid_code(code: &str) -> bool {
+    (8..=64).contains(&code.len())
+        && code
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// Middleware for the guest routes.
+pub async fn require(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let access = &state.access;
+    if !access.required().await {
+        return next.run(req).await;
+    }
+    let from_url = req.uri().query().and_then(|q| {
+        q.split('&')
+            .filter_map(|p| p.split_once('='))
+            .find(|(k, _)| *k == "code")
+            .map(|(_, v)| v.to_string())
+    });
+    let addr = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|c| c.0);
+    let client = Client::from_parts(
+        req.headers(),
+        addr,
+        &state.trusted_proxies,
+        state.audit.salt(),
+    );
+    if let Some(code) = from_url {
+        if let Some(access) = access.check(&code).await {
+            state.audit.record(
+                Action::Login,
+                &client.guest(None),
+                None,
+                None,
+                serde_json::json!({ "label": access.label }),
+            );
+            let cookie = set_cookie(&code, access.expires);
+            // keep the code out of the address bar (and out of screenshots)
+            if req.method() == Method::GET && req.uri().path() == "/" {
+                return (
+                    StatusCode::SEE_OTHER,
+                    [
+                        (header::LOCATION, HeaderValue::from_static("/")),
+                        (header::SET_COOKIE, cookie),
+                    ],
+                )
+                    .into_response();
+            }
+            let mut res = next.run(req).await;
+            res.headers_mut().append(header::SET_COOKIE, cookie);
+            return res;
+        }
+    }
+    if let Some(code) = session::cookie(req.headers(), COOKIE) {
+        if access.check(&code).await.is_some() {
+            return next.run(req).await;
+        }
+    }
+    if req.uri().path() == "/api/message" {
+        metrics::rejected("no_access_code");
+        state.audit.record(
+            Action::Reject,
+            &client.guest(None),
+            None,
+            None,
+            serde_json::json!({ "reason": "no_access_code" }),
+        );
+    }
+    if req.uri().path() == "/" {
+        let html: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/locked.html"));
+        return (StatusCode::FORBIDDEN, Html(html)).into_response();
+    }
+    (StatusCode::FORBIDDEN, "Access code required").into_response()
+}
+
+fn set_cookie(code: &str, expires: Option<u64>) -> HeaderValue {
+    let max_age = expires.map_or(MAX_AGE_SECS, |e| e.saturating_sub(now()).min(MAX_AGE_SECS));
+    HeaderValue::from_str(&format!(
+        "{COOKIE}={code}; Path=/; Max-Age={max_age}; SameSite=Lax; HttpOnly"
+    ))
+    .expect("code is URL-safe")
+}
+
+/// The link a code's QR code points to.
+fn code_url(state: &AppState, headers: &HeaderMap, code: &str) -> String {
+    let base = match &state.public_url {
+        Some(url) => url.trim_end_matches('/').to_string(),
+        None => {
+            let host = headers
+                .get(header::HOST)
+                .and_then(|h| h.to_str().ok())
+                .unwrap_or("localhost");
+            let scheme = headers
+                .get("x-forwarded-proto")
+                .and_then(|h| h.to_str().ok())
+                .unwrap_or("http");
+            format!("{scheme}://{host}")
+        }
+    };
+    format!("{base}/?code={}", urlencoding::encode(code))
+}
+
+/// Refuses to print a QR code for a code that won't let guests in.
+async fn printable(state: &AppState, code: &str) -> Result<(), Response> {
+    let codes = state.access.all().await;
+    match codes.iter().find(|c| c.code == code) {
+        None => Err((StatusCode::NOT_FOUND, "Unknown code").into_response()),
+        Some(c) if !c.usable(now()) => {
+            Err((StatusCode::GONE, "Code is revoked or expired").into_response())
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+/// `GET /api/admin/access`
+pub async fn list(State(state): State<AppState>, headers: HeaderMap) -> impl IntoResponse {
+    let now = now();
+    let codes = state.access.all().await;
+    let items: Vec<_> = codes
+        .iter()
+        .map(|c| {
+            serde_json::json!({
+                "code": c.code,
+                "label": c.label,
+                "created": c.created,
+                "expires": c.expires,
+                "revoked": c.revoked,
+                "active": c.usable(now),
+                "url": code_url(&state, &headers, &c.code),
+            })
+        })
+        .collect();
+    (
+        [(header::CACHE_CONTROL, "no-store, max-age=0")],
+        axum::Json(serde_json::json!({ "required": !codes.is_empty(), "codes": items })),
+    )
+}
+
+#[derive(Deserialize)]
+pub struct CreateForm {
+    label: Option<String>,
+    /// Lifetime in hours; no expiry when absent.
+    hours: Option<u64>,
+    /// A chosen code instead of a random one.
+    code: Option<String>,
+}
+
+/// `POST /api/admin/access`: adds a code and returns it.
+pub async fn create(
+    State(state): State<AppState>,
+    client: Client,
+    Form(f): Form<CreateForm>,
+) -> impl IntoResponse {
+    let code = f
+        .code
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())
+        .unwrap_or_else(session::new_token);
+    if !valid_code(&code) {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Codes are 8-64 letters, digits, '-' or '_'",
+        )
+            .into_response();
+    }
+    let created = now();
+    let expires = match f.hours {
+        Some(h) => match h.checked_mul(3600).and_then(|s| created.checked_add(s)) {
+            Some(at) => Some(at),
+            None => return (StatusCode::BAD_REQUEST, "Lifetime too long").into_response(),
+        },
+        None => None,
+    };
+    let access = AccessCode {
+        code,
+        label: f.label.unwrap_or_default().trim().to_string(),
+        created,
+        expires,
+        revoked: false,
+    };
+    match state.access.add(access.clone()).await {
+        Ok(()) => {
+            info!(label = %access.label, "access code added");
+            state.audit.record(
+                Action::Access,
+                &client.admin(),
+                None,
+                None,
+                serde_json::json!({ "op": "add", "label": access.label, "expires": access.expires }),
+            );
+            axum::Json(access).into_response()
+        }
+        Err(e) => {
+            error!(?e, "storing access code failed");
+            (StatusCode::CONFLICT, e.to_string()).into_response()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CodeForm {
+    code: String,
+}
+
+/// `POST /api/admin/access/revoke`
+pub async fn revoke(
+    State(state): State<AppState>,
+    client: Client,
+    Form(f): Form<CodeForm>,
+) -> impl IntoResponse {
+    match state.access.revoke(&f.code).await {
+        Ok(Some(label)) => {
+            state.audit.record(
+                Action::Access,
+                &client.admin(),
+                None,
+                None,
+                serde_json::json!({ "op": "revoke", "label": label }),
+            );
+            (StatusCode::OK, "ok".to_string())
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "Unknown code".to_string()),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct QrQuery {
+    code: String,
+    /// `svg` (default) or `png`.
+    format: Option<String>,
+}
+
+/// `GET /api/admin/access/qr?code=…[&format=png]`: the code's link as a QR code.
+pub async fn qr(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(q): Query<QrQuery>,
+) -> impl IntoResponse {
+    if let Err(res) = printable(&state, &q.code).await {
+        return res;
+    }
+    let url = code_url(&state, &headers, &q.code);
+    match q.format.as_deref().unwrap_or("svg") {
+        "svg" => match qr_svg(&url, 240) {
+            Ok(svg) => ([(header::CONTENT_TYPE, "image/svg+xml")], svg).into_response(),
+            Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        },
+        "png" => match qr_png(&url, 8) {
+            Ok(png) => ([(header::CONTENT_TYPE, "image/png")], png).into_response(),
+            Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        },
+        _ => (StatusCode::BAD_REQUEST, "Format is svg or png").into_response(),
+    }
+}
+
+fn qr_svg(data: &str, size: u32) -> Result<String, qrcode::types::QrError> {
+    Ok(QrCode::new(data.as_bytes())?
+        .render::<svg::Color>()
+        .min_dimensions(size, size)
+        .quiet_zone(true)
+        .build())
+}
+
+/// Black on white, `scale` pixels per module, with the four-module quiet zone.
+fn qr_png(data: &str, scale: usize) -> anyhow::Result<Vec<u8>> {
+    const QUIET: usize = 4;
+    let code = QrCode::new(data.as_bytes())?;
+    let modules = code.width();
+    let colors = code.to_colors();
+    let side = (modules + 2 * QUIET) * scale;
+    let mut pixels = vec![255u8; side * side];
+    for (i, color) in colors.iter().enumerate() {
+        if *color == qrcode::Color::Light {
+            continue;
+        }
+        let (x, y) = ((i % modules + QUIET) * scale, (i / modules + QUIET) * scale);
+        for row in y..y + scale {
+            pixels[row * side + x..row * side + x + scale].fill(0);
+        }
+    }
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, side as u32, side as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&pixels)?;
+    Ok(out)
+}
+
+#[derive(Deserialize)]
+pub struct CardsQuery {
+    code: String,
+    /// Cards on the sheet.
+    count: Option<usize>,
+    /// Printed above the QR code.
+    title: Option<String>,
+}
+
+/// `GET /admin/access/cards?code=…[&count=…][&title=…]`: a printable sheet of table cards.
+pub async fn cards(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(q): Query<CardsQuery>,
+) -> impl IntoResponse {
+    if let Err(res) = printable(&state, &q.code).await {
+        return res;
+    }
+    let url = code_url(&state, &headers, &q.code);
+    let svg = match qr_svg(&url, 200) {
+        Ok(svg) => svg,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    // strip the XML declaration so the SVG can be inlined
+    let svg = svg.find("<svg").map_or(svg.as_str(), |i| &svg[i..]);
+    let title = html_escape(
+        q.title
+            .as_deref()
+            .unwrap_or("Stuur je wens naar het gordijn ✨"),
+    );
+    let card = format!(
+        r#"<div class="card"><div class="title">{title}</div>{svg}<div class="url">{}</div></div>"#,
+        html_escape(&url)
+    );
+    let html = format!(
+        r#"<!doctype html><html><head><meta charset="utf-8"><title>Table cards</title><style>
+body {{ font-family: Georgia, serif; margin: 0; }}
+.sheet {{ display: flex; flex-wrap: wrap; gap: 8mm; padding: 10mm; }}
+.card {{ width: 85mm; padding: 6mm; border: 1px dashed #bbb; text-align: center; break-inside: avoid; }}
+.title {{ font-size: 16pt; margin-bottom: 4mm; }}
+.url {{ font-family: monospace; font-size: 7pt; color: #666; word-break: break-all; margin-top: 2mm; }}
+</style></head><body><div class="sheet">{}</div></body></html>"#,
+        card.repeat(q.count.unwrap_or(8).clamp(1, 100))
+    );
+    Html(html).into_response()
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,354 @@
+//! Outbound onsite agent.
+//!
+//! `trouw-gordijn agent` runs on the onsite machine, dials out to the public server over an
+//! authenticated WebSocket and relays WLED requests to the LAN device. On the server side a
+//! target with the `agent` transport sends its WLED calls through an [`AgentLink`] instead of
+//! HTTP, so the server needs no SSH keys, Tailscale or inbound access to the venue.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::ws::{Message, WebSocket},
+    http::StatusCode,
+};
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{mpsc, oneshot, Mutex},
+    time,
+};
+use tokio_tungstenite::tungstenite::{self, client::IntoClientRequest};
+use tracing::{error, info, warn};
+
+use crate::{
+    transport::{Transport, WledClient},
+    wled::WledError,
+};
+
+/// How long the server waits for the agent to answer a relayed request.
+const RELAY_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often the agent reports WLED reachability.
+const STATUS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Server → agent.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMsg {
+    Request {
+        id: u64,
+        method: RelayMethod,
+        path: String,
+        body: Option<serde_json::Value>,
+    },
+}
+
+/// Agent → server.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AgentMsg {
+    Response {
+        id: u64,
+        status: u16,
+        body: String,
+    },
+    Error {
+        id: u64,
+        message: String,
+    },
+    Status {
+        wled_reachable: bool,
+        info: Option<serde_json::Value>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum RelayMethod {
+    Get,
+    Post,
+}
+
+/// Last status reported by an agent.
+#[derive(Clone, Debug, Default)]
+pub struct AgentStatus {
+    pub connected: bool,
+    pub wled_reachable: bool,
+    pub last_seen: Option<Instant>,
+    pub info: Option<serde_json::Value>,
+}
+
+impl AgentStatus {
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "connected": self.connected,
+            "wled_reachable": self.wled_reachable,
+            "last_seen_seconds": self.last_seen.map(|t| t.elapsed().as_secs()),
+        })
+    }
+}
+
+type RelayResult = Result<(u16, String), String>;
+
+/// Server-side handle to the (at most one) agent connected for a target.
+#[derive(Clone, Default)]
+pub struct AgentLink {
+    inner: Arc<AgentLinkInner>,
+}
+
+#[derive(Default)]
+struct AgentLinkInner {
+    conn: Mutex<Option<mpsc::UnboundedSender<Message>>>,
+    pending: Mutex<HashMap<u64, oneshot::Sender<RelayResult>>>,
+    next_id: AtomicU64,
+    status: Mutex<AgentStatus>,
+}
+
+impl AgentLink {
+    pub async fn status(&self) -> AgentStatus {
+        self.inner.status.lock().await.clone()
+    }
+
+    /// Sends a WLED request through the agent and waits for its answer.
+    pub async fn request(
+        &self,
+        method: RelayMethod,
+        path: &str,
+        body: Option<&serde_json::Value>,
+    ) -> Result<reqwest::Response, WledError> {
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        let msg = serde_json::to_string(&ServerMsg::Request {
+            id,
+            method,
+            path: path.to_string(),
+            body: body.cloned(),
+        })
+        .map_err(WledError::Encode)?;
+        let relay_err = |msg: &str| WledError::Relay(msg.to_string());
+        let (tx, rx) = oneshot::channel();
+        self.inner.pending.lock().await.insert(id, tx);
+        let sent = match self.inner.conn.lock().await.as_ref() {
+            Some(conn) => conn.send(Message::Text(msg)).is_ok(),
+            None => false,
+        };
+        if !sent {
+            self.inner.pending.lock().await.remove(&id);
+            return Err(relay_err("agent not connected"));
+        }
+        let (status, body) = match time::timeout(RELAY_TIMEOUT, rx).await {
+            Ok(Ok(Ok(res))) => res,
+            Ok(Ok(Err(message))) => return Err(WledError::Relay(message)),
+            Ok(Err(_)) => return Err(relay_err("agent disconnected")),
+            Err(_) => {
+                self.inner.pending.lock().await.remove(&id);
+                return Err(relay_err("agent request timed out"));
+            }
+        };
+        let status = StatusCode::from_u16(status).map_err(|_| relay_err("bad status code"))?;
+        let res = axum::http::Response::builder()
+            .status(status)
+            .body(body)
+            .map_err(|e| WledError::Relay(e.to_string()))?;
+        Ok(reqwest::Response::from(res))
+    }
+
+    /// Drives an accepted agent WebSocket until it closes. A newer connection replaces an older one.
+    pub async fn serve(&self, socket: WebSocket) {
+        let (mut sink, mut stream) = socket.split();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        *self.inner.conn.lock().await = Some(tx.clone());
+        {
+            let mut st = self.inner.status.lock().await;
+            st.connected = true;
+            st.last_seen = Some(Instant::now());
+        }
+        let writer = tokio::spawn(async move {
+            while let Some(msg) = rx.recv().await {
+                if sink.send(msg).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        while let Some(Ok(msg)) = stream.next().await {
+            let text = match msg {
+                Message::Text(t) => t,
+                Message::Close(_) => break,
+                _ => continue,
+            };
+            self.inner.status.lock().await.last_seen = Some(Instant::now());
+            match serde_json::from_str::<AgentMsg>(&text) {
+                Ok(AgentMsg::Response { id, status, body }) => {
+                    self.resolve(id, Ok((status, body))).await
+                }
+                Ok(AgentMsg::Error { id, message }) => self.resolve(id, Err(message)).await,
+                Ok(AgentMsg::Status {
+                    wled_reachable,
+                    info,
+                }) => {
+                    let mut st = self.inner.status.lock().await;
+                    st.wled_reachable = wled_reachable;
+                    if info.is_some() {
+                        st.info = info;
+                    }
+                }
+                Err(e) => warn!(?e, "bad agent message"),
+            }
+        }
+
+        writer.abort();
+        let mut conn = self.inner.conn.lock().await;
+        // only tear down if we are still the active connection
+        if conn.as_ref().is_some_and(|c| c.same_channel(&tx)) {
+            *conn = None;
+            drop(conn);
+            // dropping the senders fails every request still waiting on this agent
+            self.inner.pending.lock().await.clear();
+            let mut st = self.inner.status.lock().await;
+            st.connected = false;
+            st.wled_reachable = false;
+        }
+    }
+
+    async fn resolve(&self, id: u64, res: RelayResult) {
+        if let Some(tx) = self.inner.pending.lock().await.remove(&id) {
+            let _ = tx.send(res);
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AgentConfig {
+    /// e.g. `wss://gordijn.example.com/api/agent/ws`
+    server_url: String,
+    token: String,
+    target: Option<String>,
+    wled_host: String,
+    wled_port: u16,
+}
+
+impl AgentConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Self::from_vars(|k| std::env::var(k).ok().filter(|v| !v.is_empty()))
+    }
+
+    /// [`AgentConfig::from_env`] with the variables looked up by `var`.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> anyhow::Result<Self> {
+        let wled_port = match var("WLED_PORT") {
+            Some(raw) => raw
+                .parse()
+                .map_err(|e| anyhow::anyhow!("WLED_PORT={raw:?}: {e}"))?,
+            None => 80,
+        };
+        Ok(AgentConfig {
+            server_url: var("AGENT_SERVER_URL")
+                .ok_or_else(|| anyhow::anyhow!("AGENT_SERVER_URL is required in agent mode"))?,
+            token: var("AGENT_TOKEN")
+                .ok_or_else(|| anyhow::anyhow!("AGENT_TOKEN is required in agent mode"))?,
+            target: var("AGENT_TARGET"),
+            wled_host: var("WLED_HOST").unwrap_or_else(|| "127.0.0.1".into()),
+            wled_port,
+        })
+    }
+}
+
+/// Runs the onsite agent forever, reconnecting with backoff.
+pub async fn run(cfg: AgentConfig) -> anyhow::Result<()> {
+    let wled = WledClient::new(&Transport::Direct, &cfg.wled_host, cfg.wled_port, 0)?;
+    let mut backoff = Duration::from_secs(1);
+    loop {
+        let started = Instant::now();
+        match run_once(&cfg, &wled).await {
+            Ok(()) => info!("agent connection closed"),
+            Err(e) => error!(?e, "agent connection failed"),
+        }
+        // reset the backoff after a connection that stayed up for a while
+        if started.elapsed() > Duration::from_secs(60) {
+            backoff = Duration::from_secs(1);
+        }
+        time::sleep(backoff).await;
+        backoff = (backoff * 2).min(Duration::from_secs(30));
+    }
+}
+
+async fn run_once(cfg: &AgentConfig, wled: &WledClient) -> anyhow::Result<()> {
+    let mut url = cfg.server_url.clone();
+    if let Some(t) = &cfg.target {
+        url.push(if url.contains('?') { '&' } else { '?' });
+        url.push_str("target=");
+        url.push_str(&urlencoding::encode(t));
+    }
+    let mut req = url.as_str().into_client_request()?;
+    req.headers_mut().insert(
+        axum::http::header::AUTHORIZATION,
+        format!("Bearer {}", cfg.token).parse()?,
+    );
+    let (ws, _) = tokio_tungstenite::connect_async(req).await?;
+    info!("agent connected to {}", cfg.server_url);
+    let (mut sink, mut stream) = ws.split();
+    let (tx, mut rx) = mpsc::unbounded_channel::<AgentMsg>();
+
+    let mut status_tick = time::interval(STATUS_INTERVAL);
+    loop {
+        tokio::select! {
+            msg = stream.next() => {
+                let text = match msg {
+                    Some(Ok(tungstenite::Message::Text(t))) => t,
+                    Some(Ok(tungstenite::Message::Close(_))) | None => return Ok(()),
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => return Err(e.into()),
+                };
+                let ServerMsg::Request { id, method, path, body } = match serde_json::from_str(&text) {
+                    Ok(msg) => msg,
+                    Err(e) => {
+                        // likely a newer server; the requests this agent understands still work
+                        warn!(?e, "ignoring a message from the server");
+                        continue;
+                    }
+                };
+                // relay concurrently so one slow WLED call doesn't block the rest
+                let (wled, tx) = (wled.clone(), tx.clone());
+                tokio::spawn(async move {
+                    let res = match method {
+                        RelayMethod::Get => wled.get(&path).await,
+                        RelayMethod::Post => {
+                            wled.post_json(&path, &body.unwrap_or(serde_json::Value::Null)).await
+                        }
+                    };
+                    let reply = match res {
+                        Ok(r) => {
+                            let status = r.status().as_u16();
+                            match r.text().await {
+                                Ok(body) => AgentMsg::Response { id, status, body },
+                                Err(e) => AgentMsg::Error { id, message: e.to_string() },
+                            }
+                        }
+                        Err(e) => AgentMsg::Error { id, message: e.to_string() },
+                    };
+                    let _ = tx.send(reply);
+                });
+            }
+            Some(reply) = rx.recv() => {
+                sink.send(tungstenite::Message::Text(serde_json::to_string(&reply)?)).await?;
+            }
+            _ = status_tick.tick() => {
+                // off the loop, so a hung device doesn't hold up relays; a probe that takes
+                // longer than the interval counts as unreachable
+                let (wled, tx) = (wled.clone(), tx.clone());
+                tokio::spawn(async move {
+                    let probe = async {
+                        wled.get("/json/info").await.ok()?.json::<serde_json::Value>().await.ok()
+                    };
+                    let info = time::timeout(STATUS_INTERVAL, probe).await.ok().flatten();
+                    let _ = tx.send(AgentMsg::Status { wled_reachable: info.is_some(), info });
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,434 @@
+//! The audit log: who did what to which message, so that when a message disappears we can tell
+//! whether an admin removed it, its sender withdrew it or the rotation simply showed it.
+//!
+//! Entries are appended to `audit.jsonl` in the data directory and never rewritten. The file is
+//! written from a thread of its own, so recording is cheap enough for the rotation actor and
+//! never waits on the disk. The admin page reads the recent entries from memory; the JSONL
+//! export reads the whole file.
+//!
+//! Guests are recorded by a hash of their session token and client address, never the token or
+//! address itself.
+
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    io::Write,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::{mpsc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts, Query, State},
+    http::{header, request::Parts, HeaderMap},
+    response::IntoResponse,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::oneshot;
+use tracing::error;
+
+use crate::{auth::Admin, session, AppState};
+
+/// Entries kept in memory for the admin page; the file has all of them.
+const KEPT: usize = 5000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// A message was accepted into a queue.
+    Submit,
+    /// A message was turned away; `detail.reason` says why.
+    Reject,
+    /// A message was confirmed on the curtain.
+    Display,
+    /// A message was taken out of a queue or off the screen.
+    Remove,
+    /// A waiting message's text or colour was changed.
+    Edit,
+    /// The message on screen was cut short.
+    Skip,
+    /// The rotation was paused or resumed.
+    Hold,
+    /// A runtime setting changed.
+    Settings,
+    /// A guest came in through an access code.
+    Login,
+    /// An admin signed in, or someone sent a wrong admin password (`detail.ok`).
+    AdminLogin,
+    /// An access code was added or revoked.
+    Access,
+    /// A device preset was activated or previewed from the admin page.
+    Preset,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub seq: u64,
+    /// Unix seconds.
+    pub at: u64,
+    pub action: Action,
+    /// `admin:<name>`, `guest`, `guest:<session hash>` or `rotation`.
+    pub actor: String,
+    /// Hash of the client address.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub detail: Value,
+}
+
+/// Who an entry is recorded for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Actor {
+    pub name: String,
+    pub ip: Option<String>,
+}
+
+impl Actor {
+    /// The rotation itself, e.g. when it puts the next message up.
+    pub fn rotation() -> Self {
+        Actor {
+            name: "rotation".into(),
+            ip: None,
+        }
+    }
+
+    /// An admin, by the name they signed in with.
+    pub fn admin(name: Option<&str>) -> Self {
+        let name = name
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+            .map(|n| n.chars().take(32).collect::<String>());
+        Actor {
+            name: name.map_or_else(|| "admin".into(), |n| format!("admin:{n}")),
+            ip: None,
+        }
+    }
+}
+
+/// Where a request came from, as the log records it. Extracting it never fails.
+pub struct Client {
+    ip: Option<String>,
+    /// Who signed in, on the admin routes.
+    admin: Option<String>,
+    session: Option<String>,
+}
+
+impl Client {
+    /// `X-Forwarded-For` is only believed from one of `trusted_proxies`: anyone else could
+    /// pick the address the log records.
+    pub fn from_parts(
+        headers: &HeaderMap,
+        addr: Option<SocketAddr>,
+        trusted_proxies: &[IpAddr],
+        salt: &str,
+    ) -> Self {
+        let ip = addr.map(|a| a.ip()).map(|peer| {
+            if !trusted_proxies.contains(&peer) {
+                return peer;
+            }
+            // proxies append the address they got the request from, so the client is the last
+            // entry not added by one of ours; whatever comes before it the client made up
+            headers
+                .get_all("x-forwarded-for")
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(|v| v.split(','))
+                .map(|v| v.trim().parse::<IpAddr>().ok())
+                .collect::<Vec<_>>()
+                .into_iter()
+                .rev()
+                .map_while(|ip| ip)
+                .find(|ip| !trusted_proxies.contains(ip))
+                .unwrap_or(peer)
+        });
+        Client {
+            ip: ip.map(|ip| hash(&format!("{salt}{ip}"))),
+            admin: None,
+            session: session::token(headers),
+        }
+    }
+
+    /// The same client, signed in as admin `name`.
+    pub fn signed_in(self, name: &str) -> Self {
+        Client {
+            admin: Some(name.to_string()),
+            ..self
+        }
+    }
+
+    /// An admin action, by whoever [`crate::auth::require`] let in.
+    pub fn admin(&self) -> Actor {
+        Actor {
+            ip: self.ip.clone(),
+            ..Actor::admin(self.admin.as_deref())
+        }
+    }
+
+    /// A guest action, by the browser's session (or `token`, when it is handed out with this
+    /// very response).
+    pub fn guest(&self, token: Option<&str>) -> Actor {
+        Actor {
+            name: token
+                .or(self.session.as_deref())
+                .map_or_else(|| "guest".into(), |t| format!("guest:{}", hash(t))),
+            ip: self.ip.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for Client {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Infallible> {
+        let addr = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|c| c.0);
+        Ok(Client {
+            admin: parts.extensions.get::<Admin>().map(|a| a.0.clone()),
+            ..Client::from_parts(
+                &parts.headers,
+                addr,
+                &state.trusted_proxies,
+                state.audit.salt(),
+            )
+        })
+    }
+}
+
+/// Short FNV-1a hash, stable across runs and builds.
+fn hash(s: &str) -> String {
+    let h = s.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |h, b| {
+        (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{:010x}", h >> 24)
+}
+
+enum Job {
+    Line(String),
+    /// Answered once everything before it is on disk.
+    Sync(oneshot::Sender<()>),
+}
+
+struct Recent {
+    next_seq: u64,
+    entries: VecDeque<Entry>,
+}
+
+pub struct AuditLog {
+    path: PathBuf,
+    recent: Mutex<Recent>,
+    writer: mpsc::Sender<Job>,
+    // for the client address hashes; new every run, so addresses can't be looked up
+    salt: String,
+}
+
+impl AuditLog {
+    /// Reads the recent entries from `path` and starts the writer.
+    pub fn load(path: PathBuf) -> anyhow::Result<Self> {
+        let mut entries = VecDeque::new();
+        match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                for line in text.lines().filter(|l| !l.trim().is_empty()) {
+                    // a line cut short by a crash shouldn't keep the app from starting
+                    if let Ok(entry) = serde_json::from_str::<Entry>(line) {
+                        if entries.len() == KEPT {
+                            entries.pop_front();
+                        }
+                        entries.push_back(entry);
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(anyhow::anyhow!("{}: {e}", path.display())),
+        }
+        let next_seq = entries.back().map_or(1, |e: &Entry| e.seq + 1);
+        let (writer, rx) = mpsc::channel();
+        let file = path.clone();
+        std::thread::Builder::new()
+            .name("audit-log".into())
+            .spawn(move || write_lines(&file, rx))?;
+        let salt: [u8; 8] = rand::thread_rng().gen();
+        Ok(AuditLog {
+            path,
+            recent: Mutex::new(Recent { next_seq, entries }),
+            writer,
+            salt: salt.iter().map(|b| format!("{b:02x}")).collect(),
+        })
+    }
+
+    /// Salt for [`Client::from_parts`].
+    pub fn salt(&self) -> &str {
+        &self.salt
+    }
+
+    /// Appends an entry.
+    pub fn record(
+        &self,
+        action: Action,
+        actor: &Actor,
+        message_id: Option<u64>,
+        target: Option<&str>,
+        detail: Value,
+    ) {
+        let mut recent = self.recent.lock().expect("audit lock");
+        let entry = Entry {
+            seq: recent.next_seq,
+            at: now(),
+            action,
+            actor: actor.name.clone(),
+            ip: actor.ip.clone(),
+            message_id,
+            target: target.map(str::to_string),
+            detail,
+        };
+        recent.next_seq += 1;
+        // under the lock, so the file is in sequence order
+        let line = serde_json::to_string(&entry).expect("entry serializes");
+        let _ = self.writer.send(Job::Line(line));
+        if recent.entries.len() == KEPT {
+            recent.entries.pop_front();
+        }
+        recent.entries.push_back(entry);
+    }
+
+    /// Recent entries matching `filter`, newest first.
+    pub fn query(&self, filter: &Filter) -> Vec<Entry> {
+        let recent = self.recent.lock().expect("audit lock");
+        recent
+            .entries
+            .iter()
+            .rev()
+            .filter(|e| filter.matches(e))
+            .take(filter.limit.unwrap_or(200))
+            .cloned()
+            .collect()
+    }
+
+    /// Every entry on disk matching `filter` (the limit aside), oldest first, as JSONL.
+    pub async fn export(&self, filter: &Filter) -> anyhow::Result<String> {
+        let (tx, rx) = oneshot::channel();
+        if self.writer.send(Job::Sync(tx)).is_ok() {
+            let _ = rx.await;
+        }
+        let text = match tokio::fs::read_to_string(&self.path).await {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut out = String::new();
+        for line in text.lines() {
+            if serde_json::from_str::<Entry>(line).is_ok_and(|e| filter.matches(&e)) {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn write_lines(path: &std::path::Path, rx: mpsc::Receiver<Job>) {
+    let mut file = None;
+    for write in rx {
+        match write {
+            Job::Line(line) => {
+                if file.is_none() {
+                    if let Some(dir) = path.parent() {
+                        let _ = std::fs::create_dir_all(dir);
+                    }
+                    file = std::fs::OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(path)
+                        .inspect_err(|e| error!(?e, path = %path.display(), "opening audit log"))
+                        .ok();
+                }
+                if let Some(f) = file.as_mut() {
+                    if let Err(e) = writeln!(f, "{line}").and_then(|()| f.flush()) {
+                        error!(?e, "writing audit log failed");
+                        file = None;
+                    }
+                }
+            }
+            Job::Sync(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Query parameters of the audit endpoints; all optional.
+#[derive(Debug, Default, Deserialize)]
+pub struct Filter {
+    pub action: Option<Action>,
+    /// Matches from the start, so `admin` also finds `admin:Arthur`.
+    pub actor: Option<String>,
+    pub message_id: Option<u64>,
+    pub target: Option<String>,
+    /// Unix seconds.
+    pub since: Option<u64>,
+    /// Most entries returned by the JSON endpoint (default 200).
+    pub limit: Option<usize>,
+}
+
+impl Filter {
+    fn matches(&self, e: &Entry) -> bool {
+        self.action.is_none_or(|a| a == e.action)
+            && self
+                .actor
+                .as_deref()
+                .filter(|a| !a.is_empty())
+                .is_none_or(|a| e.actor.starts_with(a))
+            && self.message_id.is_none_or(|id| e.message_id == Some(id))
+            && self
+                .target
+                .as_deref()
+                .filter(|t| !t.is_empty())
+                .is_none_or(|t| e.target.as_deref() == Some(t))
+            && self.since.is_none_or(|s| e.at >= s)
+    }
+}
+
+/// `GET /api/admin/audit`: recent entries, newest first, filtered by the query.
+pub async fn list(State(state): State<AppState>, Query(f): Query<Filter>) -> impl IntoResponse {
+    (
+        [(header::CACHE_CONTROL, "no-store, max-age=0")],
+        axum::Json(serde_json::json!({ "entries": state.audit.query(&f) })),
+    )
+}
+
+/// `GET /api/admin/audit/export`: the whole log (filtered by the query) as JSONL.
+pub async fn export(State(state): State<AppState>, Query(f): Query<Filter>) -> impl IntoResponse {
+    match state.audit.export(&f).await {
+        Ok(jsonl) => (
+            [
+                (header::CONTENT_TYPE, "application/x-ndjson"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"audit.jsonl\"",
+                ),
+            ],
+            jsonl,
+        )
+            .into_response(),
+        Err(e) => {
+            error!(?e, "reading audit log failed");
+            (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    }
+}
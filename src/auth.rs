@@ -0,0 +1,174 @@
+//! Admin sign-in: the admin page and every `/api/admin/*` route answer only requests carrying a
+//! configured admin's password, as HTTP Basic (browsers ask once and keep sending it) or as a
+//! Bearer token (scripts).
+//!
+//! Admins come from `ADMIN_PASSWORD` (named `admin`) and `[admin.users]`. With none configured
+//! a password for `admin` is made up at every start and printed to stderr, not the log.
+//!
+//! Browsers resend Basic credentials on their own, so changes coming from another site's page
+//! (a cross-site form post) are refused.
+//!
+//! The audit log gets an `admin_login` entry the first time an admin signs in from an address,
+//! and one for every wrong password.
+
+use std::{collections::HashSet, net::SocketAddr, sync::Mutex as StdMutex};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use tracing::warn;
+
+use crate::{
+    audit::{Action, Actor, Client},
+    session, AppState,
+};
+
+/// The signed-in admin, put in the request extensions for [`crate::audit::Client`].
+#[derive(Clone)]
+pub struct Admin(pub String);
+
+const CHALLENGE: &str = "Basic realm=\"trouw-gordijn admin\", charset=\"UTF-8\"";
+
+pub struct Admins {
+    /// (name, password)
+    users: Vec<(String, String)>,
+    /// (actor, address hash) already recorded as signed in since the start.
+    signed_in: StdMutex<HashSet<(String, Option<String>)>>,
+}
+
+impl Admins {
+    pub fn new(configured: &[(String, String)]) -> Self {
+        if !configured.is_empty() {
+            return Admins {
+                users: configured.to_vec(),
+                signed_in: StdMutex::default(),
+            };
+        }
+        let password = session::new_token();
+        warn!("no admin password configured (ADMIN_PASSWORD); one was printed to stderr");
+        // kept out of the log, which may be shipped elsewhere
+        eprintln!("admin password until the next start: admin / {password}");
+        Admins {
+            users: vec![("admin".into(), password)],
+            signed_in: StdMutex::default(),
+        }
+    }
+
+    /// The admin `password` belongs to; with a `user` name, only if it is theirs.
+    fn check(&self, user: Option<&str>, password: &str) -> Option<&str> {
+        self.users
+            .iter()
+            .find(|(name, pw)| {
+                user.is_none_or(|u| u.eq_ignore_ascii_case(name)) && same(pw, password)
+            })
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Whether this is the first request `actor` got in with since the start.
+    fn first_sign_in(&self, actor: &Actor) -> bool {
+        self.signed_in
+            .lock()
+            .expect("sign-in lock")
+            .insert((actor.name.clone(), actor.ip.clone()))
+    }
+}
+
+/// Compares without stopping at the first difference, so timing doesn't give the password away.
+fn same(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |d, (x, y)| d | (x ^ y)) == 0
+}
+
+/// User name (Basic only) and password from the `Authorization` header.
+fn credentials(headers: &HeaderMap) -> Option<(Option<String>, String)> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    if let Some(token) = value.strip_prefix("Bearer ") {
+        return Some((None, token.trim().to_string()));
+    }
+    let decoded = STANDARD.decode(value.strip_prefix("Basic ")?.trim()).ok()?;
+    let (user, password) = String::from_utf8(decoded)
+        .ok()?
+        .split_once(':')
+        .map(|(u, p)| (u.to_string(), p.to_string()))?;
+    Some((Some(user), password))
+}
+
+/// Whether a change may have come from the admin page rather than another site. Browsers say
+/// where a request comes from in `Sec-Fetch-Site` or `Origin`; scripts send neither.
+fn same_site(state: &AppState, req: &Request) -> bool {
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return true;
+    }
+    let headers = req.headers();
+    if let Some(site) = headers.get("sec-fetch-site").and_then(|v| v.to_str().ok()) {
+        return matches!(site, "same-origin" | "none");
+    }
+    let Some(origin) = headers.get(header::ORIGIN).and_then(|v| v.to_str().ok()) else {
+        return true;
+    };
+    let host = origin.split_once("://").map(|(_, host)| host);
+    host.is_some() && host == headers.get(header::HOST).and_then(|v| v.to_str().ok())
+        || state
+            .public_url
+            .as_deref()
+            .is_some_and(|url| url.trim_end_matches('/') == origin)
+}
+
+/// Middleware for the admin routes.
+pub async fn require(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    let addr = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|c| c.0);
+    let client = Client::from_parts(
+        req.headers(),
+        addr,
+        &state.trusted_proxies,
+        state.audit.salt(),
+    );
+    let given = credentials(req.headers());
+    let admin = given
+        .as_ref()
+        .and_then(|(user, password)| state.admins.check(user.as_deref(), password))
+        .map(str::to_string);
+    match admin {
+        Some(_) if !same_site(&state, &req) => {
+            (StatusCode::FORBIDDEN, "Cross-site request refused").into_response()
+        }
+        Some(name) => {
+            let actor = client.signed_in(&name).admin();
+            if state.admins.first_sign_in(&actor) {
+                state.audit.record(
+                    Action::AdminLogin,
+                    &actor,
+                    None,
+                    None,
+                    serde_json::json!({ "ok": true }),
+                );
+            }
+            req.extensions_mut().insert(Admin(name));
+            next.run(req).await
+        }
+        None => {
+            // a browser's first request comes without credentials; only a wrong password counts
+            if let Some((user, _)) = given {
+                state.audit.record(
+                    Action::AdminLogin,
+                    &client.guest(None),
+                    None,
+                    None,
+                    serde_json::json!({ "ok": false, "user": user }),
+                );
+            }
+            (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, CHALLENGE)],
+                "Admin sign-in required",
+            )
+                .into_response()
+        }
+    }
+}
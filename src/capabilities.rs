@@ -0,0 +1,113 @@
+//! Per-device cache of what a WLED build can do.
+//!
+//! Effect and palette indices differ between builds, so they are looked up once per device and
+//! kept until the device reconnects, reports a different build, or an admin asks for a rediscover.
+
+use std::time::Instant;
+
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tracing::info;
+
+use crate::{
+    transport::WledClient,
+    wled::{self, Info, Matrix, Version, WledError},
+};
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Capabilities {
+    #[serde(serialize_with = "version_str")]
+    pub version: Option<Version>,
+    /// Build id from `info.vid`; a change means the device was reflashed.
+    pub vid: u64,
+    pub led_count: u32,
+    pub matrix: Option<Matrix>,
+    pub usermods: Vec<String>,
+    pub text_effect: Option<u16>,
+    pub color1_palette: Option<u16>,
+    /// Longest message text the device shows, in bytes.
+    pub segment_name_len: usize,
+    #[serde(skip)]
+    pub discovered: Instant,
+}
+
+fn version_str<S: serde::Serializer>(v: &Option<Version>, s: S) -> Result<S::Ok, S::Error> {
+    match v {
+        Some(v) => s.collect_str(v),
+        None => s.serialize_none(),
+    }
+}
+
+impl Capabilities {
+    fn matches(&self, info: &Info) -> bool {
+        self.vid == info.vid && self.version == info.version()
+    }
+}
+
+#[derive(Default)]
+pub struct CapabilityCache {
+    inner: Mutex<Option<Capabilities>>,
+}
+
+impl CapabilityCache {
+    /// Cached capabilities, discovering them first if needed.
+    pub async fn get(&self, wled: &WledClient) -> wled::Result<Capabilities> {
+        if let Some(caps) = self.inner.lock().await.clone() {
+            return Ok(caps);
+        }
+        self.discover(wled).await
+    }
+
+    /// Cached capabilities without touching the device.
+    pub async fn peek(&self) -> Option<Capabilities> {
+        self.inner.lock().await.clone()
+    }
+
+    /// Re-reads everything from the device and replaces the cache.
+    pub async fn discover(&self, wled: &WledClient) -> wled::Result<Capabilities> {
+        let info = wled.info().await?;
+        self.discover_with(wled, info).await
+    }
+
+    /// Refreshes the cache if `info` (e.g. from a heartbeat) reports a different build.
+    pub async fn observe(&self, wled: &WledClient, info: Info) -> wled::Result<()> {
+        let stale = match self.inner.lock().await.as_ref() {
+            Some(caps) => !caps.matches(&info),
+            None => false,
+        };
+        if stale {
+            info!(ver = %info.ver, vid = info.vid, "WLED build changed; rediscovering");
+            self.discover_with(wled, info).await?;
+        }
+        Ok(())
+    }
+
+    async fn discover_with(&self, wled: &WledClient, info: Info) -> wled::Result<Capabilities> {
+        // effect/palette lists are optional extras; a build without them still works, but a
+        // device that didn't answer is asked again rather than remembered as lacking them
+        let text_effect = optional(wled.effects().await)?.and_then(|e| e.scrolling_text());
+        let color1_palette = optional(wled.palettes().await)?.and_then(|p| p.color1());
+        let caps = Capabilities {
+            version: info.version(),
+            vid: info.vid,
+            led_count: info.leds.count,
+            matrix: info.leds.matrix,
+            usermods: info.u.keys().cloned().collect(),
+            text_effect,
+            color1_palette,
+            segment_name_len: info.segment_name_len(),
+            discovered: Instant::now(),
+        };
+        *self.inner.lock().await = Some(caps.clone());
+        Ok(caps)
+    }
+}
+
+/// `None` when the device has no such list: it answers 404 or something we can't read.
+fn optional<T>(res: wled::Result<T>) -> wled::Result<Option<T>> {
+    match res {
+        Ok(v) => Ok(Some(v)),
+        Err(WledError::Status { status: 404, .. } | WledError::Decode { .. }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
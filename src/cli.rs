@@ -0,0 +1,371 @@
+//! The command line. Without a subcommand the binary serves the web app, as it always did.
+//!
+//! `send`, `queue` and `export` talk to a running instance's admin API (`--url`), so the curtain
+//! can be run from a terminal at the venue; `test-wled` probes a device straight from the
+//! configuration, without a server.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use serde_json::Value;
+
+use crate::{build_state, config, device_report, ensure_tunnel};
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Guest messages on a WLED curtain")]
+pub struct Cli {
+    /// Config file (TOML or YAML); by default gordijn.toml or gordijn.yaml in the working
+    /// directory.
+    #[arg(long, short, global = true, env = "CONFIG_FILE")]
+    pub config: Option<PathBuf>,
+    /// Base URL of the running instance, for `send`, `queue` and `export`.
+    #[arg(
+        long,
+        global = true,
+        env = "GORDIJN_URL",
+        default_value = "http://127.0.0.1:8080"
+    )]
+    pub url: String,
+    /// Admin to sign in as, for `send`, `queue` and `export`.
+    #[arg(
+        long,
+        global = true,
+        env = "GORDIJN_ADMIN_USER",
+        default_value = "admin"
+    )]
+    pub admin_user: String,
+    /// That admin's password.
+    #[arg(long, global = true, env = "ADMIN_PASSWORD", hide_env_values = true)]
+    pub admin_password: Option<String>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the web app (the default).
+    Serve,
+    /// Relay WLED calls for a server that uses the agent transport (set up through AGENT_*).
+    Agent,
+    /// Pretend to be a WLED device, for rehearsals (set up through MOCK_WLED_*).
+    MockWled,
+    /// Validate the configuration and print what it amounts to.
+    CheckConfig {
+        /// Config file to check instead of the one in use.
+        path: Option<PathBuf>,
+    },
+    /// Probe a target's device and print its info, effects and palettes.
+    TestWled {
+        /// Target name; the main target by default.
+        #[arg(long)]
+        target: Option<String>,
+        /// Print the full report as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Send a message, as from the admin page.
+    Send {
+        text: String,
+        /// Colour as #rrggbb.
+        #[arg(long)]
+        color: Option<String>,
+        /// Signature shown with the message.
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long)]
+        target: Option<String>,
+        /// Share of the turns under the weighted policy.
+        #[arg(long)]
+        weight: Option<u32>,
+    },
+    /// Show or change the queue.
+    Queue {
+        #[command(subcommand)]
+        action: QueueAction,
+    },
+    /// Save the queues, settings and access codes as JSON.
+    Export {
+        /// File to write; stdout by default.
+        #[arg(long, short)]
+        out: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum QueueAction {
+    /// What is on screen and waiting.
+    List {
+        #[arg(long)]
+        target: Option<String>,
+    },
+    /// Drop one message, waiting or on screen.
+    Remove { id: u64 },
+    /// Drop every waiting message; the one on screen stays.
+    Clear {
+        #[arg(long)]
+        target: Option<String>,
+    },
+}
+
+/// Runs the subcommands other than `serve`, `agent` and `mock-wled`.
+pub async fn run(cli: Cli) -> anyhow::Result<()> {
+    let login = cli
+        .admin_password
+        .as_deref()
+        .map(|password| (cli.admin_user.as_str(), password));
+    let remote = Remote::new(&cli.url, login);
+    match cli.command.expect("serve is handled by main") {
+        Command::CheckConfig { path } => {
+            config::check_command(path.or(cli.config).as_deref())?;
+        }
+        Command::TestWled { target, json } => {
+            test_wled(cli.config.as_deref(), target.as_deref(), json).await?;
+        }
+        Command::Send {
+            text,
+            color,
+            name,
+            target,
+            weight,
+        } => {
+            let mut form = vec![("text", text)];
+            form.extend(color.map(|v| ("color", v)));
+            form.extend(name.map(|v| ("name", v)));
+            form.extend(target.map(|v| ("target", v)));
+            form.extend(weight.map(|v| ("weight", v.to_string())));
+            let outcome = remote.post("/api/admin/message", &form).await?;
+            println!("{outcome}");
+        }
+        Command::Queue { action } => match action {
+            QueueAction::List { target } => {
+                print!("{}", format_queue(&remote.queue(target.as_deref()).await?));
+            }
+            QueueAction::Remove { id } => {
+                remote
+                    .post("/api/admin/remove", &[("id", id.to_string())])
+                    .await?;
+                println!("removed #{id}");
+            }
+            QueueAction::Clear { target } => {
+                let form: Vec<_> = target.map(|t| ("target", t)).into_iter().collect();
+                let res: Value =
+                    serde_json::from_str(&remote.post("/api/admin/clear", &form).await?)?;
+                println!("removed {} waiting message(s)", res["removed"]);
+            }
+        },
+        Command::Export { out } => {
+            let export = remote.get("/api/admin/export").await?;
+            let text = serde_json::to_string_pretty(&export)?;
+            match out {
+                Some(path) => {
+                    std::fs::write(&path, text + "\n")
+                        .with_context(|| format!("writing {}", path.display()))?;
+                    eprintln!("exported to {}", path.display());
+                }
+                None => println!("{text}"),
+            }
+        }
+        Command::Serve | Command::Agent | Command::MockWled => unreachable!("handled by main"),
+    }
+    Ok(())
+}
+
+/// A running instance's admin API.
+pub struct Remote {
+    http: reqwest::Client,
+    base: String,
+    // admin name and password
+    login: Option<(String, String)>,
+}
+
+impl Remote {
+    pub fn new(base: &str, login: Option<(&str, &str)>) -> Self {
+        Remote {
+            http: reqwest::Client::new(),
+            base: base.trim_end_matches('/').to_string(),
+            login: login.map(|(user, password)| (user.to_string(), password.to_string())),
+        }
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let req = self.http.request(method, format!("{}{path}", self.base));
+        match &self.login {
+            Some((user, password)) => req.basic_auth(user, Some(password)),
+            None => req,
+        }
+    }
+
+    pub async fn get(&self, path: &str) -> anyhow::Result<Value> {
+        let res = self
+            .request(reqwest::Method::GET, path)
+            .send()
+            .await
+            .with_context(|| format!("is the app running at {}?", self.base))?;
+        let status = res.status();
+        let body = res.text().await?;
+        if !status.is_success() {
+            anyhow::bail!("{status}: {body}");
+        }
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    /// POSTs a form and returns the response body.
+    pub async fn post(&self, path: &str, form: &[(&str, String)]) -> anyhow::Result<String> {
+        let res = self
+            .request(reqwest::Method::POST, path)
+            .form(form)
+            .send()
+            .await
+            .with_context(|| format!("is the app running at {}?", self.base))?;
+        let status = res.status();
+        let body = res.text().await?;
+        if !status.is_success() {
+            anyhow::bail!("{status}: {body}");
+        }
+        Ok(body)
+    }
+
+    pub async fn queue(&self, target: Option<&str>) -> anyhow::Result<Value> {
+        match target {
+            Some(t) => {
+                self.get(&format!("/api/queue?target={}", urlencoding::encode(t)))
+                    .await
+            }
+            None => self.get("/api/queue").await,
+        }
+    }
+}
+
+/// `GET /api/queue` as lines for the terminal.
+pub fn format_queue(q: &Value) -> String {
+    let line = |m: &Value| {
+        let name = m["name"]
+            .as_str()
+            .map(|n| format!(" — {n}"))
+            .unwrap_or_default();
+        format!(
+            "#{} {}{name}",
+            m["id"],
+            m["text"].as_str().unwrap_or_default()
+        )
+    };
+    let mut out = format!(
+        "{}{}\n",
+        q["target"].as_str().unwrap_or("curtain"),
+        if q["held"] == true { " (paused)" } else { "" }
+    );
+    match q.get("current").filter(|c| !c.is_null()) {
+        Some(c) => out.push_str(&format!("  now  {} ({}s)\n", line(c), q["elapsed_seconds"])),
+        None => out.push_str("  now  -\n"),
+    }
+    let items = q["items"].as_array().cloned().unwrap_or_default();
+    for (i, m) in items.iter().enumerate() {
+        let eta = m["eta_seconds"]
+            .as_u64()
+            .map_or("    ".to_string(), |s| format!("{s:>3}s"));
+        out.push_str(&format!("  {:>2}.  {eta} {}\n", i + 1, line(m)));
+    }
+    if items.is_empty() {
+        out.push_str("  nothing waiting\n");
+    }
+    out
+}
+
+/// `test-wled`: reaches the target like the server would (starting the SSH tunnel if needed)
+/// and prints what the device reports.
+async fn test_wled(config: Option<&Path>, target: Option<&str>, json: bool) -> anyhow::Result<()> {
+    let cfg = config::load(config)?;
+    let state = build_state(&cfg)?;
+    let Some(idx) = state.target_index(target) else {
+        anyhow::bail!("unknown target '{}'", target.unwrap_or_default());
+    };
+    let target = &state.targets[idx];
+    if target.wled.agent_link().is_some() {
+        anyhow::bail!(
+            "target '{}' is reached through the onsite agent; run test-wled on the onsite \
+             machine with WLED_TRANSPORT=direct",
+            target.cfg.name
+        );
+    }
+    if target.cfg.transport.uses_tunnel() {
+        ensure_tunnel(target).await?;
+    }
+    let report = device_report(&target.wled)
+        .await
+        .with_context(|| format!("no answer from {}", target.wled.base()))?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    let info = &report["info"];
+    let names = |key: &str| -> Vec<String> {
+        report[key]
+            .as_array()
+            .map(|a| {
+                a.iter()
+                    .map(|v| v.as_str().unwrap_or_default().to_string())
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let effects = names("effects");
+    let palettes = names("palettes");
+    let named = |list: &[String], i: &Value| {
+        i.as_u64().map_or("none found".to_string(), |i| {
+            format!("{i} ({})", list.get(i as usize).map_or("?", String::as_str))
+        })
+    };
+    println!(
+        "target:     {} via {} ({})",
+        target.cfg.name,
+        target.cfg.transport,
+        target.wled.base()
+    );
+    println!(
+        "device:     WLED {} {}",
+        info["ver"].as_str().unwrap_or("?"),
+        info["name"]
+            .as_str()
+            .map(|n| format!("{n:?}"))
+            .unwrap_or_default()
+    );
+    let matrix = &info["leds"]["matrix"];
+    println!(
+        "leds:       {}{}",
+        info["leds"]["count"],
+        if matrix.is_object() {
+            format!(" ({}×{} matrix)", matrix["w"], matrix["h"])
+        } else {
+            String::new()
+        }
+    );
+    println!(
+        "text:       effect {}",
+        named(&effects, &report["text_effect"]["index"])
+    );
+    println!(
+        "colour 1:   palette {}",
+        named(&palettes, &report["color1_palette"])
+    );
+    if let Some(presets) = report["presets"].as_array() {
+        println!("presets:    {}", presets.len());
+        for p in presets {
+            println!(
+                "  {:>3}  {}",
+                p["id"],
+                p["name"].as_str().unwrap_or("(unnamed)")
+            );
+        }
+    }
+    println!("effects:    {}", effects.len());
+    for (i, name) in effects.iter().enumerate() {
+        println!("  {i:>3}  {name}");
+    }
+    println!("palettes:   {}", palettes.len());
+    for (i, name) in palettes.iter().enumerate() {
+        println!("  {i:>3}  {name}");
+    }
+    Ok(())
+}
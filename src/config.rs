@@ -0,0 +1,804 @@
+//! Settings from an optional TOML or YAML file, overridden by environment variables.
+//!
+//! The file is looked up at `CONFIG_FILE`, or as `gordijn.toml` / `gordijn.yaml` in the working
+//! directory. Every value is checked, and a bad one stops startup with the file key or variable
+//! it came from; all problems are reported at once. `gordijn.example.toml` lists every setting.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    access,
+    presets::PresetSettings,
+    schedule::{self, Policy},
+    settings::Settings,
+    shutdown::Goodnight,
+    theme::Theme,
+    tls::{TlsConfig, TlsMode},
+    transport::Transport,
+    AppConfig, TargetConfig, TargetMode,
+};
+
+/// Looked for in the working directory when `CONFIG_FILE` isn't set.
+const DEFAULT_FILES: [&str; 3] = ["gordijn.toml", "gordijn.yaml", "gordijn.yml"];
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    server: ServerFile,
+    queue: QueueFile,
+    display: DisplayFile,
+    messages: MessagesFile,
+    access: AccessFile,
+    admin: AdminFile,
+    agent: AgentFile,
+    tls: TlsFile,
+    acme: AcmeFile,
+    shutdown: ShutdownFile,
+    theme: ThemeFile,
+    targets: Vec<TargetFile>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ServerFile {
+    bind_host: Option<IpAddr>,
+    bind_port: Option<u16>,
+    public_url: Option<String>,
+    data_dir: Option<PathBuf>,
+    /// Reverse proxies whose `X-Forwarded-For` is believed.
+    trusted_proxies: Vec<IpAddr>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct QueueFile {
+    policy: Option<Policy>,
+    weights: HashMap<String, u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct DisplayFile {
+    seconds: Option<u64>,
+    brightness: Option<u8>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct MessagesFile {
+    max_text_len: Option<usize>,
+    max_name_len: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct AccessFile {
+    /// label → code
+    codes: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct AdminFile {
+    /// for the admin named `admin`
+    password: Option<String>,
+    /// name → password, one per person
+    users: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct AgentFile {
+    token: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TlsFile {
+    /// off, static or acme; follows from the other settings when unset
+    mode: Option<String>,
+    cert: Option<PathBuf>,
+    key: Option<PathBuf>,
+    https_port: Option<u16>,
+    /// 0 turns the redirect off
+    http_port: Option<u16>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct AcmeFile {
+    /// one domain; `domains` for several
+    domain: Option<String>,
+    domains: Vec<String>,
+    contact_email: Option<String>,
+    cache_dir: Option<PathBuf>,
+    staging: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ShutdownFile {
+    goodnight: Option<Goodnight>,
+    timeout_seconds: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ThemeFile {
+    couple: Option<String>,
+    date: Option<String>,
+    accent: Option<String>,
+    background: Option<String>,
+    /// a file in the data directory's `theme/`
+    photo: Option<String>,
+    welcome: Option<String>,
+    footer: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TargetFile {
+    name: String,
+    mode: Option<TargetMode>,
+    transport: Option<String>,
+    proxy: Option<String>,
+    ssh_host: Option<String>,
+    ssh_user: Option<String>,
+    wled_host: Option<String>,
+    wled_port: Option<u16>,
+    local_tunnel_port: Option<u16>,
+    text_param_key: Option<String>,
+    text_preset_id: Option<i32>,
+}
+
+/// The config file in use, if any: `CONFIG_FILE`, else the first default file that exists.
+pub fn find_file() -> Option<PathBuf> {
+    match env("CONFIG_FILE") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => DEFAULT_FILES.iter().map(PathBuf::from).find(|p| p.exists()),
+    }
+}
+
+pub fn read_file(path: &Path) -> anyhow::Result<FileConfig> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("reading {}: {e}", path.display()))?;
+    let yaml = matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("yaml" | "yml")
+    );
+    if yaml {
+        serde_yaml::from_str(&text).map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))
+    } else {
+        toml::from_str(&text).map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))
+    }
+}
+
+/// Reads the config file at `path` (or [`find_file`]) and the environment.
+pub fn load(path: Option<&Path>) -> anyhow::Result<AppConfig> {
+    let found = match path {
+        Some(p) => Some(p.to_path_buf()),
+        None => find_file(),
+    };
+    let file = match &found {
+        Some(p) => read_file(p)?,
+        None => FileConfig::default(),
+    };
+    resolve(file)
+}
+
+fn env(key: &str) -> Option<String> {
+    std::env::var(key).ok().filter(|v| !v.is_empty())
+}
+
+/// Collects every problem instead of stopping at the first.
+#[derive(Default)]
+struct Check {
+    errors: Vec<String>,
+}
+
+impl Check {
+    fn error(&mut self, msg: impl Into<String>) {
+        self.errors.push(msg.into());
+    }
+
+    /// The environment variable `key` if set, else the file's value.
+    fn get<T>(&mut self, key: &str, file: Option<T>) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match env(key) {
+            Some(raw) => match raw.parse() {
+                Ok(v) => Some(v),
+                Err(e) => {
+                    self.error(format!("{key}={raw:?}: {e}"));
+                    None
+                }
+            },
+            None => file,
+        }
+    }
+}
+
+/// Applies the environment on top of `file` and validates the result.
+pub fn resolve(file: FileConfig) -> anyhow::Result<AppConfig> {
+    let mut check = Check::default();
+
+    let bind_host = check
+        .get::<IpAddr>("BIND_HOST", file.server.bind_host)
+        .unwrap_or(IpAddr::from([0, 0, 0, 0]));
+    let bind_port = check
+        .get("BIND_PORT", file.server.bind_port)
+        .unwrap_or(8080);
+    let public_url = check.get("PUBLIC_URL", file.server.public_url);
+    if let Some(url) = &public_url {
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            check.error(format!(
+                "server.public_url / PUBLIC_URL: '{url}' must start with http:// or https://"
+            ));
+        }
+    }
+    let data_dir = check
+        .get("DATA_DIR", file.server.data_dir)
+        .unwrap_or_else(|| PathBuf::from("./data"));
+    let mut trusted_proxies = file.server.trusted_proxies;
+    if let Some(raw) = env("TRUSTED_PROXIES") {
+        // TRUSTED_PROXIES=127.0.0.1,::1
+        trusted_proxies = Vec::new();
+        for ip in raw.split(',').map(str::trim).filter(|ip| !ip.is_empty()) {
+            match ip.parse() {
+                Ok(ip) => trusted_proxies.push(ip),
+                Err(e) => check.error(format!("TRUSTED_PROXIES: '{ip}': {e}")),
+            }
+        }
+    }
+
+    let queue_policy = check
+        .get("QUEUE_POLICY", file.queue.policy)
+        .unwrap_or_default();
+    let mut queue_weights: HashMap<String, u32> = HashMap::new();
+    for (name, weight) in file.queue.weights {
+        if weight == 0 {
+            check.error(format!("queue.weights.{name}: must be a positive number"));
+        }
+        queue_weights.insert(name.to_lowercase(), weight);
+    }
+    if let Some(raw) = env("QUEUE_WEIGHTS") {
+        match schedule::parse_weights(&raw) {
+            Ok(w) => queue_weights = w,
+            Err(e) => check.error(format!("QUEUE_WEIGHTS: {e}")),
+        }
+    }
+
+    // the starting point for the runtime settings
+    let defaults = Settings::default();
+    let mut settings = Settings {
+        display_seconds: check
+            .get("DISPLAY_SECONDS", file.display.seconds)
+            .unwrap_or(defaults.display_seconds),
+        brightness: check
+            .get("BRIGHTNESS", file.display.brightness)
+            .unwrap_or(defaults.brightness),
+        max_text_len: check
+            .get("MAX_TEXT_LEN", file.messages.max_text_len)
+            .unwrap_or(defaults.max_text_len),
+        max_name_len: check
+            .get("MAX_NAME_LEN", file.messages.max_name_len)
+            .unwrap_or(defaults.max_name_len),
+        presets: Default::default(),
+        theme: resolve_theme(&mut check, file.theme),
+    };
+    if let Err(e) = settings.validate() {
+        check.error(e);
+    }
+
+    let mut access_codes: Vec<(String, String)> = file.access.codes.into_iter().collect();
+    if let Some(raw) = env("ACCESS_CODES") {
+        // ACCESS_CODES=tafels=<code>,familie=<code>
+        access_codes = raw
+            .split(',')
+            .map(str::trim)
+            .filter(|e| !e.is_empty())
+            .map(|e| {
+                let (label, code) = e.split_once('=').unwrap_or(("", e));
+                (label.to_string(), code.to_string())
+            })
+            .collect();
+    }
+    for (label, code) in &access_codes {
+        if !access::valid_code(code) {
+            check.error(format!(
+                "access code '{label}': '{code}' must be 8-64 letters, digits, '-' or '_'"
+            ));
+        }
+    }
+
+    let admins = resolve_admins(&mut check, file.admin);
+    let agent_token = check.get("AGENT_TOKEN", file.agent.token);
+
+    let targets = resolve_targets(&mut check, file.targets);
+    settings.presets = targets
+        .iter()
+        .map(|t| {
+            let presets = PresetSettings {
+                text_preset_id: t.text_preset_id,
+                scenes: Vec::new(),
+            };
+            (t.name.clone(), presets)
+        })
+        .collect();
+    if agent_token.is_none() && targets.iter().any(|t| t.transport == Transport::Agent) {
+        check.error("agent.token / AGENT_TOKEN is required when a target uses the agent transport");
+    }
+
+    let goodnight = check.get("GOODNIGHT", file.shutdown.goodnight);
+    let shutdown_timeout = Duration::from_secs(
+        check
+            .get("SHUTDOWN_TIMEOUT", file.shutdown.timeout_seconds)
+            .unwrap_or(10),
+    );
+
+    let tls = resolve_tls(&mut check, file.tls, file.acme);
+    if tls.mode != TlsMode::Off && tls.http_port == Some(tls.https_port) {
+        check.error("tls.https_port and tls.http_port must differ");
+    }
+
+    if !check.errors.is_empty() {
+        anyhow::bail!(
+            "invalid configuration:\n  - {}",
+            check.errors.join("\n  - ")
+        );
+    }
+    Ok(AppConfig {
+        bind_addr: SocketAddr::from((bind_host, bind_port)),
+        targets,
+        agent_token,
+        queue_policy,
+        queue_weights,
+        data_dir,
+        access_codes,
+        admins,
+        public_url,
+        trusted_proxies,
+        settings,
+        goodnight,
+        shutdown_timeout,
+        tls,
+    })
+}
+
+/// Admins as (name, password): `ADMIN_USERS=<name>=<password>,…` replaces `[admin.users]`, and
+/// `ADMIN_PASSWORD` (or `admin.password`) adds one named `admin`.
+fn resolve_admins(check: &mut Check, file: AdminFile) -> Vec<(String, String)> {
+    let mut admins: Vec<(String, String)> = file.users.into_iter().collect();
+    if let Some(raw) = env("ADMIN_USERS") {
+        admins = raw
+            .split(',')
+            .map(str::trim)
+            .filter(|e| !e.is_empty())
+            .map(|e| {
+                let (name, password) = e.split_once('=').unwrap_or((e, ""));
+                (name.trim().to_string(), password.to_string())
+            })
+            .collect();
+    }
+    if let Some(password) = check.get::<String>("ADMIN_PASSWORD", file.password) {
+        admins.push(("admin".into(), password));
+    }
+    for (i, (name, password)) in admins.iter().enumerate() {
+        let name_ok = !name.is_empty()
+            && name.len() <= 32
+            && name
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        if !name_ok {
+            check.error(format!(
+                "admin '{name}': names must be 1-32 letters, digits, '-' or '_'"
+            ));
+        }
+        if password.len() < 8 {
+            check.error(format!(
+                "admin '{name}': the password must be at least 8 characters"
+            ));
+        }
+        // a Bearer token is only a password, so it must say who it is
+        let earlier = &admins[..i];
+        if earlier.iter().any(|(n, _)| n.eq_ignore_ascii_case(name)) {
+            check.error(format!("admin '{name}' is configured twice"));
+        } else if earlier.iter().any(|(_, p)| p == password) {
+            check.error(format!(
+                "admin '{name}': another admin has the same password"
+            ));
+        }
+    }
+    admins
+}
+
+/// The guest page's starting theme; `THEME_<FIELD>` overrides the file's `[theme]`.
+fn resolve_theme(check: &mut Check, file: ThemeFile) -> Theme {
+    let defaults = Theme::default();
+    let mut get = |key: &str, file: Option<String>, default: String| {
+        check
+            .get::<String>(key, file)
+            .map_or(default, |v| v.trim().to_string())
+    };
+    Theme {
+        couple: get("THEME_COUPLE", file.couple, defaults.couple),
+        date: get("THEME_DATE", file.date, defaults.date),
+        accent: get("THEME_ACCENT", file.accent, defaults.accent),
+        background: get("THEME_BACKGROUND", file.background, defaults.background),
+        photo: get("THEME_PHOTO", file.photo, defaults.photo),
+        welcome: get("THEME_WELCOME", file.welcome, defaults.welcome),
+        footer: get("THEME_FOOTER", file.footer, defaults.footer),
+    }
+}
+
+/// HTTPS: `TLS_MODE` if set, else static when a certificate is configured, acme when a domain
+/// is, off otherwise. `ACME_DOMAIN` takes a comma-separated list.
+fn resolve_tls(check: &mut Check, file: TlsFile, acme: AcmeFile) -> TlsConfig {
+    let defaults = TlsConfig::default();
+    let https_port = check
+        .get("HTTPS_PORT", file.https_port)
+        .unwrap_or(defaults.https_port);
+    let http_port = match check.get("HTTP_PORT", file.http_port) {
+        Some(0) => None,
+        Some(port) => Some(port),
+        None => defaults.http_port,
+    };
+
+    let cert = check.get::<PathBuf>("TLS_CERT", file.cert);
+    let key = check.get::<PathBuf>("TLS_KEY", file.key);
+    let mut domains: Vec<String> = acme.domain.into_iter().chain(acme.domains).collect();
+    if let Some(raw) = env("ACME_DOMAIN") {
+        domains = raw.split(',').map(str::to_string).collect();
+    }
+    let domains: Vec<String> = domains
+        .iter()
+        .map(|d| d.trim().to_lowercase())
+        .filter(|d| !d.is_empty())
+        .collect();
+    for d in &domains {
+        let ok = d
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'.');
+        if !ok || !d.contains('.') {
+            check.error(format!(
+                "acme domain '{d}' must be a plain host name, like gordijn.example.com"
+            ));
+        }
+    }
+    let contact = check.get::<String>("ACME_CONTACT_EMAIL", acme.contact_email);
+    let cache_dir = check
+        .get("ACME_CACHE_DIR", acme.cache_dir)
+        .unwrap_or_else(|| PathBuf::from("./acme-cache"));
+    let staging = check.get("ACME_STAGING", acme.staging).unwrap_or(false);
+
+    let mode = match check.get::<String>("TLS_MODE", file.mode) {
+        Some(mode) => mode.trim().to_lowercase(),
+        None if cert.is_some() || key.is_some() => {
+            if !domains.is_empty() {
+                check.error("both a certificate and acme domains are set; pick one with tls.mode / TLS_MODE");
+            }
+            "static".into()
+        }
+        None if !domains.is_empty() => "acme".into(),
+        None => "off".into(),
+    };
+    let mode = match mode.as_str() {
+        "off" => TlsMode::Off,
+        "static" => match (cert, key) {
+            (Some(cert), Some(key)) => {
+                for (what, path) in [("tls.cert / TLS_CERT", &cert), ("tls.key / TLS_KEY", &key)] {
+                    if !path.is_file() {
+                        check.error(format!("{what}: {} is not a readable file", path.display()));
+                    }
+                }
+                TlsMode::Static { cert, key }
+            }
+            _ => {
+                check.error("tls mode static needs both tls.cert / TLS_CERT and tls.key / TLS_KEY");
+                TlsMode::Off
+            }
+        },
+        "acme" => {
+            if domains.is_empty() {
+                check.error("tls mode acme needs acme.domains / ACME_DOMAIN");
+            }
+            TlsMode::Acme {
+                domains,
+                contact,
+                cache_dir,
+                staging,
+            }
+        }
+        other => {
+            check.error(format!(
+                "tls.mode / TLS_MODE: expected off, static or acme, not '{other}'"
+            ));
+            TlsMode::Off
+        }
+    };
+    if let Some(feature) = mode.missing_feature() {
+        check.error(format!(
+            "TLS mode {} needs a build with the {feature} feature (cargo build --features {feature})",
+            mode.as_str()
+        ));
+    }
+    TlsConfig {
+        mode,
+        https_port,
+        http_port,
+    }
+}
+
+/// The display targets: `DISPLAY_TARGETS` picks (and orders) them by name if set, else the
+/// file's `[[targets]]`, else one "curtain". The first is the main target and takes the
+/// unprefixed variables (`WLED_HOST`, …); the others read `TARGET_<NAME>_*`.
+fn resolve_targets(check: &mut Check, files: Vec<TargetFile>) -> Vec<TargetConfig> {
+    let mut files: Vec<TargetFile> = files;
+    for (i, t) in files.iter().enumerate() {
+        if t.name.trim().is_empty() {
+            check.error(format!("targets[{i}].name is required"));
+        }
+    }
+    let display_targets = env("DISPLAY_TARGETS");
+    let names: Vec<String> = match &display_targets {
+        Some(raw) => raw
+            .split(',')
+            .map(|n| n.trim().to_string())
+            .filter(|n| !n.is_empty())
+            .collect(),
+        None => files.iter().map(|t| t.name.clone()).collect(),
+    };
+    let names = if names.is_empty() {
+        vec!["curtain".to_string()]
+    } else {
+        names
+    };
+
+    let mut targets: Vec<TargetConfig> = Vec::with_capacity(names.len());
+    for (i, name) in names.iter().enumerate() {
+        let file = match files.iter().position(|t| &t.name == name) {
+            Some(pos) => files.swap_remove(pos),
+            None => TargetFile::default(),
+        };
+        let main = targets.first();
+        let prefix = if i == 0 {
+            String::new()
+        } else {
+            format!("TARGET_{}_", name.to_uppercase().replace('-', "_"))
+        };
+        let key = |k: &str| format!("{prefix}{k}");
+        let what = format!("target '{name}'");
+
+        if targets.iter().any(|t| t.name.eq_ignore_ascii_case(name)) {
+            check.error(format!("{what} is listed twice"));
+        }
+        if !name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        {
+            check.error(format!(
+                "{what}: names may only use letters, digits, '-' and '_'"
+            ));
+        }
+
+        let mode = if i == 0 {
+            file.mode
+        } else {
+            check.get(&key("MODE"), file.mode)
+        };
+        let mode = match (i, mode) {
+            (0, Some(TargetMode::Mirror)) => {
+                check.error(format!("{what} is the main target and can't be a mirror"));
+                TargetMode::Queue
+            }
+            (0, _) => TargetMode::Queue,
+            (_, mode) => mode.unwrap_or(TargetMode::Mirror),
+        };
+
+        // ssh-tunnel (default), direct, websocket, http-proxy, socks5 or agent
+        let (kind_key, proxy_key) = if i == 0 {
+            ("WLED_TRANSPORT".to_string(), "WLED_PROXY".to_string())
+        } else {
+            (key("TRANSPORT"), key("PROXY"))
+        };
+        let kind = check.get::<String>(&kind_key, file.transport);
+        let proxy = check.get::<String>(&proxy_key, file.proxy);
+        let transport = match (kind, main) {
+            (None, Some(main)) => Ok(main.transport.clone()),
+            (kind, _) => Transport::from_parts(kind.as_deref(), proxy),
+        };
+        let transport = match transport {
+            Ok(t) => t,
+            Err(e) => {
+                check.error(format!("{what}: {e}"));
+                Transport::Direct
+            }
+        };
+
+        let ssh_host = check
+            .get(&key("SSH_HOST"), file.ssh_host)
+            .or_else(|| main.map(|m| m.ssh_host.clone()))
+            .unwrap_or_else(|| "x220-nixos.tail19d694.ts.net".into());
+        let ssh_user = check
+            .get(&key("SSH_USER"), file.ssh_user)
+            .or_else(|| main.and_then(|m| m.ssh_user.clone()));
+        // host as seen from the SSH host
+        let wled_host = check.get::<String>(&key("WLED_HOST"), file.wled_host);
+        let wled_host = match (i, wled_host) {
+            (_, Some(h)) => h,
+            (0, None) => "127.0.0.1".into(),
+            (_, None) => {
+                check.error(format!(
+                    "{what}: wled_host / {}WLED_HOST is required",
+                    prefix
+                ));
+                String::new()
+            }
+        };
+        let wled_port = check.get(&key("WLED_PORT"), file.wled_port).unwrap_or(80);
+        if wled_port == 0 {
+            check.error(format!("{what}: wled_port must not be 0"));
+        }
+        let local_tunnel_port = check.get(&key("LOCAL_TUNNEL_PORT"), file.local_tunnel_port);
+        let local_tunnel_port = match (i, local_tunnel_port) {
+            (_, Some(p)) => p,
+            (0, None) => 18080,
+            (_, None) if transport.uses_tunnel() => {
+                check.error(format!(
+                    "{what}: local_tunnel_port / {prefix}LOCAL_TUNNEL_PORT is required for the ssh-tunnel transport"
+                ));
+                0
+            }
+            (_, None) => 0,
+        };
+        if transport.uses_tunnel()
+            && targets
+                .iter()
+                .any(|t| t.transport.uses_tunnel() && t.local_tunnel_port == local_tunnel_port)
+        {
+            check.error(format!(
+                "{what}: local tunnel port {local_tunnel_port} is already used by another target"
+            ));
+        }
+
+        targets.push(TargetConfig {
+            name: name.clone(),
+            ssh_host,
+            ssh_user,
+            wled_host,
+            wled_port,
+            local_tunnel_port,
+            // e.g. "TT" if a Text usermod is installed
+            text_param_key: check.get(&key("TEXT_PARAM_KEY"), file.text_param_key),
+            text_preset_id: check.get(&key("TEXT_PRESET_ID"), file.text_preset_id),
+            mode,
+            transport,
+        });
+    }
+    if display_targets.is_some() {
+        for t in files {
+            check.error(format!(
+                "target '{}' is in the config file but not in DISPLAY_TARGETS",
+                t.name
+            ));
+        }
+    }
+    targets
+}
+
+/// `trouw-gordijn check-config [path]`: validates the configuration and prints what it amounts
+/// to, without starting anything.
+pub fn check_command(path: Option<&Path>) -> anyhow::Result<()> {
+    let found = match path {
+        Some(p) => Some(p.to_path_buf()),
+        None => find_file(),
+    };
+    let cfg = load(found.as_deref())?;
+    match &found {
+        Some(p) => println!("config file: {}", p.display()),
+        None => println!("config file: none (environment only)"),
+    }
+    match &cfg.tls.mode {
+        TlsMode::Off => println!("listen:      {} (HTTP)", cfg.bind_addr),
+        mode => {
+            let host = cfg.bind_addr.ip();
+            println!("listen:      {host}:{} (HTTPS)", cfg.tls.https_port);
+            if let Some(port) = cfg.tls.http_port {
+                println!("redirect:    {host}:{port} -> HTTPS");
+            }
+            match mode {
+                TlsMode::Static { cert, key } => {
+                    println!(
+                        "tls:         static, {} / {}",
+                        cert.display(),
+                        key.display()
+                    )
+                }
+                TlsMode::Acme {
+                    domains, staging, ..
+                } => println!(
+                    "tls:         acme ({}) for {}",
+                    if *staging { "staging" } else { "production" },
+                    domains.join(", ")
+                ),
+                TlsMode::Off => {}
+            }
+        }
+    }
+    if let Some(url) = &cfg.public_url {
+        println!("public url:  {url}");
+    }
+    if !cfg.trusted_proxies.is_empty() {
+        let proxies: Vec<_> = cfg.trusted_proxies.iter().map(IpAddr::to_string).collect();
+        println!("proxies:     {}", proxies.join(", "));
+    }
+    println!("data dir:    {}", cfg.data_dir.display());
+    println!("queue:       {}", cfg.queue_policy.as_str());
+    for (name, weight) in &cfg.queue_weights {
+        println!("  weight {name} = {weight}");
+    }
+    println!(
+        "display:     {}s per message, brightness {}",
+        cfg.settings.display_seconds, cfg.settings.brightness
+    );
+    println!(
+        "messages:    text up to {} bytes, names up to {}",
+        cfg.settings.max_text_len, cfg.settings.max_name_len
+    );
+    println!(
+        "access:      {}",
+        if cfg.access_codes.is_empty() {
+            "open (no configured codes)".to_string()
+        } else {
+            format!("{} configured code(s)", cfg.access_codes.len())
+        }
+    );
+    println!(
+        "admin:       {}",
+        if cfg.admins.is_empty() {
+            "no password configured; one is made up and logged at every start".to_string()
+        } else {
+            let names: Vec<_> = cfg.admins.iter().map(|(name, _)| name.as_str()).collect();
+            names.join(", ")
+        }
+    );
+    let theme = &cfg.settings.theme;
+    if !theme.couple.is_empty() || !theme.date.is_empty() {
+        println!("theme:       {} {}", theme.couple, theme.date);
+    }
+    if !theme.photo.is_empty() {
+        let photo = cfg.data_dir.join("theme").join(&theme.photo);
+        let missing = if photo.is_file() { "" } else { " (missing)" };
+        println!("photo:       {}{missing}", photo.display());
+    }
+    println!(
+        "shutdown:    goodnight {}, waits up to {}s for open requests",
+        cfg.goodnight
+            .as_ref()
+            .map_or("none".to_string(), ToString::to_string),
+        cfg.shutdown_timeout.as_secs()
+    );
+    for (i, t) in cfg.targets.iter().enumerate() {
+        println!(
+            "target {i}:    {} ({}) via {} -> {}:{}",
+            t.name,
+            if i == 0 { "main" } else { t.mode.as_str() },
+            t.transport,
+            t.wled_host,
+            t.wled_port
+        );
+    }
+    println!("ok");
+    Ok(())
+}
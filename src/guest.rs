@@ -0,0 +1,184 @@
+//! "My messages": a guest's own messages, found through their browser's session cookie, which
+//! they can follow, withdraw or correct until they are shown. An event stream tells the page
+//! when one of them goes live, so a guest at the bar doesn't miss it.
+
+use std::convert::Infallible;
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    Form,
+};
+use futures_util::{stream, Stream};
+use serde::Deserialize;
+use tokio::sync::broadcast;
+
+use crate::{
+    audit::{Action, Client},
+    message_text,
+    rotation::{Edit, OnAir},
+    session, AppState,
+};
+
+/// `GET /api/my/messages`: the guest's message on screen (`"showing"`) and the ones waiting,
+/// with their place in line and estimated show time.
+pub async fn list(State(state): State<AppState>, headers: HeaderMap) -> impl IntoResponse {
+    let mut items = Vec::new();
+    if let Some(token) = session::token(&headers) {
+        for target in state.targets.iter() {
+            let Some(rotation) = &target.rotation else {
+                continue;
+            };
+            let snapshot = rotation.snapshot();
+            if let Some(c) = snapshot.current.as_ref().filter(|c| c.owner == token) {
+                items.push(serde_json::json!({
+                    "id": c.id,
+                    "text": c.text,
+                    "color": c.color,
+                    "target": target.cfg.name,
+                    "status": "showing",
+                    "position": 0,
+                    "eta_seconds": 0,
+                }));
+            }
+            for (pos, m) in snapshot.queue.iter().enumerate() {
+                if m.owner != token {
+                    continue;
+                }
+                items.push(serde_json::json!({
+                    "id": m.id,
+                    "text": m.text,
+                    "color": m.color,
+                    "target": target.cfg.name,
+                    "status": "waiting",
+                    "position": pos + 1,
+                    "eta_seconds": snapshot.eta(pos).map(|d| d.as_secs()),
+                }));
+            }
+        }
+    }
+    (
+        [(header::CACHE_CONTROL, "no-store, max-age=0")],
+        axum::Json(serde_json::json!({ "items": items })),
+    )
+}
+
+#[derive(Deserialize)]
+pub struct CancelForm {
+    id: u64,
+}
+
+/// `POST /api/my/messages/cancel`: withdraws a waiting message.
+pub async fn cancel(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    client: Client,
+    Form(f): Form<CancelForm>,
+) -> impl IntoResponse {
+    let Some(token) = session::token(&headers) else {
+        return (StatusCode::FORBIDDEN, "No session");
+    };
+    for target in state.targets.iter() {
+        let Some(rotation) = &target.rotation else {
+            continue;
+        };
+        if rotation.cancel(f.id, &token).await {
+            state.audit.record(
+                Action::Remove,
+                &client.guest(None),
+                Some(f.id),
+                Some(&target.cfg.name),
+                serde_json::json!({ "via": "cancel" }),
+            );
+            return (StatusCode::OK, "ok");
+        }
+    }
+    (StatusCode::NOT_FOUND, "Not waiting")
+}
+
+#[derive(Deserialize)]
+pub struct EditForm {
+    id: u64,
+    text: String,
+    color: Option<String>,
+}
+
+/// `POST /api/my/messages/edit`: changes a waiting message's text and colour.
+pub async fn edit(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    client: Client,
+    Form(f): Form<EditForm>,
+) -> impl IntoResponse {
+    let Some(token) = session::token(&headers) else {
+        return (StatusCode::FORBIDDEN, "No session".to_string());
+    };
+    for (idx, target) in state.targets.iter().enumerate() {
+        let Some(rotation) = &target.rotation else {
+            continue;
+        };
+        if !rotation.snapshot().queue.iter().any(|m| m.id == f.id) {
+            continue;
+        }
+        // this rotation's curtain decides how long the text may be
+        let text = match message_text(&f.text, state.max_text_len(idx).await) {
+            Ok(text) => text,
+            Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()),
+        };
+        let edit = Edit {
+            id: f.id,
+            owner: token.clone(),
+            text: text.clone(),
+            color: f.color.clone(),
+        };
+        if rotation.edit(edit).await {
+            state.audit.record(
+                Action::Edit,
+                &client.guest(None),
+                Some(f.id),
+                Some(&target.cfg.name),
+                serde_json::json!({ "text": text, "color": f.color }),
+            );
+            return (StatusCode::OK, "ok".to_string());
+        }
+    }
+    (StatusCode::NOT_FOUND, "Not waiting".to_string())
+}
+
+/// `GET /api/my/events`: server-sent `live` events (`{id, text, target}`) whenever one of the
+/// guest's messages goes on the curtain.
+pub async fn events(State(state): State<AppState>, headers: HeaderMap) -> impl IntoResponse {
+    let Some(token) = session::token(&headers) else {
+        return (StatusCode::FORBIDDEN, "No session").into_response();
+    };
+    Sse::new(live_events(state.on_air.subscribe(), token))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+fn live_events(
+    rx: broadcast::Receiver<OnAir>,
+    token: String,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    stream::unfold((rx, token), |(mut rx, token)| async move {
+        loop {
+            match rx.recv().await {
+                Ok(on_air) if on_air.owner == token => {
+                    let data = serde_json::json!({
+                        "id": on_air.id,
+                        "text": on_air.text,
+                        "target": on_air.target,
+                    });
+                    let event = Event::default().event("live").data(data.to_string());
+                    return Some((Ok(event), (rx, token)));
+                }
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+}
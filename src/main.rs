@@ -1,31 +1,109 @@
 use std::{
-    collections::VecDeque,
+    collections::HashMap,
     net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
     process::Stdio,
     sync::{
-        atomic::{AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
         Arc,
     },
-    time::{Duration, Instant},
+    time::Duration,
 };
 
 use axum::{
-    extract::State,
-    http::{header, StatusCode},
+    extract::{ws::WebSocketUpgrade, DefaultBodyLimit, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
     response::{Html, IntoResponse},
     routing::{get, post},
     Form, Router,
 };
 use serde::Deserialize;
-use tokio::{process::Command, sync::Mutex, time};
-use tower_http::trace::TraceLayer;
-use tracing::{error, info};
+use tokio::{
+    process::{Child, Command},
+    sync::{broadcast, watch, Mutex, Notify},
+    time::{self, Instant},
+};
+use tower_http::{services::ServeDir, trace::TraceLayer};
+use tracing::{error, info, warn};
+
+mod access;
+mod agent;
+mod audit;
+mod auth;
+mod capabilities;
+mod cli;
+mod config;
+mod guest;
+mod metrics;
+mod mock_wled;
+mod presets;
+mod preview;
+mod rotation;
+mod schedule;
+mod session;
+mod settings;
+mod shutdown;
+mod theme;
+mod tls;
+mod transport;
+mod wled;
+mod wled_ws;
+
+#[cfg(test)]
+mod tests;
+
+use access::AccessCodes;
+use audit::{Action, Actor, AuditLog, Client};
+use auth::Admins;
+use capabilities::{Capabilities, CapabilityCache};
+use clap::Parser;
+use cli::Cli;
+use preview::LivePreview;
+use rotation::{CurrentDisplay, Enqueued, OnAir, QueuedMessage, Rotation, Shown};
+use schedule::Policy;
+use settings::{Settings, SettingsStore};
+use tls::{TlsMode, TlsStatus};
+use transport::{Transport, WledClient};
 
 // Picture upload functionality removed
 
+/// Default share of the turns for messages sent from the admin page (weighted policy).
+const ADMIN_WEIGHT: u32 = 4;
+
 #[derive(Clone, Debug)]
 struct AppConfig {
     bind_addr: SocketAddr,
+    // display targets; the first one owns the main rotation
+    targets: Vec<TargetConfig>,
+    // shared secret for onsite agents (agent transport)
+    agent_token: Option<String>,
+    // order of waiting messages, and the weighted policy's per-name weights (lowercase)
+    queue_policy: Policy,
+    queue_weights: HashMap<String, u32>,
+    // where the app keeps what it changes at runtime (access codes, …)
+    data_dir: PathBuf,
+    // `ACCESS_CODES` as (label, code) pairs
+    access_codes: Vec<(String, String)>,
+    // who may use the admin page and API, as (name, password) pairs
+    admins: Vec<(String, String)>,
+    // base URL guests reach the page at, for QR codes; taken from the request when unset
+    public_url: Option<String>,
+    // reverse proxies whose X-Forwarded-For names the client
+    trusted_proxies: Vec<IpAddr>,
+    // starting values for the settings editable at runtime
+    settings: Settings,
+    // what the curtain shows once the app has stopped; left as it is when None
+    goodnight: Option<shutdown::Goodnight>,
+    // how long open requests may take to finish when stopping
+    shutdown_timeout: Duration,
+    // HTTPS served by the app itself; the TLS listeners use `bind_addr`'s host
+    tls: tls::TlsConfig,
+}
+
+#[derive(Clone, Debug)]
+struct TargetConfig {
+    name: String,
     ssh_host: String,
     ssh_user: Option<String>,
     wled_host: String,
@@ -33,40 +111,163 @@ struct AppConfig {
     local_tunnel_port: u16,
     text_param_key: Option<String>,
     text_preset_id: Option<i32>,
-    // ACME/HTTPS (only when feature enabled)
-    #[cfg(feature = "acme")]
-    acme_domain: Option<String>,
-    #[cfg(feature = "acme")]
-    acme_contact_email: Option<String>,
-    #[cfg(feature = "acme")]
-    acme_cache_dir: String,
+    mode: TargetMode,
+    transport: Transport,
+}
+
+/// How a display target picks what to show.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TargetMode {
+    /// Shows whatever the main (first) target shows.
+    Mirror,
+    /// Runs its own queue and rotation.
+    Queue,
+}
+
+impl TargetMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            TargetMode::Mirror => "mirror",
+            TargetMode::Queue => "queue",
+        }
+    }
+}
+
+impl std::str::FromStr for TargetMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mirror" => Ok(TargetMode::Mirror),
+            "queue" => Ok(TargetMode::Queue),
+            other => anyhow::bail!("must be 'mirror' or 'queue', got '{other}'"),
+        }
+    }
 }
 
 #[derive(Clone)]
 struct AppState {
-    cfg: AppConfig,
-    client: reqwest::Client,
-    // simple guard to avoid overlapping tunnel restarts
-    tunnel_lock: Arc<Mutex<()>>,
-    // message rotation
-    queue: Arc<Mutex<VecDeque<QueuedMessage>>>,
-    current: Arc<Mutex<Option<CurrentDisplay>>>,
+    agent_token: Option<Arc<str>>,
+    targets: Arc<Vec<Target>>,
     next_id: Arc<AtomicU64>,
+    policy: Policy,
+    weights: Arc<HashMap<String, u32>>,
+    // messages going live, for the guests' "on the curtain now" notifications
+    on_air: broadcast::Sender<OnAir>,
+    access: Arc<AccessCodes>,
+    admins: Arc<Admins>,
+    public_url: Option<Arc<str>>,
+    trusted_proxies: Arc<[IpAddr]>,
+    settings: Arc<SettingsStore>,
+    audit: Arc<AuditLog>,
+    data_dir: Arc<Path>,
+    tls: Arc<TlsStatus>,
 }
 
-#[derive(Clone, Debug)]
-struct QueuedMessage {
-    id: u64,
-    text: String,
-    color: Option<String>, // #rrggbb
+#[derive(Clone)]
+struct Target {
+    cfg: TargetConfig,
+    wled: WledClient,
+    // the ssh process of the tunnel (SSH transport); the lock also serializes restarts, and
+    // dropping the last handle stops it
+    tunnel: Arc<Mutex<Option<Child>>>,
+    // last known reachability of the device (heartbeat / display results)
+    online: Arc<Online>,
+    // outcome of the last read-back after a display update
+    last_check: Arc<Mutex<Option<StateCheck>>>,
+    // effect/palette indices, firmware version etc. of this device
+    caps: Arc<CapabilityCache>,
+    // runtime settings (brightness, text preset, idle scenes, …) as last changed
+    settings: watch::Receiver<Settings>,
+    // live pixel frames for the preview canvases
+    preview: Arc<LivePreview>,
+    // counts presets put up from the admin page; a preview only restores the rotation if no
+    // later one replaced it
+    presets_shown: Arc<AtomicU64>,
+    // message rotation; None for mirrors
+    rotation: Option<Rotation>,
+}
+
+impl AppState {
+    /// Looks up a target by name, or the main target when no name is given.
+    fn target_index(&self, name: Option<&str>) -> Option<usize> {
+        match name.map(str::trim).filter(|n| !n.is_empty()) {
+            None => Some(0),
+            Some(n) => self
+                .targets
+                .iter()
+                .position(|t| t.cfg.name.eq_ignore_ascii_case(n)),
+        }
+    }
+
+    /// Index of the target whose rotation feeds `idx` (mirrors follow the main target).
+    fn rotation_owner(&self, idx: usize) -> usize {
+        if self.targets[idx].rotation.is_some() {
+            idx
+        } else {
+            0
+        }
+    }
+
+    /// Longest message text for target `idx`: the setting, or less if its rotation's curtain
+    /// keeps shorter segment names.
+    async fn max_text_len(&self, idx: usize) -> usize {
+        let limit = self.settings.get().max_text_len;
+        match self.targets[self.rotation_owner(idx)].caps.peek().await {
+            Some(caps) => limit.min(caps.segment_name_len),
+            None => limit,
+        }
+    }
+
+    fn rotation(&self, idx: usize) -> &Rotation {
+        self.targets[self.rotation_owner(idx)]
+            .rotation
+            .as_ref()
+            .expect("rotation owner has a rotation")
+    }
+
+    /// Targets (with their index) that display the rotation owned by `owner`.
+    fn display_targets(&self, owner: usize) -> impl Iterator<Item = (usize, &Target)> {
+        self.targets
+            .iter()
+            .enumerate()
+            .filter(move |(i, t)| *i == owner || (owner == 0 && t.rotation.is_none()))
+    }
 }
 
+/// Last known reachability of a device; changes wake the rotation actor.
+#[derive(Default)]
+struct Online {
+    up: AtomicBool,
+    changed: Notify,
+}
+
+impl Online {
+    fn get(&self) -> bool {
+        self.up.load(Ordering::Relaxed)
+    }
+
+    /// Stores the new reachability and returns the previous one.
+    fn set(&self, up: bool) -> bool {
+        let was = self.up.swap(up, Ordering::Relaxed);
+        if was != up {
+            self.changed.notify_one();
+        }
+        was
+    }
+
+    async fn changed(&self) {
+        self.changed.notified().await
+    }
+}
+
+/// Result of comparing the device state with what we asked for.
 #[derive(Clone, Debug)]
-struct CurrentDisplay {
-    id: u64,
+struct StateCheck {
+    at: Instant,
     text: String,
-    color: Option<String>,
-    started: Instant,
+    mismatches: Vec<String>,
 }
 
 // UI assets are compiled in from the assets/ directory
@@ -78,317 +279,231 @@ async fn main() -> anyhow::Result<()> {
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
         .init();
 
-    let cfg = load_config()?;
-    // No uploads directory needed anymore
-
-    let state = AppState {
-        cfg: cfg.clone(),
-        client: reqwest::Client::new(),
-        tunnel_lock: Arc::new(Mutex::new(())),
-        queue: Arc::new(Mutex::new(VecDeque::new())),
-        current: Arc::new(Mutex::new(None)),
-        next_id: Arc::new(AtomicU64::new(1)),
-    };
-
-    // Start tunnel supervision in background
-    let tunnel_state = state.clone();
-    tokio::spawn(async move { supervise_tunnel(tunnel_state).await });
+    let cli = Cli::parse();
+    match cli.command {
+        None | Some(cli::Command::Serve) => serve(cli.config.as_deref()).await,
+        // the onsite relay instead of the web app
+        Some(cli::Command::Agent) => agent::run(agent::AgentConfig::from_env()?).await,
+        // pretends to be a WLED device for rehearsals
+        Some(cli::Command::MockWled) => mock_wled::run().await,
+        Some(_) => cli::run(cli).await,
+    }
+}
 
-    // Start message rotation worker
-    let rot_state = state.clone();
-    tokio::spawn(async move { rotation_worker(rot_state).await });
+/// Runs the web app until it is stopped, then hands the curtain over (see [`shutdown`]).
+async fn serve(config: Option<&Path>) -> anyhow::Result<()> {
+    let cfg = config::load(config)?;
+    metrics::start();
 
-    let app = Router::new()
-        .route("/", get(index))
-        .route("/admin", get(admin_page))
-        .route("/api/message", post(send_message))
-        .route("/assets/app.js", get(app_js))
-        .route("/assets/admin.js", get(admin_js))
-        .route("/api/queue", get(get_queue))
-        .route("/api/admin/remove", post(admin_remove))
-        .with_state(state)
-        .layer(TraceLayer::new_for_http());
+    let state = build_state(&cfg)?;
+    spawn_workers(&state);
+    match rotation::restore(&state).await {
+        Ok(0) => {}
+        Ok(n) => info!(messages = n, "saved queue restored"),
+        Err(e) => warn!(?e, "restoring the saved queue failed"),
+    }
+    let app = router(state.clone());
+    let stopping = shutdown::listen();
 
-    // If ACME is configured and the feature is enabled, serve HTTPS with automatic certificates
-    #[cfg(feature = "acme")]
-    {
-        if let Some(domain) = cfg.acme_domain.clone() {
-            return serve_with_acme(
+    let served = async {
+        if cfg.tls.mode != TlsMode::Off {
+            return tls::serve(
                 app,
-                domain,
-                cfg.acme_contact_email.clone(),
-                cfg.acme_cache_dir.clone(),
+                cfg.bind_addr.ip(),
+                state.tls.clone(),
+                stopping.clone(),
+                cfg.shutdown_timeout,
             )
             .await;
         }
-    }
 
-    // Fallback: plain HTTP
-    let listener = tokio::net::TcpListener::bind(cfg.bind_addr).await?;
-    info!("listening on {}", cfg.bind_addr);
-    axum::serve(listener, app).await?;
-    Ok(())
+        // plain HTTP, e.g. behind a reverse proxy
+        let listener = tokio::net::TcpListener::bind(cfg.bind_addr).await?;
+        info!("listening on {}", cfg.bind_addr);
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(shutdown::stopped(stopping.clone()))
+        .await?;
+        anyhow::Ok(())
+    };
+    // event streams and websockets stay open until the browser goes, so don't wait forever
+    let drained = async {
+        shutdown::stopped(stopping.clone()).await;
+        time::sleep(cfg.shutdown_timeout).await;
+    };
+    let result = tokio::select! {
+        r = served => r,
+        () = drained => {
+            warn!(timeout = ?cfg.shutdown_timeout, "connections still open; stopping anyway");
+            Ok(())
+        }
+    };
+    shutdown::finish(&state, cfg.goodnight.as_ref()).await;
+    result
 }
 
-fn load_config() -> anyhow::Result<AppConfig> {
-    let bind_host = std::env::var("BIND_HOST").unwrap_or_else(|_| "0.0.0.0".into());
-    let bind_port: u16 = std::env::var("BIND_PORT")
-        .ok()
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(8080);
-    let ssh_host =
-        std::env::var("SSH_HOST").unwrap_or_else(|_| "x220-nixos.tail19d694.ts.net".into());
-    let ssh_user = std::env::var("SSH_USER").ok();
-    let wled_host = std::env::var("WLED_HOST").unwrap_or_else(|_| "127.0.0.1".into()); // host as seen from SSH host
-    let wled_port: u16 = std::env::var("WLED_PORT")
-        .ok()
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(80);
-    let local_tunnel_port: u16 = std::env::var("LOCAL_TUNNEL_PORT")
-        .ok()
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(18080);
-    let text_param_key = std::env::var("TEXT_PARAM_KEY").ok(); // e.g. "TT" if a Text usermod is installed
-    let text_preset_id = std::env::var("TEXT_PRESET_ID")
-        .ok()
-        .and_then(|s| s.parse().ok());
-    // ACME options
-    #[cfg(feature = "acme")]
-    let acme_domain = std::env::var("ACME_DOMAIN").ok();
-    #[cfg(feature = "acme")]
-    let acme_contact_email = std::env::var("ACME_CONTACT_EMAIL").ok();
-    #[cfg(feature = "acme")]
-    let acme_cache_dir = std::env::var("ACME_CACHE_DIR").unwrap_or_else(|_| "./acme-cache".into());
-
-    let ip: IpAddr = bind_host.parse().unwrap_or(IpAddr::from([0, 0, 0, 0]));
-    Ok(AppConfig {
-        bind_addr: SocketAddr::from((ip, bind_port)),
-        ssh_host,
-        ssh_user,
-        wled_host,
-        wled_port,
-        local_tunnel_port,
-        text_param_key,
-        text_preset_id,
-        #[cfg(feature = "acme")]
-        acme_domain,
-        #[cfg(feature = "acme")]
-        acme_contact_email,
-        #[cfg(feature = "acme")]
-        acme_cache_dir,
+/// Builds the shared state (WLED clients, queues) for the configured targets.
+fn build_state(cfg: &AppConfig) -> anyhow::Result<AppState> {
+    let audit = Arc::new(AuditLog::load(cfg.data_dir.join("audit.jsonl"))?);
+    let settings = Arc::new(SettingsStore::load(
+        cfg.data_dir.clone(),
+        cfg.settings.clone(),
+        audit.clone(),
+    )?);
+    let targets = cfg
+        .targets
+        .iter()
+        .enumerate()
+        .map(|(i, t)| {
+            let wled =
+                WledClient::new(&t.transport, &t.wled_host, t.wled_port, t.local_tunnel_port)?;
+            info!(
+                "target {} reaches WLED at {} via {}",
+                t.name,
+                wled.base(),
+                t.transport
+            );
+            Ok(Target {
+                cfg: t.clone(),
+                wled,
+                tunnel: Arc::new(Mutex::new(None)),
+                online: Arc::new(Online::default()),
+                last_check: Arc::new(Mutex::new(None)),
+                caps: Arc::new(CapabilityCache::default()),
+                settings: settings.subscribe(),
+                preview: Arc::new(LivePreview::default()),
+                presets_shown: Arc::new(AtomicU64::new(0)),
+                // the main target always runs the main rotation
+                rotation: (i == 0 || t.mode == TargetMode::Queue).then(Rotation::default),
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(AppState {
+        agent_token: cfg.agent_token.as_deref().map(Arc::from),
+        targets: Arc::new(targets),
+        next_id: Arc::new(AtomicU64::new(1)),
+        policy: cfg.queue_policy,
+        weights: Arc::new(cfg.queue_weights.clone()),
+        on_air: broadcast::channel(64).0,
+        access: Arc::new(AccessCodes::load(
+            cfg.data_dir.join("access-codes.json"),
+            &cfg.access_codes,
+        )?),
+        admins: Arc::new(Admins::new(&cfg.admins)),
+        public_url: cfg.public_url.as_deref().map(Arc::from),
+        trusted_proxies: Arc::from(cfg.trusted_proxies.as_slice()),
+        settings,
+        audit,
+        data_dir: Arc::from(cfg.data_dir.as_path()),
+        tls: Arc::new(TlsStatus::new(cfg.tls.clone())),
     })
 }
 
-async fn index(State(_state): State<AppState>) -> impl IntoResponse {
-    let html: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/index.html"));
+/// Starts device supervision and the rotation workers for every target.
+fn spawn_workers(state: &AppState) {
+    for (idx, target) in state.targets.iter().enumerate() {
+        // Start device supervision (tunnel for the SSH transport, heartbeat for all)
+        let device_state = state.clone();
+        tokio::spawn(async move { supervise_device(device_state, idx).await });
+        if let Some(socket) = target.wled.socket() {
+            tokio::spawn(socket.clone().run());
+        }
+
+        // Start the rotation actor
+        if target.rotation.is_some() {
+            tokio::spawn(rotation::run(state.clone(), idx));
+        }
+    }
+}
+
+fn router(state: AppState) -> Router {
+    // the guest page and its API; behind an access code once any exists (the queue stays open,
+    // the admin page uses it too)
+    let guest = Router::new()
+        .route("/", get(index))
+        .route("/api/preview/ws", get(preview::ws))
+        .route("/api/message", post(send_message))
+        .route("/api/my/messages", get(guest::list))
+        .route("/api/my/messages/cancel", post(guest::cancel))
+        .route("/api/my/messages/edit", post(guest::edit))
+        .route("/api/my/events", get(guest::events))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            access::require,
+        ));
+    // the admin page and everything it can change; signed in as a configured admin
+    let admin = Router::new()
+        .route("/admin", get(admin_page))
+        .route("/api/admin/access", get(access::list).post(access::create))
+        .route("/api/admin/access/revoke", post(access::revoke))
+        .route("/api/admin/access/qr", get(access::qr))
+        .route("/admin/access/cards", get(access::cards))
+        .route("/api/admin/preview/ws", get(preview::ws))
+        .route("/api/admin/remove", post(admin_remove))
+        .route("/api/admin/message", post(admin_message))
+        .route("/api/admin/skip", post(admin_skip))
+        .route("/api/admin/hold", post(admin_hold))
+        .route("/api/admin/clear", post(admin_clear))
+        .route("/api/admin/export", get(admin_export))
+        .route("/api/admin/audit", get(audit::list))
+        .route("/api/admin/audit/export", get(audit::export))
+        .route(
+            "/api/admin/settings",
+            get(settings::get).post(settings::update),
+        )
+        .route("/api/admin/device", get(admin_device))
+        .route("/api/admin/rediscover", post(admin_rediscover))
+        .route("/api/admin/presets", get(presets::list))
+        .route("/api/admin/presets/activate", post(presets::activate))
+        .route("/api/admin/presets/preview", post(presets::preview))
+        .route("/api/admin/presets/text", post(presets::generate_text))
+        .route(
+            "/api/admin/presets/settings",
+            post(presets::update_settings),
+        )
+        .route("/api/admin/theme", post(theme::update))
+        .route(
+            "/api/admin/theme/photo",
+            post(theme::upload_photo).layer(DefaultBodyLimit::max(theme::MAX_PHOTO_BYTES)),
+        )
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::require));
+    Router::new()
+        .merge(guest)
+        .merge(admin)
+        .route("/api/queue", get(get_queue))
+        .route("/assets/app.js", get(app_js))
+        .route("/assets/admin.js", get(admin_js))
+        .route("/assets/preview.js", get(preview_js))
+        .route("/api/targets", get(get_targets))
+        .route("/api/status", get(get_status))
+        .route("/metrics", get(metrics::handler))
+        // uploaded theme photos
+        .nest_service("/theme", ServeDir::new(state.data_dir.join("theme")))
+        .route("/api/agent/ws", get(agent_ws))
+        .with_state(state)
+        .layer(TraceLayer::new_for_http())
+}
+
+async fn index(State(state): State<AppState>) -> impl IntoResponse {
+    let html = match theme::render_index(&state.settings.get().theme) {
+        Ok(html) => html,
+        Err(e) => {
+            error!(?e, "rendering the guest page failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "page unavailable").into_response();
+        }
+    };
     (
         [
             (header::CACHE_CONTROL, "no-store, max-age=0"),
             (header::PRAGMA, "no-cache"),
         ],
-        Html(html.to_string()),
+        Html(html),
     )
+        .into_response()
 }
 
-/*
-#[allow(dead_code)]
-static APP_JS: &str = r#"(function(){
-  const I18N = {
-    nl: {
-      subtitle: 'Laat je felicitatie schitteren op het LED gordijn ✨',
-      message_label: 'Jouw bericht',
-      color_label: 'Kleur',
-      submit_btn: 'Stuur naar gordijn',
-      note: 'Max 64 tekens. Houd het lief en feestelijk 💛',
-      queue_title: 'Berichten wachtrij',
-      queue_empty: 'Geen berichten in de wachtrij…',
-      footer: 'Met liefde gemaakt • Wens fijn en respectvol 💐',
-      error_prefix: 'Mislukt:',
-      placeholder_text: 'Liefde, geluk en een lang leven samen!'
-    },
-    fr: {
-      subtitle: 'Faites briller votre félicitation sur le rideau LED ✨',
-      message_label: 'Votre message',
-      color_label: 'Couleur',
-      submit_btn: 'Envoyer au rideau',
-      note: '64 caractères max. Restez gentil et festif 💛',
-      queue_title: 'File d’attente des messages',
-      queue_empty: 'Aucun message dans la file d’attente…',
-      footer: 'Fait avec amour • Souhaitez avec gentillesse 💐',
-      error_prefix: 'Échec :',
-      placeholder_text: 'Amour, bonheur et une longue vie ensemble !'
-    },
-    de: {
-      subtitle: 'Lass deine Glückwünsche auf dem LED‑Vorhang erstrahlen ✨',
-      message_label: 'Deine Nachricht',
-      color_label: 'Farbe',
-      submit_btn: 'An den Vorhang senden',
-      note: 'Max. 64 Zeichen. Bitte lieb und festlich 💛',
-      queue_title: 'Nachrichten‑Warteschlange',
-      queue_empty: 'Keine Nachrichten in der Warteschlange…',
-      footer: 'Mit Liebe gemacht • Wünsche freundlich und respektvoll 💐',
-      error_prefix: 'Fehlgeschlagen:',
-      placeholder_text: 'Liebe, Glück und ein langes gemeinsames Leben!'
-    }
-  };
-  function getLang(){ return localStorage.getItem('lang') || 'nl'; }
-  function setLang(l){ localStorage.setItem('lang', l); applyTranslations(); markActiveLang(); refreshQueue().catch(()=>{}); }
-  function tr(key){ const lang = getLang(); return (I18N[lang] && I18N[lang][key]) || (I18N['nl'] && I18N['nl'][key]) || null; }
-  function applyTranslations(){
-    document.querySelectorAll('[data-i18n]').forEach(el=>{
-      const k = el.getAttribute('data-i18n'); if(!k) return;
-      const v = tr(k);
-      if(v != null) el.textContent = v; // only replace when we have a translation
-    });
-    const input = document.getElementById('text'); if(input){ const ph = tr('placeholder_text'); if(ph!=null) input.placeholder = ph; }
-  }
-  function markActiveLang(){ const sel = document.getElementById('langSelector'); if(!sel) return; const cur = getLang(); sel.querySelectorAll('button[data-lang]').forEach(btn=>{ btn.classList.toggle('active', btn.getAttribute('data-lang')===cur); }); }
-
-  async function submitMessage(ev){
-    ev.preventDefault();
-    const fd = new FormData(ev.target);
-    const res = await fetch('/api/message', { method: 'POST', body: new URLSearchParams(fd) });
-    if(res.ok){ ev.target.reset(); try{ await refreshQueue(); }catch(e){} }
-    else { const tt = await res.text(); const pref = tr('error_prefix') || 'Mislukt:'; alert(pref+' '+tt); }
-  }
-  window.submitMessage = submitMessage;
-
-  function hsvToRgb(h, s, v){
-    const c = v * s;
-    const x = c * (1 - Math.abs(((h / 60) % 2) - 1));
-    const m = v - c;
-    let r=0,g=0,b=0;
-    if (0 <= h && h < 60) { r=c; g=x; b=0; }
-    else if (60 <= h && h < 120) { r=x; g=c; b=0; }
-    else if (120 <= h && h < 180) { r=0; g=c; b=x; }
-    else if (180 <= h && h < 240) { r=0; g=x; b=c; }
-    else if (240 <= h && h < 300) { r=x; g=0; b=c; }
-    else { r=c; g=0; b=x; }
-    return [Math.round((r+m)*255), Math.round((g+m)*255), Math.round((b+m)*255)];
-  }
-  function rgbToHsv(r, g, b){
-    r /= 255; g /= 255; b /= 255;
-    const max = Math.max(r,g,b), min = Math.min(r,g,b);
-    const d = max - min;
-    let h = 0;
-    if (d !== 0){
-      switch(max){
-        case r: h = 60 * (((g-b)/d) % 6); break;
-        case g: h = 60 * ((b-r)/d + 2); break;
-        case b: h = 60 * ((r-g)/d + 4); break;
-      }
-    }
-    if (h < 0) h += 360;
-    const s = max === 0 ? 0 : d / max;
-    const v = max;
-    return { h, s, v };
-  }
-  function rgbToHex(r,g,b){ return '#' + [r,g,b].map(x => x.toString(16).padStart(2,'0')).join(''); }
-  function hexToRgb(hex){ const m = /^#?([\da-f]{2})([\da-f]{2})([\da-f]{2})$/i.exec(hex); if(!m) return {r:255,g:215,b:0}; return { r: parseInt(m[1],16), g: parseInt(m[2],16), b: parseInt(m[3],16) }; }
-  function drawColorWheel(canvas){
-    const ctx = canvas.getContext('2d');
-    const w = canvas.width, h = canvas.height;
-    const R = Math.min(w,h)/2;
-    const img = ctx.createImageData(w,h);
-    const data = img.data;
-    for(let y=0;y<h;y++){
-      for(let x=0;x<w;x++){
-        const dx = x - R;
-        const dy = y - R;
-        const d = Math.sqrt(dx*dx + dy*dy);
-        const idx = (y*w + x)*4;
-        if(d > R){ data[idx+3] = 0; continue; }
-        const ang = Math.atan2(dy, dx);
-        const hue = (ang * 180/Math.PI + 360) % 360;
-        const sat = d / R;
-        const [r,g,b] = hsvToRgb(hue, sat, 1);
-        data[idx] = r; data[idx+1] = g; data[idx+2] = b; data[idx+3] = 255;
-      }
-    }
-    ctx.putImageData(img, 0, 0);
-  }
-  function initColorWheel(){
-    const canvas = document.getElementById('wheelCanvas');
-    const hidden = document.getElementById('color');
-    const dot = document.getElementById('pickerDot');
-    const preview = document.getElementById('colorPreview');
-    if(!canvas || !hidden || !dot) return;
-    drawColorWheel(canvas);
-    const R = canvas.width/2;
-    function moveDotTo(hex){
-      const {r,g,b} = hexToRgb(hex);
-      const hsv = rgbToHsv(r,g,b);
-      const rad = hsv.s * R;
-      const x = R + rad * Math.cos(hsv.h * Math.PI/180);
-      const y = R + rad * Math.sin(hsv.h * Math.PI/180);
-      dot.style.left = (x - 6) + 'px';
-      dot.style.top = (y - 6) + 'px';
-      if(preview) preview.style.background = hex;
-    }
-    function pickAtPoint(x, y){
-      const rect = canvas.getBoundingClientRect();
-      const cx = x - rect.left; const cy = y - rect.top;
-      const dx = cx - R; const dy = cy - R;
-      const dist = Math.sqrt(dx*dx + dy*dy);
-      if(dist > R) return;
-      const d = canvas.getContext('2d').getImageData(Math.floor(cx), Math.floor(cy), 1, 1).data;
-      if(d[3] === 0) return;
-      const hex = rgbToHex(d[0], d[1], d[2]);
-      hidden.value = hex;
-      if(preview) preview.style.background = hex;
-      dot.style.left = (cx - 6) + 'px';
-      dot.style.top = (cy - 6) + 'px';
-    }
-    let dragging = false;
-    canvas.addEventListener('mousedown', (e)=>{ dragging=true; pickAtPoint(e.clientX, e.clientY); });
-    window.addEventListener('mousemove', (e)=>{ if(dragging) pickAtPoint(e.clientX, e.clientY); });
-    window.addEventListener('mouseup', ()=> dragging=false);
-    canvas.addEventListener('touchstart', (e)=>{ dragging=true; const t=e.touches[0]; pickAtPoint(t.clientX, t.clientY); e.preventDefault(); }, {passive:false});
-    window.addEventListener('touchmove', (e)=>{ if(dragging){ const t=e.touches[0]; pickAtPoint(t.clientX, t.clientY);} }, {passive:false});
-    window.addEventListener('touchend', ()=> dragging=false);
-    moveDotTo(hidden.value || '#ffd700');
-  }
-  async function refreshQueue(){
-    const ul = document.getElementById('queueList');
-    if(!ul) return;
-    const r = await fetch('/api/queue', { cache: 'no-store' });
-    const data = await r.json();
-    ul.innerHTML = '';
-    if(!data.current && (!data.items || data.items.length===0)){
-      const li = document.createElement('li'); li.className='queue-empty'; li.textContent= tr('queue_empty') || 'Geen berichten in de wachtrij…'; ul.appendChild(li); return;
-    }
-    const renderItem = (item, isCurrent, elapsed) => {
-      const li = document.createElement('li'); li.className='queue-item'+(isCurrent?' current':'');
-      const sw = document.createElement('span'); sw.className='swatch'; sw.style.background = item.color || '#ffd700'; li.appendChild(sw);
-      const text = document.createElement('span'); text.className='text'; text.textContent=item.text; li.appendChild(text);
-      if(isCurrent){ const t = document.createElement('span'); t.className='timer'; const s = Math.max(0, Math.min(60, Math.floor(elapsed||0))); t.textContent = '⏱ ' + String(s).padStart(2,'0')+'s'; t.setAttribute('data-elapsed', String(s)); li.appendChild(t); }
-      return li;
-    };
-    if(data.current){ ul.appendChild(renderItem(data.current, true, data.elapsed_seconds)); }
-    for(const it of (data.items||[])){ ul.appendChild(renderItem(it, false)); }
-  }
-
-  function tickTimer(){
-    const t = document.querySelector('.queue-item.current .timer');
-    if(!t) return; let s = parseInt(t.getAttribute('data-elapsed')||'0',10); s = Math.min(60, s+1); t.setAttribute('data-elapsed', String(s)); t.textContent='⏱ ' + String(s).padStart(2,'0')+'s';
-  }
-
-  function boot(){
-    initColorWheel();
-    // lang selector
-    const sel = document.getElementById('langSelector');
-    if(sel){ sel.addEventListener('click', (e)=>{ const btn = e.target.closest('button[data-lang]'); if(btn){ setLang(btn.getAttribute('data-lang')); }}); }
-    applyTranslations();
-    markActiveLang();
-    refreshQueue();
-    setInterval(()=>{ refreshQueue().catch(()=>{}); }, 5000);
-    setInterval(tickTimer, 1000);
-  }
-
-  if (document.readyState !== 'loading') boot(); else window.addEventListener('DOMContentLoaded', boot);
-})();"#;
-*/
-
 async fn app_js() -> impl IntoResponse {
     let js: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/app.js"));
     (
@@ -408,62 +523,200 @@ async fn app_js() -> impl IntoResponse {
 struct MessageForm {
     text: String,
     color: Option<String>, // #rrggbb
+    target: Option<String>,
+    // optional signature; guests with the same name take turns together
+    name: Option<String>,
+}
+
+impl MessageForm {
+    /// Validates the form into a message from the browser session `owner` for target `idx`.
+    async fn into_message(
+        self,
+        state: &AppState,
+        idx: usize,
+        owner: String,
+    ) -> Result<QueuedMessage, MessageError> {
+        let text = message_text(&self.text, state.max_text_len(idx).await)?;
+        let limits = state.settings.get();
+        let name = self
+            .name
+            .map(|n| n.trim().to_string())
+            .filter(|n| !n.is_empty());
+        if name.as_ref().is_some_and(|n| n.len() > limits.max_name_len) {
+            return Err(MessageError::InvalidName);
+        }
+        let weight = name
+            .as_ref()
+            .and_then(|n| state.weights.get(&n.to_lowercase()).copied())
+            .unwrap_or(1);
+        let sender = match &name {
+            Some(n) => format!("name:{}", n.to_lowercase()),
+            None => format!("session:{owner}"),
+        };
+        Ok(QueuedMessage {
+            id: state.next_id.fetch_add(1, Ordering::Relaxed),
+            text,
+            color: self.color,
+            sender,
+            name,
+            owner,
+            weight,
+            attempts: 0,
+            queued_at: None,
+        })
+    }
+}
+
+/// Why a message didn't get in; the message is the 400's body.
+#[derive(Debug, thiserror::Error)]
+enum MessageError {
+    #[error("Unknown target")]
+    UnknownTarget,
+    #[error("Invalid text")]
+    InvalidText,
+    #[error("Invalid name")]
+    InvalidName,
+}
+
+impl MessageError {
+    /// Label for the rejection metric and the audit log.
+    fn reason(&self) -> &'static str {
+        match self {
+            MessageError::UnknownTarget => "unknown_target",
+            MessageError::InvalidText => "invalid_text",
+            MessageError::InvalidName => "invalid_name",
+        }
+    }
+}
+
+/// A 400 for a message that didn't get in, counted and logged by reason.
+fn reject(state: &AppState, by: &Actor, e: MessageError) -> axum::response::Response {
+    let reason = e.reason();
+    metrics::rejected(reason);
+    state.audit.record(
+        Action::Reject,
+        by,
+        None,
+        None,
+        serde_json::json!({ "reason": reason }),
+    );
+    (StatusCode::BAD_REQUEST, e.to_string()).into_response()
+}
+
+/// Trimmed message text, or why it can't be shown.
+fn message_text(text: &str, max_len: usize) -> Result<String, MessageError> {
+    let text = text.trim();
+    if text.is_empty() || text.len() > max_len {
+        return Err(MessageError::InvalidText);
+    }
+    Ok(text.to_string())
 }
 
 async fn send_message(
     State(state): State<AppState>,
+    headers: HeaderMap,
+    client: Client,
     Form(form): Form<MessageForm>,
 ) -> impl IntoResponse {
-    let text = form.text.trim().to_string();
-    if text.is_empty() || text.len() > 128 {
-        return (StatusCode::BAD_REQUEST, "Invalid text").into_response();
-    }
-
-    let id = state.next_id.fetch_add(1, Ordering::Relaxed);
-
-    // If only the last item is showing and it already ran 60s, jump to the new one immediately
-    let mut switched_now = false;
-    {
-        let mut cur = state.current.lock().await;
-        if let Some(ref display) = *cur {
-            if display.started.elapsed() >= Duration::from_secs(60) {
-                let new_disp = CurrentDisplay {
-                    id,
-                    text: text.clone(),
-                    color: form.color.clone(),
-                    started: Instant::now(),
-                };
-                *cur = Some(new_disp.clone());
-                switched_now = true;
-                drop(cur);
-                if let Err(e) =
-                    apply_display(&state, &new_disp.text, new_disp.color.as_deref()).await
-                {
-                    error!(?e, "apply_display failed");
-                }
-            }
-        }
+    let Some(idx) = state.target_index(form.target.as_deref()) else {
+        return reject(&state, &client.guest(None), MessageError::UnknownTarget);
+    };
+    let token = session::token(&headers);
+    let new_session = token.is_none();
+    let token = token.unwrap_or_else(session::new_token);
+    let actor = client.guest(Some(&token));
+    let msg = match form.into_message(&state, idx, token.clone()).await {
+        Ok(msg) => msg,
+        Err(e) => return reject(&state, &actor, e),
+    };
+    metrics::submitted("guest");
+    let id = msg.id;
+    record_submit(&state, &actor, idx, &msg);
+    let rotation = state.rotation(idx);
+    let outcome = rotation.enqueue(msg).await;
+    // the actor publishes before replying, so the message is in this snapshot
+    let snapshot = rotation.snapshot();
+    let (position, eta) = match snapshot.queue.iter().position(|m| m.id == id) {
+        Some(pos) => (pos + 1, snapshot.eta(pos).map(|d| d.as_secs())),
+        None => (0, Some(0)),
+    };
+    let body = serde_json::json!({
+        "id": id,
+        "status": match outcome {
+            Enqueued::Switched => "switched",
+            Enqueued::Queued => "queued",
+        },
+        "target": state.targets[state.rotation_owner(idx)].cfg.name,
+        // 0 while on screen, 1 when next
+        "position": position,
+        "eta_seconds": eta,
+    });
+    let mut res = axum::Json(body).into_response();
+    if new_session {
+        res.headers_mut()
+            .insert(header::SET_COOKIE, session::set_cookie(&token));
     }
+    res
+}
 
-    if !switched_now {
-        // Enqueue for rotation
-        let mut q = state.queue.lock().await;
-        q.push_back(QueuedMessage {
-            id,
-            text,
-            color: form.color,
-        });
-    }
+#[derive(Deserialize)]
+struct AdminMessageForm {
+    text: String,
+    color: Option<String>,
+    target: Option<String>,
+    name: Option<String>,
+    weight: Option<u32>,
+}
+
+/// Messages from the admin page, e.g. for the couple or the best man. They all count as one
+/// sender, with `weight` turns (default [`ADMIN_WEIGHT`]) under the weighted policy.
+async fn admin_message(
+    State(state): State<AppState>,
+    client: Client,
+    Form(f): Form<AdminMessageForm>,
+) -> impl IntoResponse {
+    let actor = client.admin();
+    let Some(idx) = state.target_index(f.target.as_deref()) else {
+        return reject(&state, &actor, MessageError::UnknownTarget);
+    };
+    let form = MessageForm {
+        text: f.text,
+        color: f.color,
+        target: None,
+        name: f.name,
+    };
+    let mut msg = match form.into_message(&state, idx, String::new()).await {
+        Ok(msg) => msg,
+        Err(e) => return reject(&state, &actor, e),
+    };
+    metrics::submitted("admin");
+    msg.sender = "admin".into();
+    msg.weight = f.weight.unwrap_or(ADMIN_WEIGHT).max(1);
+    record_submit(&state, &actor, idx, &msg);
+    let outcome = state.rotation(idx).enqueue(msg).await;
     (
         StatusCode::OK,
-        if switched_now { "switched" } else { "queued" },
+        match outcome {
+            Enqueued::Switched => "switched",
+            Enqueued::Queued => "queued",
+        },
     )
         .into_response()
 }
 
+fn record_submit(state: &AppState, by: &Actor, idx: usize, msg: &QueuedMessage) {
+    state.audit.record(
+        Action::Submit,
+        by,
+        Some(msg.id),
+        Some(&state.targets[state.rotation_owner(idx)].cfg.name),
+        serde_json::json!({ "text": msg.text, "name": msg.name }),
+    );
+}
+
 // Upload endpoint removed
 
-fn parse_hex_color(s: &str) -> Option<(u8, u8, u8)> {
+pub(crate) fn parse_hex_color(s: &str) -> Option<(u8, u8, u8)> {
     let s = s.strip_prefix('#').unwrap_or(s);
     if s.len() != 6 {
         return None;
@@ -474,45 +727,68 @@ fn parse_hex_color(s: &str) -> Option<(u8, u8, u8)> {
     Some((r, g, b))
 }
 
-async fn supervise_tunnel(state: AppState) {
+/// Keeps the tunnel up (SSH transport only) and tracks whether the device is reachable.
+async fn supervise_device(state: AppState, idx: usize) {
+    let target = &state.targets[idx];
     loop {
-        if let Err(e) = ensure_tunnel(&state).await {
-            error!(?e, "ssh tunnel error");
-            time::sleep(Duration::from_secs(5)).await;
-            continue;
+        if target.cfg.transport.uses_tunnel() {
+            if let Err(e) = ensure_tunnel(target).await {
+                error!(?e, target = %target.cfg.name, "ssh tunnel error");
+                target.online.set(false);
+                time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
         }
-        // Keep a light heartbeat to WLED through the tunnel
-        let base = format!("http://127.0.0.1:{}", state.cfg.local_tunnel_port);
-        let res = state.client.get(format!("{}/json", base)).send().await;
-        match res {
-            Ok(_) => time::sleep(Duration::from_secs(10)).await,
-            Err(_) => time::sleep(Duration::from_secs(2)).await,
+        // Keep a light heartbeat to WLED
+        let info = target.wled.info().await.ok();
+        let ok = info.is_some();
+        let was_online = target.online.set(ok);
+        if ok != was_online {
+            info!(target = %target.cfg.name, online = ok, "WLED reachability changed");
+        }
+        if let Some(info) = info {
+            // a reconnect may mean a reboot into a different build, so always rediscover then
+            let res = if was_online {
+                target.caps.observe(&target.wled, info).await
+            } else {
+                target.caps.discover(&target.wled).await.map(|_| ())
+            };
+            if let Err(e) = res {
+                error!(?e, target = %target.cfg.name, "capability discovery failed");
+            }
+        }
+        match ok {
+            true => time::sleep(Duration::from_secs(10)).await,
+            false => time::sleep(Duration::from_secs(2)).await,
         }
     }
 }
 
-async fn ensure_tunnel(state: &AppState) -> anyhow::Result<()> {
-    let _g = state.tunnel_lock.lock().await; // serialize restarts
-                                             // quick probe if local port already responds
-    let base = format!("http://127.0.0.1:{}", state.cfg.local_tunnel_port);
-    if state.client.get(format!("{}/", base)).send().await.is_ok() {
+async fn ensure_tunnel(target: &Target) -> anyhow::Result<()> {
+    let mut tunnel = target.tunnel.lock().await;
+    // quick probe if local port already responds
+    let cfg = &target.cfg;
+    if target.wled.get("/").await.is_ok() {
         return Ok(());
     }
 
     // Start ssh -NT -L 127.0.0.1:<local>:<wled_host>:<wled_port> <ssh_target>
     let mut target = String::new();
-    if let Some(user) = &state.cfg.ssh_user {
+    if let Some(user) = &cfg.ssh_user {
         target.push_str(user);
         target.push('@');
     }
-    target.push_str(&state.cfg.ssh_host);
+    target.push_str(&cfg.ssh_host);
 
     let forward = format!(
         "127.0.0.1:{}:{}:{}",
-        state.cfg.local_tunnel_port, state.cfg.wled_host, state.cfg.wled_port
+        cfg.local_tunnel_port, cfg.wled_host, cfg.wled_port
     );
 
-    info!("starting ssh tunnel to {} forwarding {}", target, forward);
+    info!(
+        "starting ssh tunnel for {} to {} forwarding {}",
+        cfg.name, target, forward
+    );
     let mut cmd = Command::new("ssh");
     cmd.arg("-NT")
         .arg("-o")
@@ -525,274 +801,201 @@ async fn ensure_tunnel(state: &AppState) -> anyhow::Result<()> {
         .arg(forward)
         .arg(target)
         .stdout(Stdio::null())
-        .stderr(Stdio::null());
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
 
-    // launch in background and give it a moment
-    let _child = cmd.spawn()?; // intentionally not awaited; relies on autossh-like keepalive via supervise
+    // launch in background and give it a moment; a previous ssh that stopped forwarding is
+    // killed when replaced
+    *tunnel = Some(cmd.spawn()?);
+    metrics::tunnel_restart(&cfg.name);
     time::sleep(Duration::from_millis(400)).await;
     Ok(())
 }
 
 // no-op
 
-#[cfg(feature = "acme")]
-async fn serve_with_acme(
-    app: Router,
-    domain: String,
-    contact: Option<String>,
-    cache_dir: String,
-) -> anyhow::Result<()> {
-    use axum::{http::Uri, response::IntoResponse};
-    use axum_server::Handle;
-    use rustls_acme::{caches::DirCache, AcmeConfig};
-
-    // Configure Let's Encrypt (use TLS-ALPN-01 on 443 and redirect 80->443)
-    let mut cfg = AcmeConfig::new(vec![domain.clone()])
-        .cache(DirCache::new(cache_dir))
-        .directory_lets_encrypt(true)
-        .ocsp(true);
-    if let Some(c) = contact {
-        cfg = cfg.contact_push(format!("mailto:{}", c));
-    }
-
-    let mut state = cfg.state();
-    tokio::spawn(async move {
-        loop {
-            if let Err(e) = state.execute().await {
-                error!(?e, "acme state error");
-                time::sleep(Duration::from_secs(5)).await;
+/// Pushes a display to the rotation owner and every target mirroring it.
+///
+/// Returns how it went on the owner; a target that can't be reached is marked offline, one that
+/// answers with another state stays online. Mirrors are best effort.
+async fn show_display(state: &AppState, owner: usize, display: &CurrentDisplay) -> Shown {
+    let mut shown = Shown::Confirmed;
+    for (i, target) in state.display_targets(owner) {
+        let outcome = match apply_display(target, &display.text, display.color.as_deref()).await {
+            Ok(()) => continue,
+            Err(e) if e.is_unreachable() => {
+                error!(?e, target = %target.cfg.name, "apply_display failed");
+                target.online.set(false);
+                Shown::Unreachable
             }
-        }
-    });
-
-    let acceptor = rustls_acme::axum::AxumAcceptor::new(state);
-    let handle = Handle::new();
-
-    // HTTPS server
-    let https = axum_server::bind_acceptor((std::net::Ipv4Addr::UNSPECIFIED, 443).into(), acceptor)
-        .handle(handle.clone())
-        .serve(app.into_make_service());
-
-    // HTTP redirect server
-    let redir_app = Router::new().fallback(move |uri: Uri| {
-        let host = domain.clone();
-        async move {
-            let location = format!("https://{}{}", host, uri);
-            (
-                StatusCode::MOVED_PERMANENTLY,
-                [(header::LOCATION, location)],
-            )
-                .into_response()
-        }
-    });
-    let http = axum_server::bind((std::net::Ipv4Addr::UNSPECIFIED, 80).into())
-        .handle(handle.clone())
-        .serve(redir_app.into_make_service());
-
-    info!("ACME enabled; serving HTTPS for domain");
-    tokio::select! {
-        r = https => { r?; },
-        r = http => { r?; },
-    }
-    Ok(())
-}
-
-async fn rotation_worker(state: AppState) {
-    loop {
-        // If no current and something is queued, start it
-        {
-            let mut cur = state.current.lock().await;
-            if cur.is_none() {
-                let mut q = state.queue.lock().await;
-                if let Some(next) = q.pop_front() {
-                    let display = CurrentDisplay {
-                        id: next.id,
-                        text: next.text.clone(),
-                        color: next.color.clone(),
-                        started: Instant::now(),
-                    };
-                    *cur = Some(display.clone());
-                    drop(q);
-                    drop(cur);
-                    if let Err(e) =
-                        apply_display(&state, &display.text, display.color.as_deref()).await
-                    {
-                        error!(?e, "apply_display failed");
-                    }
-                }
-            }
-        }
-
-        // If current elapsed >= 60s, advance to next if present; otherwise keep displaying last
-        {
-            let mut maybe_new_display: Option<CurrentDisplay> = None;
-            {
-                let mut cur = state.current.lock().await;
-                if let Some(ref display) = *cur {
-                    if display.started.elapsed() >= Duration::from_secs(60) {
-                        let mut q = state.queue.lock().await;
-                        if let Some(next) = q.pop_front() {
-                            let new_disp = CurrentDisplay {
-                                id: next.id,
-                                text: next.text.clone(),
-                                color: next.color.clone(),
-                                started: Instant::now(),
-                            };
-                            *cur = Some(new_disp.clone());
-                            maybe_new_display = Some(new_disp);
-                        } else {
-                            // No next item; keep showing current as-is (the last item stays)
-                        }
-                    }
-                }
-            }
-            if let Some(d) = maybe_new_display {
-                if let Err(e) = apply_display(&state, &d.text, d.color.as_deref()).await {
-                    error!(?e, "apply_display failed");
-                }
+            Err(e) => {
+                warn!(?e, target = %target.cfg.name, "display not confirmed");
+                Shown::Differs
             }
+        };
+        if i == owner {
+            shown = outcome;
         }
-        time::sleep(Duration::from_millis(900)).await;
     }
+    shown
 }
 
-async fn apply_display(state: &AppState, text: &str, color: Option<&str>) -> anyhow::Result<()> {
-    if let Err(e) = ensure_tunnel(state).await {
-        error!(?e, "tunnel ensure failed");
+async fn apply_display(target: &Target, text: &str, color: Option<&str>) -> wled::Result<()> {
+    if target.cfg.transport.uses_tunnel() {
+        if let Err(e) = ensure_tunnel(target).await {
+            error!(?e, "tunnel ensure failed");
+        }
     }
-    let base = format!("http://127.0.0.1:{}", state.cfg.local_tunnel_port);
+    let wled = &target.wled;
+    let caps = target.caps.get(wled).await?;
+    // the device would cut it anyway; cut it ourselves so the read-back can be compared
+    let name = wled::truncate_name(text, caps.segment_name_len);
 
     // Ensure scrolling text effect is active first
     // If a preset is provided, switch to it (assumed to be the scrolling text preset).
     // Otherwise, pick the scrolling text effect index and include it in the next state update.
-    let mut fx_idx: Option<usize> = None;
-    if let Some(ps) = state.cfg.text_preset_id {
-        let _ = state
-            .client
-            .post(format!("{}/json/state", base))
-            .json(&serde_json::json!({"ps": ps}))
-            .send()
-            .await;
+    let (text_preset_id, bri) = {
+        let settings = target.settings.borrow();
+        (
+            settings.presets(&target.cfg.name).text_preset_id,
+            settings.brightness,
+        )
+    };
+    let mut fx_idx: Option<u16> = None;
+    if let Some(ps) = text_preset_id {
+        wled.set_state(&wled::StateUpdate {
+            ps: Some(ps),
+            ..Default::default()
+        })
+        .await?;
     } else {
-        fx_idx = find_text_effect_index(&state.client, &base).await;
+        fx_idx = caps.text_effect;
     }
 
     // Now apply color (as Color 1), select a palette that respects Color 1, and set the segment name to the message.
     // If effect index is known (no preset), set it alongside to ensure the effect is scrolling text.
     let (r, g, b) = color.and_then(parse_hex_color).unwrap_or((255, 215, 0));
-    let bri: u8 = 128;
-    // Force the effect's color mode to use Color 1 and set font size to max.
-    // WLED 0.14+: preferred is the consolidated options array `o: [o1, o2, ...]`.
-    // For Scrolling Text: o1 = color mode (0 = Color 1), o2 = font size (max 255).
-    // Keep legacy fields (o1/o2/c1) for compatibility.
-    let mut seg = serde_json::json!({
-        "id": 0,
-        "n": text,
-        "col": [[r, g, b]],
-        "o": [0, 255],
-        "o1": 0,
-        "c2": 255,
-        "c1": 0
-    });
-    if let Some(p) = find_color1_palette_index(&state.client, &base).await { seg["pal"] = serde_json::json!(p); }
-    if let Some(idx) = fx_idx {
-        seg["fx"] = serde_json::json!(idx);
-    }
-    let json_body = serde_json::json!({ "on": true, "bri": bri, "seg": [ seg ] });
-    let _ = state
-        .client
-        .post(format!("{}/json/state", base))
-        .json(&json_body)
-        .send()
-        .await;
+    let pal_idx = caps.color1_palette;
+    let seg = text_segment(&caps, name, (r, g, b), fx_idx);
+    let mut st = wled
+        .set_state(&wled::StateUpdate {
+            on: Some(true),
+            bri: Some(bri),
+            seg: vec![seg],
+            ..Default::default()
+        })
+        .await?;
 
     // Optional legacy text API
-    if let Some(key) = &state.cfg.text_param_key {
-        let url = format!("{}/win?{}={}", base, key, urlencoding::encode(text));
-        let _ = state.client.get(url).send().await;
+    if let Some(key) = &target.cfg.text_param_key {
+        let _ = wled.win(key, text).await;
+    }
+
+    // A preset is applied asynchronously and may still override the segment, so read back
+    if text_preset_id.is_some() {
+        time::sleep(Duration::from_millis(300)).await;
+        st = wled.state().await?;
+    }
+
+    let expected = wled::ExpectedDisplay {
+        bri,
+        text: name,
+        rgb: (r, g, b),
+        fx: fx_idx,
+        pal: pal_idx,
+        c1: 0,
+        c2: 255,
+        o1: false,
+    };
+    let mismatches = expected.mismatches(&st);
+    if !mismatches.is_empty() {
+        warn!(target = %target.cfg.name, ?mismatches, "WLED state differs from request");
+    }
+    *target.last_check.lock().await = Some(StateCheck {
+        at: Instant::now(),
+        text: text.to_string(),
+        mismatches,
+    });
+    // Only a missing text means the message didn't make it
+    if !expected.text_shown(&st) {
+        return Err(wled::WledError::NotConfirmed);
     }
     Ok(())
 }
 
-static TEXT_EFFECT_INDEX: once_cell::sync::OnceCell<usize> = once_cell::sync::OnceCell::new();
-async fn find_text_effect_index(client: &reqwest::Client, base: &str) -> Option<usize> {
-    if let Some(idx) = TEXT_EFFECT_INDEX.get() {
-        return Some(*idx);
-    }
-    let url = format!("{}/json/effects", base);
-    let res = client.get(url).send().await.ok()?;
-    let effects: serde_json::Value = res.json().await.ok()?;
-    let arr = effects.as_array()?;
-    let mut candidate: Option<usize> = None;
-    for (i, v) in arr.iter().enumerate() {
-        if let Some(name) = v.as_str() {
-            let lc = name.to_lowercase();
-            if lc.contains("scroll") && lc.contains("text") {
-                candidate = Some(i);
-                break;
-            }
-            if candidate.is_none() && lc.contains("text") {
-                candidate = Some(i);
-            }
-        }
-    }
-    if let Some(i) = candidate {
-        let _ = TEXT_EFFECT_INDEX.set(i);
-        return Some(i);
-    }
-    None
-}
-
-static COLOR1_PALETTE_INDEX: once_cell::sync::OnceCell<usize> = once_cell::sync::OnceCell::new();
-async fn find_color1_palette_index(client: &reqwest::Client, base: &str) -> Option<usize> {
-    if let Some(idx) = COLOR1_PALETTE_INDEX.get() { return Some(*idx); }
-    let url = format!("{}/json/palettes", base);
-    let res = client.get(url).send().await.ok()?;
-    let palettes: serde_json::Value = res.json().await.ok()?;
-    let arr = palettes.as_array()?;
-    let mut candidate: Option<usize> = None;
-    for (i, v) in arr.iter().enumerate() {
-        if let Some(name) = v.as_str() {
-            let lc = name.to_lowercase();
-            if lc.contains("primary") || lc.contains("color 1") || lc.contains("single") || lc.contains("solid") {
-                candidate = Some(i);
-                break;
-            }
-        }
+/// Segment 0 showing `text` in `rgb` with the scrolling text effect settings.
+fn text_segment(
+    caps: &Capabilities,
+    text: &str,
+    (r, g, b): (u8, u8, u8),
+    fx: Option<u16>,
+) -> wled::SegmentUpdate {
+    // Force the effect's color mode to use Color 1 and set font size to max.
+    // For Scrolling Text: o1 = color mode (0 = Color 1), o2 = font size (max 255).
+    wled::SegmentUpdate {
+        id: 0,
+        n: Some(text.to_string()),
+        col: Some(vec![[r, g, b]]),
+        fx,
+        pal: caps.color1_palette,
+        c1: Some(0),
+        c2: Some(255),
+        ..Default::default()
     }
-    if let Some(i) = candidate { let _ = COLOR1_PALETTE_INDEX.set(i); return Some(i); }
-    None
+    // both option encodings are sent when the firmware version is unknown
+    .with_options(&[0, 255], caps.version)
+}
+
+#[derive(Deserialize)]
+struct TargetQuery {
+    target: Option<String>,
 }
 
-async fn get_queue(State(state): State<AppState>) -> impl IntoResponse {
-    let cur = state.current.lock().await;
-    let (current, elapsed) = if let Some(ref c) = *cur {
+async fn get_queue(
+    State(state): State<AppState>,
+    Query(tq): Query<TargetQuery>,
+) -> impl IntoResponse {
+    let Some(idx) = state.target_index(tq.target.as_deref()) else {
+        return (StatusCode::NOT_FOUND, "Unknown target").into_response();
+    };
+    let snapshot = state.rotation(idx).snapshot();
+    let (current, elapsed) = if let Some(ref c) = snapshot.current {
         (
             Some(serde_json::json!({
                 "id": c.id,
                 "text": c.text,
                 "color": c.color,
+                "name": c.name,
             })),
-            c.started.elapsed().as_secs(),
+            c.elapsed().as_secs(),
         )
     } else {
         (None, 0)
     };
-    drop(cur);
-    let q = state.queue.lock().await;
-    let items: Vec<_> = q
+    let paused = snapshot.current.as_ref().is_some_and(|c| c.is_paused());
+    let items: Vec<_> = snapshot
+        .queue
         .iter()
-        .map(|m| {
+        .enumerate()
+        .map(|(pos, m)| {
             serde_json::json!({
                 "id": m.id,
                 "text": m.text,
                 "color": m.color,
+                "name": m.name,
+                // seconds until it is expected on screen; null while the rotation is stopped
+                "eta_seconds": snapshot.eta(pos).map(|d| d.as_secs()),
             })
         })
         .collect();
     let body = serde_json::json!({
+        "target": state.targets[idx].cfg.name,
         "current": current,
         "elapsed_seconds": elapsed,
+        "paused": paused,
+        "held": snapshot.held,
+        "policy": state.policy.as_str(),
+        "online": state.targets[state.rotation_owner(idx)].online.get(),
         "items": items,
     });
     (
@@ -803,6 +1006,41 @@ async fn get_queue(State(state): State<AppState>) -> impl IntoResponse {
         ],
         body.to_string(),
     )
+        .into_response()
+}
+
+async fn get_targets(State(state): State<AppState>) -> impl IntoResponse {
+    let mut items = Vec::with_capacity(state.targets.len());
+    for (i, t) in state.targets.iter().enumerate() {
+        let mut item = serde_json::json!({
+            "name": t.cfg.name,
+            "mode": if i == 0 { "main" } else { t.cfg.mode.as_str() },
+            "rotation": state.targets[state.rotation_owner(i)].cfg.name,
+        });
+        if let Some(link) = t.wled.agent_link() {
+            item["agent"] = link.status().await.to_json();
+        }
+        items.push(item);
+    }
+    (
+        [(header::CACHE_CONTROL, "no-store, max-age=0")],
+        axum::Json(items),
+    )
+}
+
+async fn preview_js() -> impl IntoResponse {
+    let js: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/preview.js"));
+    (
+        [
+            (
+                header::CONTENT_TYPE,
+                "application/javascript; charset=utf-8",
+            ),
+            (header::CACHE_CONTROL, "no-store, max-age=0"),
+            (header::PRAGMA, "no-cache"),
+        ],
+        js,
+    )
 }
 
 async fn admin_page() -> impl IntoResponse {
@@ -838,21 +1076,279 @@ struct RemoveForm {
 
 async fn admin_remove(
     State(state): State<AppState>,
+    client: Client,
     Form(f): Form<RemoveForm>,
 ) -> impl IntoResponse {
-    // Remove from queue
-    {
-        let mut q = state.queue.lock().await;
-        q.retain(|m| m.id != f.id);
-    }
-    // If removing current, clear it
-    {
-        let mut cur = state.current.lock().await;
-        if let Some(c) = cur.as_ref() {
-            if c.id == f.id {
-                *cur = None;
-            }
+    // Ids are unique across targets, so check every rotation
+    for target in state.targets.iter() {
+        let Some(rotation) = &target.rotation else {
+            continue;
+        };
+        if rotation.remove(f.id).await {
+            state.audit.record(
+                Action::Remove,
+                &client.admin(),
+                Some(f.id),
+                Some(&target.cfg.name),
+                serde_json::Value::Null,
+            );
+            return (StatusCode::OK, "ok");
+        }
+    }
+    (StatusCode::NOT_FOUND, "Not in any queue")
+}
+
+/// WebSocket endpoint that onsite agents dial into.
+async fn agent_ws(
+    State(state): State<AppState>,
+    Query(tq): Query<TargetQuery>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse {
+    let Some(expected) = state.agent_token.as_deref() else {
+        return (StatusCode::NOT_FOUND, "Agent mode disabled").into_response();
+    };
+    let presented = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if presented != Some(expected) {
+        return (StatusCode::UNAUTHORIZED, "Invalid agent token").into_response();
+    }
+    let link = state
+        .target_index(tq.target.as_deref())
+        .and_then(|i| state.targets[i].wled.agent_link().cloned());
+    let Some(link) = link else {
+        return (StatusCode::NOT_FOUND, "No agent target with that name").into_response();
+    };
+    ws.on_upgrade(move |socket| async move {
+        info!("onsite agent connected");
+        link.serve(socket).await;
+        info!("onsite agent disconnected");
+    })
+    .into_response()
+}
+
+/// Per-target health: reachability, agent link and the last state verification.
+async fn get_status(State(state): State<AppState>) -> impl IntoResponse {
+    let mut targets = Vec::with_capacity(state.targets.len());
+    for t in state.targets.iter() {
+        let check = t.last_check.lock().await.clone();
+        let caps = t.caps.peek().await;
+        let mut item = serde_json::json!({
+            "name": t.cfg.name,
+            "transport": t.cfg.transport.to_string(),
+            "online": t.online.get(),
+            "last_check": check.map(|c| serde_json::json!({
+                "seconds_ago": c.at.elapsed().as_secs(),
+                "text": c.text,
+                "ok": c.mismatches.is_empty(),
+                "mismatches": c.mismatches,
+            })),
+            "capabilities": caps.map(|c| {
+                let mut v = serde_json::json!(c);
+                v["discovered_seconds_ago"] = c.discovered.elapsed().as_secs().into();
+                v
+            }),
+        });
+        if let Some(link) = t.wled.agent_link() {
+            item["agent"] = link.status().await.to_json();
         }
+        if let Some(socket) = t.wled.socket() {
+            item["websocket"] = socket.status().await.to_json();
+        }
+        targets.push(item);
+    }
+    (
+        [(header::CACHE_CONTROL, "no-store, max-age=0")],
+        axum::Json(serde_json::json!({ "targets": targets, "tls": state.tls.to_json() })),
+    )
+}
+
+/// Everything the typed WLED client can read from a target's device, for troubleshooting.
+async fn admin_device(
+    State(state): State<AppState>,
+    Query(tq): Query<TargetQuery>,
+) -> impl IntoResponse {
+    let Some(idx) = state.target_index(tq.target.as_deref()) else {
+        return (StatusCode::NOT_FOUND, "Unknown target").into_response();
+    };
+    let mut body = match device_report(&state.targets[idx].wled).await {
+        Ok(body) => body,
+        Err(e) => return (StatusCode::BAD_GATEWAY, e.to_string()).into_response(),
+    };
+    body["target"] = state.targets[idx].cfg.name.clone().into();
+    (
+        [(header::CACHE_CONTROL, "no-store, max-age=0")],
+        axum::Json(body),
+    )
+        .into_response()
+}
+
+/// Info, state, effects, palettes and presets of a device; only the info is required.
+async fn device_report(wled: &WledClient) -> wled::Result<serde_json::Value> {
+    let info = wled.info().await?;
+    let effects = wled.effects().await.ok();
+    let fxdata = wled.fxdata().await.ok();
+    let text_fx = effects.as_ref().and_then(|e| e.scrolling_text());
+    let palettes = wled.palettes().await.ok();
+    Ok(serde_json::json!({
+        "version": info.version().map(|v| v.to_string()),
+        "info": info,
+        "state": wled.state().await.ok(),
+        "text_effect": text_fx.map(|i| serde_json::json!({
+            "index": i,
+            "fxdata": fxdata.as_ref().and_then(|d| d.get(usize::from(i))),
+        })),
+        "color1_palette": palettes.as_ref().and_then(|p| p.color1()),
+        "effects": effects,
+        "palettes": palettes,
+        "presets": wled.presets().await.ok(),
+    }))
+}
+
+#[derive(Deserialize)]
+struct TargetForm {
+    target: Option<String>,
+}
+
+/// Ends the current message early in favour of the next one in the queue.
+async fn admin_skip(
+    State(state): State<AppState>,
+    client: Client,
+    Form(f): Form<TargetForm>,
+) -> impl IntoResponse {
+    let Some(idx) = state.target_index(f.target.as_deref()) else {
+        return (StatusCode::NOT_FOUND, "Unknown target").into_response();
+    };
+    let rotation = state.rotation(idx);
+    let current = rotation.snapshot().current.map(|c| c.id);
+    if rotation.skip().await {
+        state.audit.record(
+            Action::Skip,
+            &client.admin(),
+            current,
+            Some(&state.targets[state.rotation_owner(idx)].cfg.name),
+            serde_json::Value::Null,
+        );
+        (StatusCode::OK, "ok").into_response()
+    } else {
+        (StatusCode::CONFLICT, "Nothing waiting").into_response()
+    }
+}
+
+#[derive(Deserialize)]
+struct HoldForm {
+    target: Option<String>,
+    held: bool,
+}
+
+/// Stops or restarts the rotation; the message on screen stays up meanwhile.
+async fn admin_hold(
+    State(state): State<AppState>,
+    client: Client,
+    Form(f): Form<HoldForm>,
+) -> impl IntoResponse {
+    let Some(idx) = state.target_index(f.target.as_deref()) else {
+        return (StatusCode::NOT_FOUND, "Unknown target").into_response();
+    };
+    state.rotation(idx).hold(f.held).await;
+    state.audit.record(
+        Action::Hold,
+        &client.admin(),
+        None,
+        Some(&state.targets[state.rotation_owner(idx)].cfg.name),
+        serde_json::json!({ "held": f.held }),
+    );
+    (StatusCode::OK, "ok").into_response()
+}
+
+/// Empties the queue of the target's rotation; the message on screen stays up.
+async fn admin_clear(
+    State(state): State<AppState>,
+    client: Client,
+    Form(f): Form<TargetForm>,
+) -> impl IntoResponse {
+    let Some(idx) = state.target_index(f.target.as_deref()) else {
+        return (StatusCode::NOT_FOUND, "Unknown target").into_response();
+    };
+    let dropped = state.rotation(idx).clear().await;
+    let actor = client.admin();
+    for id in &dropped {
+        state.audit.record(
+            Action::Remove,
+            &actor,
+            Some(*id),
+            Some(&state.targets[state.rotation_owner(idx)].cfg.name),
+            serde_json::json!({ "via": "clear" }),
+        );
+    }
+    axum::Json(serde_json::json!({ "removed": dropped.len() })).into_response()
+}
+
+/// A message as [`admin_export`] lists it.
+fn exported(
+    id: u64,
+    text: &str,
+    color: &Option<String>,
+    name: &Option<String>,
+) -> serde_json::Value {
+    serde_json::json!({
+        "id": id,
+        "text": text,
+        "color": color,
+        "name": name,
+    })
+}
+
+/// Everything the app holds as one JSON document: per rotation what is on screen and waiting,
+/// the runtime settings and the access codes. Session tokens are left out.
+async fn admin_export(State(state): State<AppState>) -> impl IntoResponse {
+    let mut rotations = Vec::new();
+    for target in state.targets.iter() {
+        let Some(rotation) = &target.rotation else {
+            continue;
+        };
+        let snapshot = rotation.snapshot();
+        rotations.push(serde_json::json!({
+            "target": target.cfg.name,
+            "held": snapshot.held,
+            "current": snapshot
+                .current
+                .as_ref()
+                .map(|c| exported(c.id, &c.text, &c.color, &c.name)),
+            "queue": snapshot
+                .queue
+                .iter()
+                .map(|m| exported(m.id, &m.text, &m.color, &m.name))
+                .collect::<Vec<_>>(),
+        }));
+    }
+    let exported_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    (
+        [(header::CACHE_CONTROL, "no-store, max-age=0")],
+        axum::Json(serde_json::json!({
+            "exported_at": exported_at,
+            "rotations": rotations,
+            "settings": state.settings.get(),
+            "access_codes": state.access.all().await,
+        })),
+    )
+}
+
+/// Forces a fresh capability discovery, e.g. after reflashing WLED.
+async fn admin_rediscover(
+    State(state): State<AppState>,
+    Form(f): Form<TargetForm>,
+) -> impl IntoResponse {
+    let Some(idx) = state.target_index(f.target.as_deref()) else {
+        return (StatusCode::NOT_FOUND, "Unknown target").into_response();
+    };
+    let target = &state.targets[idx];
+    match target.caps.discover(&target.wled).await {
+        Ok(caps) => axum::Json(caps).into_response(),
+        Err(e) => (StatusCode::BAD_GATEWAY, e.to_string()).into_response(),
     }
-    (StatusCode::OK, "ok")
 }
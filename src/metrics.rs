@@ -0,0 +1,202 @@
+//! Prometheus metrics at `GET /metrics`: how many messages came in, were turned away, shown and
+//! removed, how long guests waited, and how the devices and tunnels held up.
+//!
+//! The counters live in one process-wide registry, so the handlers, the rotation actors and the
+//! WLED client can count without passing anything around.
+
+use std::{
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+
+use axum::{http::header, response::IntoResponse};
+use prometheus_client::{
+    encoding::text::encode,
+    metrics::{counter::Counter, family::Family, gauge::Gauge, histogram::Histogram},
+    registry::Registry,
+};
+
+type Labels = Vec<(&'static str, String)>;
+type HistogramFamily = Family<Labels, Histogram, fn() -> Histogram>;
+
+pub struct Metrics {
+    registry: Registry,
+    started: Instant,
+    uptime: Gauge,
+    submitted: Family<Labels, Counter>,
+    rejected: Family<Labels, Counter>,
+    displayed: Family<Labels, Counter>,
+    removed: Family<Labels, Counter>,
+    queue_depth: Family<Labels, Gauge>,
+    wait: HistogramFamily,
+    tunnel_restarts: Family<Labels, Counter>,
+    wled_latency: HistogramFamily,
+    wled_errors: Family<Labels, Counter>,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+fn wait_histogram() -> Histogram {
+    // a minute per message: from "right away" to an hour's queue
+    Histogram::new([5.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1200.0, 3600.0])
+}
+
+fn latency_histogram() -> Histogram {
+    Histogram::new([0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0])
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let mut m = Metrics {
+            registry: Registry::with_prefix("gordijn"),
+            started: Instant::now(),
+            uptime: Gauge::default(),
+            submitted: Family::default(),
+            rejected: Family::default(),
+            displayed: Family::default(),
+            removed: Family::default(),
+            queue_depth: Family::default(),
+            wait: Family::new_with_constructor(wait_histogram),
+            tunnel_restarts: Family::default(),
+            wled_latency: Family::new_with_constructor(latency_histogram),
+            wled_errors: Family::default(),
+        };
+        let r = &mut m.registry;
+        r.register(
+            "uptime_seconds",
+            "Seconds since the app started",
+            m.uptime.clone(),
+        );
+        r.register(
+            "messages_submitted",
+            "Messages accepted into a queue, by source (guest, admin)",
+            m.submitted.clone(),
+        );
+        r.register(
+            "messages_rejected",
+            "Messages turned away, by reason",
+            m.rejected.clone(),
+        );
+        r.register(
+            "messages_displayed",
+            "Messages confirmed on a display, by rotation",
+            m.displayed.clone(),
+        );
+        r.register(
+            "messages_removed",
+            "Messages taken out before or while showing, by who (admin, guest, clear)",
+            m.removed.clone(),
+        );
+        r.register(
+            "queue_depth",
+            "Messages waiting, by rotation",
+            m.queue_depth.clone(),
+        );
+        r.register(
+            "queue_wait_seconds",
+            "Time from submission until on screen, by rotation",
+            m.wait.clone(),
+        );
+        r.register(
+            "tunnel_restarts",
+            "SSH tunnels started, by target",
+            m.tunnel_restarts.clone(),
+        );
+        r.register(
+            "wled_request_duration_seconds",
+            "WLED request latency, by endpoint",
+            m.wled_latency.clone(),
+        );
+        r.register(
+            "wled_request_errors",
+            "Failed WLED requests, by endpoint",
+            m.wled_errors.clone(),
+        );
+        m
+    }
+}
+
+fn label(key: &'static str, value: &str) -> Labels {
+    vec![(key, value.to_string())]
+}
+
+pub fn submitted(source: &str) {
+    METRICS
+        .submitted
+        .get_or_create(&label("source", source))
+        .inc();
+}
+
+/// `reason` is a short slug, e.g. `invalid_text` or `no_access_code`.
+pub fn rejected(reason: &str) {
+    METRICS
+        .rejected
+        .get_or_create(&label("reason", reason))
+        .inc();
+}
+
+pub fn displayed(rotation: &str, waited: Duration) {
+    let l = label("rotation", rotation);
+    METRICS.displayed.get_or_create(&l).inc();
+    METRICS.wait.get_or_create(&l).observe(waited.as_secs_f64());
+}
+
+pub fn removed(by: &str, count: usize) {
+    METRICS
+        .removed
+        .get_or_create(&label("by", by))
+        .inc_by(count as u64);
+}
+
+pub fn queue_depth(rotation: &str, depth: usize) {
+    METRICS
+        .queue_depth
+        .get_or_create(&label("rotation", rotation))
+        .set(depth as i64);
+}
+
+pub fn tunnel_restart(target: &str) {
+    METRICS
+        .tunnel_restarts
+        .get_or_create(&label("target", target))
+        .inc();
+}
+
+/// Records one WLED request; `path` may carry a query string, which is left out of the label.
+pub fn wled_request(path: &str, took: Duration, ok: bool) {
+    let endpoint = path.split('?').next().unwrap_or(path);
+    let l = label("endpoint", endpoint);
+    METRICS
+        .wled_latency
+        .get_or_create(&l)
+        .observe(took.as_secs_f64());
+    if !ok {
+        METRICS.wled_errors.get_or_create(&l).inc();
+    }
+}
+
+/// Starts the uptime clock.
+pub fn start() {
+    LazyLock::force(&METRICS);
+}
+
+/// The registry in the Prometheus text format.
+pub fn render() -> String {
+    METRICS
+        .uptime
+        .set(METRICS.started.elapsed().as_secs() as i64);
+    let mut out = String::new();
+    encode(&mut out, &METRICS.registry).expect("writing to a String");
+    out
+}
+
+/// `GET /metrics`
+pub async fn handler() -> impl IntoResponse {
+    (
+        [(
+            header::CONTENT_TYPE,
+            "application/openmetrics-text; version=1.0.0; charset=utf-8",
+        )],
+        render(),
+    )
+}
@@ -0,0 +1,480 @@
+//! A stand-in WLED device for tests and rehearsals.
+//!
+//! `trouw-gordijn mock-wled` serves the parts of the WLED HTTP API the app uses (`/json`,
+//! `/json/state`, `/json/info`, `/json/effects`, `/json/palettes`, `/json/live`,
+//! `/presets.json` and `/win`), keeps every state update it receives, and can be told to be slow,
+//! fail or drop the text. Open it in a browser to see what the "curtain" shows.
+
+use std::{collections::BTreeMap, net::SocketAddr, sync::Arc, time::Duration};
+
+use axum::{
+    extract::{RawQuery, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::{sync::Mutex, time};
+use tracing::info;
+
+use crate::wled;
+
+/// Index of "Scrolling Text" in the effect list, as on real 0.14 builds.
+const TEXT_EFFECT: usize = 122;
+
+/// Misbehaviour to simulate.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Faults {
+    /// Added to every request.
+    pub latency_ms: u64,
+    /// The next this many requests answer HTTP 500.
+    pub fail_requests: u32,
+    /// Answer everything with HTTP 503, like a device that dropped off the network.
+    pub offline: bool,
+    /// Accept updates but don't take over segment names, so the text never shows.
+    pub drop_text: bool,
+    /// Keep only this many bytes of segment names, like a build with a shorter limit than its
+    /// chip suggests; 0 for the chip's own limit.
+    pub name_len: usize,
+    /// Answer `/json/effects` and `/json/palettes` with this HTTP status (404 for a build
+    /// without them); 0 to serve them.
+    pub lists_status: u16,
+}
+
+#[derive(Clone, Debug)]
+pub struct MockConfig {
+    pub version: String,
+    /// `esp32` keeps 64 bytes of segment names, anything else 32.
+    pub arch: String,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        MockConfig {
+            version: "0.14.0".into(),
+            arch: "esp32".into(),
+            width: 32,
+            height: 16,
+        }
+    }
+}
+
+struct Device {
+    cfg: MockConfig,
+    state: Value,
+    presets: BTreeMap<u16, Value>,
+    received: Vec<Value>,
+    win: Vec<String>,
+    faults: Faults,
+}
+
+/// Handle to a mock device; clones share the device.
+#[derive(Clone)]
+pub struct MockWled {
+    inner: Arc<Mutex<Device>>,
+}
+
+impl Default for MockWled {
+    fn default() -> Self {
+        MockWled::new(MockConfig::default())
+    }
+}
+
+impl MockWled {
+    pub fn new(cfg: MockConfig) -> Self {
+        MockWled {
+            inner: Arc::new(Mutex::new(Device {
+                cfg,
+                state: json!({
+                    "on": false,
+                    "bri": 128,
+                    "ps": -1,
+                    "seg": [{
+                        "id": 0, "n": "", "col": [[0, 0, 0]], "fx": 0, "pal": 0,
+                        "c1": 128, "c2": 128, "o1": false, "o2": false, "o3": false,
+                    }],
+                }),
+                presets: BTreeMap::new(),
+                received: Vec::new(),
+                win: Vec::new(),
+                faults: Faults::default(),
+            })),
+        }
+    }
+
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/", get(view))
+            .route("/json", get(full))
+            .route("/json/state", get(get_state).post(post_state))
+            .route("/json/info", get(info))
+            .route("/json/effects", get(effects))
+            .route("/json/palettes", get(palettes))
+            .route("/json/live", get(live))
+            .route("/presets.json", get(presets))
+            .route("/win", get(win))
+            .route("/mock/faults", post(set_faults))
+            .route("/mock/received", get(received))
+            .with_state(self.clone())
+    }
+
+    /// Serves the device on `addr` (port 0 picks a free one) in the background.
+    pub async fn spawn(&self, addr: SocketAddr) -> std::io::Result<SocketAddr> {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let app = self.router();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        Ok(addr)
+    }
+
+    /// The current device state, as `GET /json/state` returns it.
+    pub async fn state(&self) -> Value {
+        self.inner.lock().await.state.clone()
+    }
+
+    /// Every `POST /json/state` body, oldest first.
+    pub async fn received(&self) -> Vec<Value> {
+        self.inner.lock().await.received.clone()
+    }
+
+    /// Every `/win` query string, oldest first.
+    pub async fn win_calls(&self) -> Vec<String> {
+        self.inner.lock().await.win.clone()
+    }
+
+    pub async fn set_faults(&self, faults: Faults) {
+        self.inner.lock().await.faults = faults;
+    }
+
+    /// Applies the configured latency and failures; `Err` is the response to send instead.
+    async fn gate(&self) -> Result<(), Response> {
+        let latency = {
+            let mut dev = self.inner.lock().await;
+            if dev.faults.offline {
+                return Err(StatusCode::SERVICE_UNAVAILABLE.into_response());
+            }
+            if dev.faults.fail_requests > 0 {
+                dev.faults.fail_requests -= 1;
+                return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+            }
+            dev.faults.latency_ms
+        };
+        if latency > 0 {
+            time::sleep(Duration::from_millis(latency)).await;
+        }
+        Ok(())
+    }
+
+    /// [`MockWled::gate`] for the effect and palette lists.
+    async fn gate_list(&self) -> Result<(), Response> {
+        self.gate().await?;
+        match self.inner.lock().await.faults.lists_status {
+            0 => Ok(()),
+            status => Err(StatusCode::from_u16(status)
+                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+                .into_response()),
+        }
+    }
+}
+
+impl Device {
+    fn info(&self) -> Value {
+        json!({
+            "ver": self.cfg.version,
+            "vid": 2405180,
+            "name": "Mock WLED",
+            "arch": self.cfg.arch,
+            "leds": {
+                "count": u32::from(self.cfg.width) * u32::from(self.cfg.height),
+                "matrix": { "w": self.cfg.width, "h": self.cfg.height },
+            },
+            "fxcount": TEXT_EFFECT + 1,
+            "palcount": PALETTES.len(),
+            "u": {},
+        })
+    }
+
+    fn apply(&mut self, update: &Value) {
+        let Some(update) = update.as_object() else {
+            return;
+        };
+        if let Some(id) = update.get("psave").and_then(Value::as_u64) {
+            let mut preset = self.state.clone();
+            if let Some(n) = update.get("n") {
+                preset["n"] = n.clone();
+            }
+            self.presets.insert(id as u16, preset);
+            return;
+        }
+        if let Some(ps) = update.get("ps").and_then(Value::as_i64) {
+            if let Some(preset) = u16::try_from(ps).ok().and_then(|id| self.presets.get(&id)) {
+                let preset = preset.clone();
+                self.apply(&preset);
+            }
+            self.state["ps"] = ps.into();
+        }
+        for key in ["on", "bri"] {
+            if let Some(v) = update.get(key) {
+                self.state[key] = v.clone();
+            }
+        }
+        for seg in update
+            .get("seg")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            let id = seg.get("id").and_then(Value::as_u64).unwrap_or(0);
+            let segs = self.state["seg"].as_array_mut().expect("seg is an array");
+            let idx = match segs.iter().position(|s| s["id"].as_u64() == Some(id)) {
+                Some(i) => i,
+                None => {
+                    segs.push(json!({ "id": id }));
+                    segs.len() - 1
+                }
+            };
+            for (k, v) in seg.as_object().into_iter().flatten() {
+                match k.as_str() {
+                    "n" if self.faults.drop_text => {}
+                    "n" => {
+                        let max = match self.faults.name_len {
+                            0 if self.cfg.arch.starts_with("esp32") => wled::LONG_SEGMENT_NAME,
+                            0 => wled::SHORT_SEGMENT_NAME,
+                            n => n,
+                        };
+                        let name = v.as_str().unwrap_or_default();
+                        segs[idx]["n"] = wled::truncate_name(name, max).into();
+                    }
+                    // newer builds take the option checkboxes as one array
+                    "o" => {
+                        for (i, o) in v.as_array().into_iter().flatten().take(3).enumerate() {
+                            segs[idx][format!("o{}", i + 1)] = truthy(o).into();
+                        }
+                    }
+                    "o1" | "o2" | "o3" => segs[idx][k.as_str()] = truthy(v).into(),
+                    _ => segs[idx][k.as_str()] = v.clone(),
+                }
+            }
+        }
+    }
+
+    /// One-line rendering of segment 0 for the terminal.
+    fn describe(&self) -> String {
+        let seg = &self.state["seg"][0];
+        format!(
+            "{} bri={} ps={} fx={} pal={} col={} text={:?}",
+            if self.state["on"] == true {
+                "ON "
+            } else {
+                "OFF"
+            },
+            self.state["bri"],
+            self.state["ps"],
+            seg["fx"],
+            seg["pal"],
+            hex(&seg["col"][0]),
+            seg["n"].as_str().unwrap_or(""),
+        )
+    }
+}
+
+/// WLED accepts both `true` and `1` for a checkbox.
+fn truthy(v: &Value) -> bool {
+    v.as_bool()
+        .unwrap_or_else(|| v.as_u64().is_some_and(|n| n != 0))
+}
+
+fn hex(col: &Value) -> String {
+    let c = |i: usize| col.get(i).and_then(Value::as_u64).unwrap_or(0).min(255);
+    format!("{:02x}{:02x}{:02x}", c(0), c(1), c(2))
+}
+
+const PALETTES: [&str; 6] = [
+    "Default",
+    "* Random Cycle",
+    "* Color 1",
+    "* Colors 1&2",
+    "* Color Gradient",
+    "* Colors Only",
+];
+
+async fn full(State(mock): State<MockWled>) -> Response {
+    if let Err(res) = mock.gate().await {
+        return res;
+    }
+    let dev = mock.inner.lock().await;
+    Json(json!({
+        "state": dev.state,
+        "info": dev.info(),
+        "effects": effect_names(),
+        "palettes": PALETTES,
+    }))
+    .into_response()
+}
+
+async fn get_state(State(mock): State<MockWled>) -> Response {
+    if let Err(res) = mock.gate().await {
+        return res;
+    }
+    Json(mock.state().await).into_response()
+}
+
+async fn post_state(State(mock): State<MockWled>, Json(update): Json<Value>) -> Response {
+    if let Err(res) = mock.gate().await {
+        return res;
+    }
+    let mut dev = mock.inner.lock().await;
+    dev.received.push(update.clone());
+    dev.apply(&update);
+    info!("mock WLED: {}", dev.describe());
+    if update.get("v") == Some(&Value::Bool(true)) {
+        Json(dev.state.clone()).into_response()
+    } else {
+        Json(json!({ "success": true })).into_response()
+    }
+}
+
+async fn info(State(mock): State<MockWled>) -> Response {
+    if let Err(res) = mock.gate().await {
+        return res;
+    }
+    Json(mock.inner.lock().await.info()).into_response()
+}
+
+fn effect_names() -> Vec<String> {
+    (0..=TEXT_EFFECT)
+        .map(|i| match i {
+            0 => "Solid".to_string(),
+            TEXT_EFFECT => "Scrolling Text".to_string(),
+            i => format!("Effect {i}"),
+        })
+        .collect()
+}
+
+async fn effects(State(mock): State<MockWled>) -> Response {
+    if let Err(res) = mock.gate_list().await {
+        return res;
+    }
+    Json(effect_names()).into_response()
+}
+
+async fn palettes(State(mock): State<MockWled>) -> Response {
+    if let Err(res) = mock.gate_list().await {
+        return res;
+    }
+    Json(PALETTES).into_response()
+}
+
+/// Every LED in segment 0's colour while on; good enough for the preview.
+async fn live(State(mock): State<MockWled>) -> Response {
+    if let Err(res) = mock.gate().await {
+        return res;
+    }
+    let dev = mock.inner.lock().await;
+    let col = if dev.state["on"] == true {
+        hex(&dev.state["seg"][0]["col"][0]).to_uppercase()
+    } else {
+        "000000".into()
+    };
+    let (w, h) = (dev.cfg.width, dev.cfg.height);
+    Json(json!({
+        "leds": vec![col; usize::from(w) * usize::from(h)],
+        "n": 1,
+        "w": w,
+        "h": h,
+    }))
+    .into_response()
+}
+
+async fn presets(State(mock): State<MockWled>) -> Response {
+    if let Err(res) = mock.gate().await {
+        return res;
+    }
+    let dev = mock.inner.lock().await;
+    let mut all = serde_json::Map::new();
+    all.insert("0".into(), json!({}));
+    for (id, p) in &dev.presets {
+        all.insert(id.to_string(), p.clone());
+    }
+    Json(Value::Object(all)).into_response()
+}
+
+async fn win(State(mock): State<MockWled>, RawQuery(query): RawQuery) -> Response {
+    if let Err(res) = mock.gate().await {
+        return res;
+    }
+    let query = query.unwrap_or_default();
+    info!("mock WLED: /win?{query}");
+    mock.inner.lock().await.win.push(query);
+    (StatusCode::OK, "<?xml version=\"1.0\" ?><vs></vs>").into_response()
+}
+
+async fn set_faults(State(mock): State<MockWled>, Json(faults): Json<Faults>) -> impl IntoResponse {
+    info!(?faults, "mock WLED: faults changed");
+    mock.set_faults(faults).await;
+    StatusCode::NO_CONTENT
+}
+
+async fn received(State(mock): State<MockWled>) -> impl IntoResponse {
+    Json(json!({ "states": mock.received().await, "win": mock.win_calls().await }))
+}
+
+/// A browser view of the curtain: segment 0's text scrolling in its colour.
+async fn view() -> impl IntoResponse {
+    Html(
+        r#"<!doctype html>
+<html><head><meta charset="utf-8"><title>Mock WLED</title>
+<style>
+  body { background:#000; color:#777; font-family:monospace; margin:0; padding:20px; }
+  #curtain { height:160px; overflow:hidden; white-space:nowrap; font-size:120px; line-height:160px; border:1px solid #222; }
+  #text { display:inline-block; padding-left:100%; animation:scroll 8s linear infinite; }
+  @keyframes scroll { to { transform:translateX(-100%); } }
+</style></head>
+<body><div id="curtain"><span id="text"></span></div><pre id="state"></pre>
+<script>
+async function tick(){
+  const s = await (await fetch('/json/state', {cache:'no-store'})).json();
+  const seg = (s.seg||[])[0] || {};
+  const c = (seg.col||[[0,0,0]])[0];
+  const el = document.getElementById('text');
+  el.textContent = s.on ? (seg.n||'') : '';
+  el.style.color = 'rgb(' + c.slice(0,3).join(',') + ')';
+  el.style.opacity = (s.bri||0)/255;
+  document.getElementById('state').textContent = JSON.stringify(s, null, 2);
+}
+tick(); setInterval(()=>tick().catch(()=>{}), 1000);
+</script></body></html>"#,
+    )
+}
+
+/// Runs the mock device from `MOCK_WLED_*` env vars until Ctrl-C.
+pub async fn run() -> anyhow::Result<()> {
+    let var = |k: &str| std::env::var(k).ok().filter(|v| !v.is_empty());
+    let defaults = MockConfig::default();
+    let cfg = MockConfig {
+        version: var("MOCK_WLED_VERSION").unwrap_or(defaults.version),
+        arch: var("MOCK_WLED_ARCH").unwrap_or(defaults.arch),
+        width: var("MOCK_WLED_WIDTH").map_or(Ok(defaults.width), |s| s.parse())?,
+        height: var("MOCK_WLED_HEIGHT").map_or(Ok(defaults.height), |s| s.parse())?,
+    };
+    let addr: SocketAddr = var("MOCK_WLED_ADDR")
+        .unwrap_or_else(|| "127.0.0.1:8081".into())
+        .parse()?;
+    let mock = MockWled::new(cfg);
+    if let Some(ms) = var("MOCK_WLED_LATENCY_MS") {
+        mock.set_faults(Faults {
+            latency_ms: ms.parse()?,
+            ..Default::default()
+        })
+        .await;
+    }
+    let addr = mock.spawn(addr).await?;
+    info!("mock WLED listening on http://{addr}");
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
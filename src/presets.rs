@@ -0,0 +1,289 @@
+//! Admin management of WLED presets: listing, preview/activate, storing an app-generated text
+//! preset and choosing which presets make up the idle scene rotation.
+
+use std::{sync::atomic::Ordering, time::Duration};
+
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Form,
+};
+use serde::{Deserialize, Serialize};
+use tokio::time;
+
+use crate::{
+    audit::{Action, Client},
+    text_segment, wled, AppState, TargetQuery,
+};
+
+/// How long a preview runs before the current message is restored.
+const DEFAULT_PREVIEW: Duration = Duration::from_secs(10);
+
+/// Runtime preset choices for one target.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PresetSettings {
+    /// Preset activated before each message (`TEXT_PRESET_ID` at startup).
+    pub text_preset_id: Option<i32>,
+    /// Presets cycled through while nothing is queued.
+    pub scenes: Vec<u16>,
+}
+
+pub async fn list(
+    State(state): State<AppState>,
+    Query(tq): Query<TargetQuery>,
+) -> impl IntoResponse {
+    let Some(idx) = state.target_index(tq.target.as_deref()) else {
+        return (StatusCode::NOT_FOUND, "Unknown target").into_response();
+    };
+    let target = &state.targets[idx];
+    let presets = match target.wled.presets().await {
+        Ok(p) => p,
+        Err(e) => return (StatusCode::BAD_GATEWAY, e.to_string()).into_response(),
+    };
+    let settings = state.settings.get().presets(&target.cfg.name);
+    let items: Vec<_> = presets
+        .iter()
+        .map(|p| {
+            serde_json::json!({
+                "id": p.id,
+                "name": p.name,
+                "quick_label": p.quick_label,
+                "playlist": p.body.contains_key("playlist"),
+            })
+        })
+        .collect();
+    (
+        [(header::CACHE_CONTROL, "no-store, max-age=0")],
+        axum::Json(serde_json::json!({
+            "target": target.cfg.name,
+            "text_preset_id": settings.text_preset_id,
+            "scenes": settings.scenes,
+            "presets": items,
+        })),
+    )
+        .into_response()
+}
+
+#[derive(Deserialize)]
+pub struct PresetForm {
+    target: Option<String>,
+    id: u16,
+    /// Preview length in seconds.
+    seconds: Option<u64>,
+}
+
+pub async fn activate(
+    State(state): State<AppState>,
+    client: Client,
+    Form(f): Form<PresetForm>,
+) -> impl IntoResponse {
+    let Some(idx) = state.target_index(f.target.as_deref()) else {
+        return (StatusCode::NOT_FOUND, "Unknown target").into_response();
+    };
+    if let Err(e) = activate_preset(&state, idx, f.id).await {
+        return (StatusCode::BAD_GATEWAY, e.to_string()).into_response();
+    }
+    record(
+        &state,
+        &client,
+        idx,
+        serde_json::json!({ "op": "activate", "id": f.id }),
+    );
+    (StatusCode::OK, "ok").into_response()
+}
+
+/// Activates a preset for a few seconds, then has the rotation put its message (or idle scene)
+/// back, unless another preset was put up meanwhile.
+pub async fn preview(
+    State(state): State<AppState>,
+    client: Client,
+    Form(f): Form<PresetForm>,
+) -> impl IntoResponse {
+    let Some(idx) = state.target_index(f.target.as_deref()) else {
+        return (StatusCode::NOT_FOUND, "Unknown target").into_response();
+    };
+    let shown = match activate_preset(&state, idx, f.id).await {
+        Ok(shown) => shown,
+        Err(e) => return (StatusCode::BAD_GATEWAY, e.to_string()).into_response(),
+    };
+    let wait = f
+        .seconds
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_PREVIEW)
+        .min(Duration::from_secs(120));
+    record(
+        &state,
+        &client,
+        idx,
+        serde_json::json!({ "op": "preview", "id": f.id, "seconds": wait.as_secs() }),
+    );
+    tokio::spawn(async move {
+        time::sleep(wait).await;
+        if state.targets[idx].presets_shown.load(Ordering::Relaxed) == shown {
+            state.rotation(idx).reshow().await;
+        }
+    });
+    (StatusCode::OK, "ok").into_response()
+}
+
+/// Puts preset `id` up on target `idx`; returns its number in the target's `presets_shown`.
+async fn activate_preset(state: &AppState, idx: usize, id: u16) -> wled::Result<u64> {
+    let target = &state.targets[idx];
+    let shown = target.presets_shown.fetch_add(1, Ordering::Relaxed) + 1;
+    let update = wled::StateUpdate {
+        ps: Some(i32::from(id)),
+        ..Default::default()
+    };
+    target.wled.set_state(&update).await?;
+    Ok(shown)
+}
+
+fn record(state: &AppState, client: &Client, idx: usize, detail: serde_json::Value) {
+    state.audit.record(
+        Action::Preset,
+        &client.admin(),
+        None,
+        Some(&state.targets[idx].cfg.name),
+        detail,
+    );
+}
+
+#[derive(Deserialize)]
+pub struct GenerateForm {
+    target: Option<String>,
+    /// Slot to store into; the first free slot when empty.
+    id: Option<u16>,
+    name: Option<String>,
+}
+
+/// Builds the text display state from the discovered capabilities, stores it as a preset and
+/// makes it the target's text preset.
+pub async fn generate_text(
+    State(state): State<AppState>,
+    client: Client,
+    Form(f): Form<GenerateForm>,
+) -> impl IntoResponse {
+    let Some(idx) = state.target_index(f.target.as_deref()) else {
+        return (StatusCode::NOT_FOUND, "Unknown target").into_response();
+    };
+    let target = &state.targets[idx];
+    let wled = &target.wled;
+    let caps = match target.caps.get(wled).await {
+        Ok(c) => c,
+        Err(e) => return (StatusCode::BAD_GATEWAY, e.to_string()).into_response(),
+    };
+    if caps.text_effect.is_none() {
+        return (
+            StatusCode::CONFLICT,
+            "This WLED build has no scrolling text effect",
+        )
+            .into_response();
+    }
+    let id = match f.id {
+        Some(id) if id > 0 => id,
+        Some(_) => return (StatusCode::BAD_REQUEST, "Invalid preset id").into_response(),
+        None => match wled.presets().await {
+            Ok(existing) => match (1..=250).find(|id| !existing.iter().any(|p| p.id == *id)) {
+                Some(id) => id,
+                None => return (StatusCode::CONFLICT, "No free preset slot").into_response(),
+            },
+            Err(e) => return (StatusCode::BAD_GATEWAY, e.to_string()).into_response(),
+        },
+    };
+    let name = f
+        .name
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty())
+        .unwrap_or_else(|| "Trouw Gordijn text".into());
+
+    let update = wled::StateUpdate {
+        on: Some(true),
+        bri: Some(state.settings.get().brightness),
+        seg: vec![text_segment(
+            &caps,
+            "Trouw Gordijn",
+            (255, 215, 0),
+            caps.text_effect,
+        )],
+        ..Default::default()
+    };
+    let res = match wled.set_state(&update).await {
+        Ok(_) => wled.save_preset(id, &name).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = res {
+        return (StatusCode::BAD_GATEWAY, e.to_string()).into_response();
+    }
+    let name_key = target.cfg.name.clone();
+    let stored = state
+        .settings
+        .update(&client.admin(), |s| {
+            s.presets.entry(name_key).or_default().text_preset_id = Some(i32::from(id));
+        })
+        .await;
+    if let Err(e) = stored {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+    }
+    axum::Json(serde_json::json!({ "id": id, "name": name })).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct SettingsForm {
+    target: Option<String>,
+    /// Empty to stop switching presets before each message.
+    text_preset_id: Option<String>,
+    /// Comma-separated preset ids, e.g. `3,5,8`.
+    scenes: Option<String>,
+}
+
+pub async fn update_settings(
+    State(state): State<AppState>,
+    client: Client,
+    Form(f): Form<SettingsForm>,
+) -> impl IntoResponse {
+    let Some(idx) = state.target_index(f.target.as_deref()) else {
+        return (StatusCode::NOT_FOUND, "Unknown target").into_response();
+    };
+    let text_preset_id = match f.text_preset_id.as_deref().map(str::trim) {
+        None => None,
+        Some("") => Some(None),
+        Some(raw) => match raw.parse::<i32>() {
+            Ok(id) if id > 0 => Some(Some(id)),
+            _ => return (StatusCode::BAD_REQUEST, "Invalid text preset id").into_response(),
+        },
+    };
+    let scenes = match f.scenes {
+        None => None,
+        Some(raw) => {
+            let parsed: Result<Vec<u16>, _> = raw
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::parse)
+                .collect();
+            match parsed {
+                Ok(ids) => Some(ids),
+                Err(_) => return (StatusCode::BAD_REQUEST, "Invalid scene list").into_response(),
+            }
+        }
+    };
+    let name = state.targets[idx].cfg.name.clone();
+    let stored = state
+        .settings
+        .update(&client.admin(), |s| {
+            let presets = s.presets.entry(name).or_default();
+            if let Some(id) = text_preset_id {
+                presets.text_preset_id = id;
+            }
+            if let Some(scenes) = scenes {
+                presets.scenes = scenes;
+            }
+        })
+        .await;
+    match stored {
+        Ok(_) => (StatusCode::OK, "ok").into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
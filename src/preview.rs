@@ -0,0 +1,131 @@
+//! Live pixel preview of a curtain for the guest and admin pages.
+//!
+//! While at least one browser watches a target, a single poller reads WLED's `/json/live`
+//! snapshot a few times per second and fans the frames out to every viewer's WebSocket.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::StatusCode,
+    response::IntoResponse,
+};
+use futures_util::{SinkExt, StreamExt};
+use tokio::{sync::broadcast, time};
+
+use crate::{capabilities::Capabilities, wled, AppState, TargetQuery};
+
+/// Time between two `/json/live` reads.
+const FRAME_INTERVAL: Duration = Duration::from_millis(400);
+/// Time between reachability checks while the device is offline.
+const OFFLINE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Frame fan-out for one target.
+pub struct LivePreview {
+    frames: broadcast::Sender<Arc<str>>,
+    polling: AtomicBool,
+}
+
+impl Default for LivePreview {
+    fn default() -> Self {
+        LivePreview {
+            frames: broadcast::channel(4).0,
+            polling: AtomicBool::new(false),
+        }
+    }
+}
+
+/// `GET /api/preview/ws[?target=<name>]` (for guests, behind their access code) and
+/// `GET /api/admin/preview/ws` (for the admin page): JSON frames `{w, h, leds}` with `RRGGBB`
+/// strings in row-major order, or `{offline: true}` while the device can't be reached.
+pub async fn ws(
+    State(state): State<AppState>,
+    Query(tq): Query<TargetQuery>,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse {
+    let Some(idx) = state.target_index(tq.target.as_deref()) else {
+        return (StatusCode::NOT_FOUND, "Unknown target").into_response();
+    };
+    ws.on_upgrade(move |socket| watch(state, idx, socket))
+}
+
+async fn watch(state: AppState, idx: usize, socket: WebSocket) {
+    let preview = state.targets[idx].preview.clone();
+    let mut frames = preview.frames.subscribe();
+    if !preview.polling.swap(true, Ordering::SeqCst) {
+        tokio::spawn(poll(state, idx));
+    }
+    let (mut sink, mut stream) = socket.split();
+    loop {
+        tokio::select! {
+            frame = frames.recv() => match frame {
+                Ok(frame) => {
+                    if sink.send(Message::Text(frame.to_string())).await.is_err() {
+                        break;
+                    }
+                }
+                // a slow viewer just skips frames
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            msg = stream.next() => match msg {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+async fn poll(state: AppState, idx: usize) {
+    let target = &state.targets[idx];
+    let preview = &target.preview;
+    loop {
+        if preview.frames.receiver_count() == 0 {
+            preview.polling.store(false, Ordering::SeqCst);
+            // a viewer may have subscribed between the check and the store
+            if preview.frames.receiver_count() == 0 || preview.polling.swap(true, Ordering::SeqCst)
+            {
+                return;
+            }
+        }
+        if !target.online.get() {
+            let _ = preview.frames.send(Arc::from(r#"{"offline":true}"#));
+            time::sleep(OFFLINE_INTERVAL).await;
+            continue;
+        }
+        let frame = match target.wled.live().await {
+            Ok(live) => frame_json(&live, target.caps.peek().await.as_ref()),
+            Err(_) => serde_json::json!({ "offline": true }),
+        };
+        let _ = preview.frames.send(Arc::from(frame.to_string()));
+        time::sleep(FRAME_INTERVAL).await;
+    }
+}
+
+fn frame_json(live: &wled::Live, caps: Option<&Capabilities>) -> serde_json::Value {
+    let len = live.leds.len() as u32;
+    let (w, h) = match (live.w, live.h, caps.and_then(|c| c.matrix)) {
+        (Some(w), Some(h), _) => (w, h),
+        // older builds only report the downsampling factor
+        (_, _, Some(m)) if live.n > 0 => (
+            (u32::from(m.w)).div_ceil(live.n),
+            (u32::from(m.h)).div_ceil(live.n),
+        ),
+        _ => (len, 1),
+    };
+    let (w, h) = if w.checked_mul(h) == Some(len) {
+        (w, h)
+    } else {
+        (len, 1)
+    };
+    serde_json::json!({ "w": w, "h": h, "leds": live.leds })
+}
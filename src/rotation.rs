@@ -0,0 +1,785 @@
+//! Message rotation.
+//!
+//! Every rotation owner runs one actor task that owns its queue and what is on screen. Handlers
+//! talk to it through a [`Rotation`] handle: commands go over a channel, and the actor publishes
+//! a [`Snapshot`] after every change for readers. Instead of polling, the actor sleeps until the
+//! current message's time is up, a command arrives or the curtain's reachability changes.
+//!
+//! The actor never waits on a device itself: pushing a message or scene runs in a task of its
+//! own that reports back over the command channel, so a slow curtain doesn't hold up handlers.
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{atomic::Ordering, Arc, Mutex as StdMutex},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{mpsc, oneshot, watch},
+    time::{self, Instant},
+};
+use tracing::{info, warn};
+
+use crate::{
+    audit::{self, Action},
+    metrics,
+    schedule::Schedule,
+    show_display, wled, AppState,
+};
+
+/// How long a message stays up when others are waiting, unless changed in the settings.
+pub const DISPLAY_TIME: Duration = Duration::from_secs(60);
+/// Tries before a message the curtain keeps answering without is given up on.
+pub const MAX_ATTEMPTS: u32 = 3;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct QueuedMessage {
+    pub id: u64,
+    pub text: String,
+    pub color: Option<String>, // #rrggbb
+    // who takes turns with whom: a guest's name or browser session, or "admin"
+    pub sender: String,
+    pub name: Option<String>,
+    // browser session that sent it, for "my messages"; empty for admin messages
+    pub owner: String,
+    // share of the turns under the weighted policy
+    pub weight: u32,
+    // tries the curtain answered without showing the text
+    #[serde(default)]
+    pub attempts: u32,
+    // when it entered the queue (set by the rotation), for the wait-time metric
+    #[serde(skip)]
+    pub queued_at: Option<Instant>,
+}
+
+#[derive(Clone, Debug)]
+pub struct CurrentDisplay {
+    pub id: u64,
+    pub text: String,
+    pub color: Option<String>,
+    pub name: Option<String>,
+    pub owner: String,
+    sender: String,
+    weight: u32,
+    attempts: u32,
+    queued_at: Option<Instant>,
+    // display time only accumulates while the curtain is reachable and the rotation runs
+    shown: Duration,
+    running_since: Option<Instant>,
+}
+
+impl CurrentDisplay {
+    fn start(msg: &QueuedMessage) -> Self {
+        CurrentDisplay {
+            id: msg.id,
+            text: msg.text.clone(),
+            color: msg.color.clone(),
+            name: msg.name.clone(),
+            owner: msg.owner.clone(),
+            sender: msg.sender.clone(),
+            weight: msg.weight,
+            attempts: msg.attempts,
+            queued_at: msg.queued_at,
+            shown: Duration::ZERO,
+            running_since: Some(Instant::now()),
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.shown + self.running_since.map_or(Duration::ZERO, |t| t.elapsed())
+    }
+
+    fn pause(&mut self) {
+        if let Some(t) = self.running_since.take() {
+            self.shown += t.elapsed();
+        }
+    }
+
+    fn resume(&mut self) {
+        self.running_since.get_or_insert_with(Instant::now);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.running_since.is_none()
+    }
+
+    fn to_queued(&self) -> QueuedMessage {
+        QueuedMessage {
+            id: self.id,
+            text: self.text.clone(),
+            color: self.color.clone(),
+            sender: self.sender.clone(),
+            name: self.name.clone(),
+            owner: self.owner.clone(),
+            weight: self.weight,
+            attempts: self.attempts,
+            queued_at: self.queued_at,
+        }
+    }
+}
+
+/// What a rotation looks like right now.
+#[derive(Clone, Debug, Default)]
+pub struct Snapshot {
+    pub current: Option<CurrentDisplay>,
+    pub queue: Vec<QueuedMessage>,
+    /// Stopped by an admin.
+    pub held: bool,
+    /// Display time is passing (reachable and not held).
+    pub running: bool,
+    /// How long each message stays up.
+    pub display_time: Duration,
+}
+
+impl Snapshot {
+    /// Roughly how long until the `pos`th waiting message is shown; None while the rotation
+    /// is stopped.
+    pub fn eta(&self, pos: usize) -> Option<Duration> {
+        if !self.running {
+            return None;
+        }
+        let first = self.current.as_ref().map_or(Duration::ZERO, |c| {
+            self.display_time.saturating_sub(c.elapsed())
+        });
+        Some(first + self.display_time * pos as u32)
+    }
+}
+
+/// A message that just made it onto the curtain.
+#[derive(Clone, Debug)]
+pub struct OnAir {
+    pub id: u64,
+    /// Browser session that sent it.
+    pub owner: String,
+    pub text: String,
+    /// The rotation's owning target.
+    pub target: String,
+}
+
+/// How pushing a display to the rotation owner went.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shown {
+    Confirmed,
+    /// The device answered, but its state doesn't show the text.
+    Differs,
+    /// The device didn't answer; it is marked offline.
+    Unreachable,
+}
+
+/// Outcome of [`Rotation::enqueue`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Enqueued {
+    Queued,
+    /// Shown straight away because the current message had already had its time.
+    Switched,
+}
+
+enum Command {
+    Enqueue(QueuedMessage, oneshot::Sender<Enqueued>),
+    Remove(u64, oneshot::Sender<bool>),
+    Cancel(u64, String, oneshot::Sender<bool>),
+    Edit(Edit, oneshot::Sender<bool>),
+    Skip(oneshot::Sender<bool>),
+    Clear(oneshot::Sender<Vec<u64>>),
+    Hold(bool, oneshot::Sender<()>),
+    Reshow(oneshot::Sender<()>),
+    /// Device IO started by the actor has finished.
+    Done(Done),
+}
+
+enum Done {
+    Shown { id: u64, shown: Shown },
+    Scene,
+    Reshown { id: u64, shown: Shown },
+}
+
+/// Device IO the actor is waiting for; nothing else is sent to the curtain meanwhile.
+enum Busy {
+    /// A message just started; the enqueuer that switched to it waits for the outcome.
+    Display {
+        reply: Option<oneshot::Sender<Enqueued>>,
+    },
+    Scene,
+    /// The current message pushed again after the curtain came back or something else was
+    /// put up in between.
+    Reshow,
+}
+
+/// A guest's change to one of their waiting messages.
+pub struct Edit {
+    pub id: u64,
+    pub owner: String,
+    pub text: String,
+    pub color: Option<String>,
+}
+
+/// Handle to a rotation actor; cheap to clone.
+#[derive(Clone)]
+pub struct Rotation {
+    commands: mpsc::UnboundedSender<Command>,
+    snapshot: watch::Receiver<Snapshot>,
+    // the actor's ends of the channels, taken once when it starts
+    inbox: Arc<StdMutex<Option<Inbox>>>,
+}
+
+struct Inbox {
+    commands: mpsc::UnboundedReceiver<Command>,
+    snapshot: watch::Sender<Snapshot>,
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (snap_tx, snap_rx) = watch::channel(Snapshot::default());
+        Rotation {
+            commands: tx,
+            snapshot: snap_rx,
+            inbox: Arc::new(StdMutex::new(Some(Inbox {
+                commands: rx,
+                snapshot: snap_tx,
+            }))),
+        }
+    }
+}
+
+impl Rotation {
+    pub fn snapshot(&self) -> Snapshot {
+        self.snapshot.borrow().clone()
+    }
+
+    /// Queues a message, or shows it right away when the current one has had its time.
+    pub async fn enqueue(&self, msg: QueuedMessage) -> Enqueued {
+        let (tx, rx) = oneshot::channel();
+        let _ = self.commands.send(Command::Enqueue(msg, tx));
+        rx.await.unwrap_or(Enqueued::Queued)
+    }
+
+    /// Drops a message from the queue or the screen; false if it isn't in this rotation.
+    pub async fn remove(&self, id: u64) -> bool {
+        let (tx, rx) = oneshot::channel();
+        let _ = self.commands.send(Command::Remove(id, tx));
+        rx.await.unwrap_or(false)
+    }
+
+    /// Withdraws a waiting message on behalf of the browser that sent it; false if it isn't
+    /// theirs or is already on screen.
+    pub async fn cancel(&self, id: u64, owner: &str) -> bool {
+        let (tx, rx) = oneshot::channel();
+        let _ = self
+            .commands
+            .send(Command::Cancel(id, owner.to_string(), tx));
+        rx.await.unwrap_or(false)
+    }
+
+    /// Changes a waiting message's text and colour; it keeps its place in the queue.
+    pub async fn edit(&self, edit: Edit) -> bool {
+        let (tx, rx) = oneshot::channel();
+        let _ = self.commands.send(Command::Edit(edit, tx));
+        rx.await.unwrap_or(false)
+    }
+
+    /// Ends the current message now; false when nothing is waiting to replace it.
+    pub async fn skip(&self) -> bool {
+        let (tx, rx) = oneshot::channel();
+        let _ = self.commands.send(Command::Skip(tx));
+        rx.await.unwrap_or(false)
+    }
+
+    /// Drops every waiting message; the one on screen stays. Returns the ids of those dropped.
+    pub async fn clear(&self) -> Vec<u64> {
+        let (tx, rx) = oneshot::channel();
+        let _ = self.commands.send(Command::Clear(tx));
+        rx.await.unwrap_or_default()
+    }
+
+    /// Stops (or restarts) the rotation; the current message stays up with its clock frozen.
+    pub async fn hold(&self, held: bool) {
+        let (tx, rx) = oneshot::channel();
+        let _ = self.commands.send(Command::Hold(held, tx));
+        let _ = rx.await;
+    }
+
+    /// Puts the current message (or the idle scenes) back on the curtain after something else
+    /// was shown there, e.g. a preset preview.
+    pub async fn reshow(&self) {
+        let (tx, rx) = oneshot::channel();
+        let _ = self.commands.send(Command::Reshow(tx));
+        let _ = rx.await;
+    }
+}
+
+/// Where [`stop_and_save`] leaves the queues, in the data directory.
+const SAVED_QUEUES: &str = "queue.json";
+
+/// One rotation as saved at shutdown.
+#[derive(Serialize, Deserialize)]
+struct SavedRotation {
+    held: bool,
+    /// The one on screen first, then the queue in order.
+    messages: Vec<QueuedMessage>,
+}
+
+/// Stops every rotation and saves what is on screen and waiting, by target name. Returns how
+/// many messages were saved.
+pub async fn stop_and_save(state: &AppState) -> anyhow::Result<usize> {
+    let mut saved = BTreeMap::new();
+    let mut count = 0;
+    for target in state.targets.iter() {
+        let Some(rotation) = &target.rotation else {
+            continue;
+        };
+        let held = rotation.snapshot().held;
+        // nothing moves on from here
+        rotation.hold(true).await;
+        let snapshot = rotation.snapshot();
+        let messages: Vec<_> = snapshot
+            .current
+            .iter()
+            .map(CurrentDisplay::to_queued)
+            .chain(snapshot.queue)
+            .collect();
+        count += messages.len();
+        saved.insert(target.cfg.name.clone(), SavedRotation { held, messages });
+    }
+    let path = state.data_dir.join(SAVED_QUEUES);
+    tokio::fs::create_dir_all(&state.data_dir).await?;
+    let tmp = path.with_extension("json.tmp");
+    tokio::fs::write(&tmp, serde_json::to_vec_pretty(&saved)?).await?;
+    tokio::fs::rename(&tmp, &path).await?;
+    Ok(count)
+}
+
+/// Queues what [`stop_and_save`] saved again and removes the file, so it is restored only once.
+/// Messages keep their ids, and their senders can still withdraw or edit them. Returns how many
+/// messages were restored.
+pub async fn restore(state: &AppState) -> anyhow::Result<usize> {
+    let path = state.data_dir.join(SAVED_QUEUES);
+    let bytes = match tokio::fs::read(&path).await {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let saved: BTreeMap<String, SavedRotation> =
+        serde_json::from_slice(&bytes).map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+    let mut count = 0;
+    for (name, saved) in saved {
+        let rotation = state
+            .targets
+            .iter()
+            .find(|t| t.cfg.name == name)
+            .and_then(|t| t.rotation.as_ref());
+        let Some(rotation) = rotation else {
+            warn!(target = %name, dropped = saved.messages.len(), "saved queue for a target that no longer runs one");
+            continue;
+        };
+        rotation.hold(saved.held).await;
+        for msg in saved.messages {
+            state.next_id.fetch_max(msg.id + 1, Ordering::Relaxed);
+            rotation.enqueue(msg).await;
+            count += 1;
+        }
+    }
+    tokio::fs::remove_file(&path).await?;
+    Ok(count)
+}
+
+/// Runs the rotation owned by target `owner` until the app stops.
+pub async fn run(state: AppState, owner: usize) {
+    let rotation = state.rotation(owner).clone();
+    let Some(inbox) = rotation.inbox.lock().expect("inbox lock").take() else {
+        warn!(owner, "rotation already running");
+        return;
+    };
+    let policy = state.policy;
+    let mut settings = state.settings.subscribe();
+    let mut actor = Actor {
+        display_time: settings.borrow_and_update().display_time(),
+        commands: rotation.commands.clone(),
+        busy: None,
+        reshow: false,
+        state,
+        owner,
+        snapshot: inbox.snapshot,
+        queue: Schedule::new(policy),
+        current: None,
+        held: false,
+        online: false,
+        scene_step: 0,
+        next_scene: Instant::now(),
+    };
+    let mut commands = inbox.commands;
+    loop {
+        actor.sync_online();
+        actor.tick();
+        actor.publish();
+        let wake = actor.deadline();
+        let online = actor.state.targets[owner].online.clone();
+        tokio::select! {
+            cmd = commands.recv() => match cmd {
+                Some(cmd) => actor.handle(cmd),
+                None => return,
+            },
+            _ = online.changed() => {}
+            Ok(()) = settings.changed() => {
+                actor.display_time = settings.borrow_and_update().display_time();
+            }
+            _ = time::sleep_until(wake) => {}
+        }
+    }
+}
+
+struct Actor {
+    state: AppState,
+    owner: usize,
+    snapshot: watch::Sender<Snapshot>,
+    // for device IO tasks to report back
+    commands: mpsc::UnboundedSender<Command>,
+    busy: Option<Busy>,
+    // the curtain shows something else; put the current message back once it can be pushed
+    reshow: bool,
+    queue: Schedule,
+    current: Option<CurrentDisplay>,
+    held: bool,
+    // reachability as last acted upon; the target's flag may already differ
+    online: bool,
+    scene_step: usize,
+    next_scene: Instant,
+    display_time: Duration,
+}
+
+impl Actor {
+    fn running(&self) -> bool {
+        self.online && !self.held
+    }
+
+    fn publish(&self) {
+        metrics::queue_depth(
+            &self.state.targets[self.owner].cfg.name,
+            self.queue.iter().count(),
+        );
+        self.snapshot.send_replace(Snapshot {
+            current: self.current.clone(),
+            queue: self.queue.iter().cloned().collect(),
+            held: self.held,
+            running: self.running(),
+            display_time: self.display_time,
+        });
+    }
+
+    /// When the next thing happens by itself (a message's time is up, the next idle scene).
+    fn deadline(&self) -> Instant {
+        let now = Instant::now();
+        let idle = now + Duration::from_secs(3600);
+        // woken by the IO's Done
+        if !self.running() || self.busy.is_some() {
+            return idle;
+        }
+        match &self.current {
+            None if !self.queue.is_empty() => now,
+            None => self.next_scene,
+            Some(d) if !self.queue.is_empty() => {
+                now + self.display_time.saturating_sub(d.elapsed())
+            }
+            // the last message stays up until something new arrives
+            Some(_) => idle,
+        }
+    }
+
+    fn sync_clock(&mut self) {
+        let running = self.running();
+        if let Some(display) = self.current.as_mut() {
+            if running {
+                display.resume();
+            } else {
+                display.pause();
+            }
+        }
+    }
+
+    /// Applies a command; the snapshot is published before replying, so callers read their own
+    /// change back.
+    fn handle(&mut self, cmd: Command) {
+        match cmd {
+            Command::Enqueue(mut msg, reply) => {
+                msg.queued_at = Some(Instant::now());
+                // If only the last item is showing and it already had its time, jump to the new
+                // one immediately (unless the curtain is unreachable; then it just waits)
+                let due = self
+                    .current
+                    .as_ref()
+                    .is_some_and(|d| d.elapsed() >= self.display_time);
+                if self.running() && self.queue.is_empty() && due && self.busy.is_none() {
+                    // replied to once the curtain confirms
+                    self.show(CurrentDisplay::start(&msg), Some(reply));
+                    self.publish();
+                } else {
+                    self.queue.push(msg);
+                    self.publish();
+                    let _ = reply.send(Enqueued::Queued);
+                }
+            }
+            Command::Remove(id, reply) => {
+                let mut found = self.queue.remove(id);
+                if self.current.as_ref().is_some_and(|c| c.id == id) {
+                    self.current = None;
+                    found = true;
+                }
+                if found {
+                    metrics::removed("admin", 1);
+                }
+                self.publish();
+                let _ = reply.send(found);
+            }
+            Command::Cancel(id, owner, reply) => {
+                let theirs = self
+                    .queue
+                    .iter()
+                    .any(|m| m.id == id && !owner.is_empty() && m.owner == owner);
+                let found = theirs && self.queue.remove(id);
+                if found {
+                    metrics::removed("guest", 1);
+                }
+                self.publish();
+                let _ = reply.send(found);
+            }
+            Command::Edit(edit, reply) => {
+                let msg = self
+                    .queue
+                    .get_mut(edit.id)
+                    .filter(|m| !edit.owner.is_empty() && m.owner == edit.owner);
+                let found = msg.is_some();
+                if let Some(msg) = msg {
+                    msg.text = edit.text;
+                    msg.color = edit.color;
+                }
+                self.publish();
+                let _ = reply.send(found);
+            }
+            Command::Skip(reply) => {
+                let skip = self.current.is_some() && !self.queue.is_empty();
+                if skip {
+                    self.current = None;
+                }
+                self.publish();
+                let _ = reply.send(skip);
+            }
+            Command::Clear(reply) => {
+                let mut dropped = Vec::new();
+                while let Some(msg) = self.queue.pop() {
+                    dropped.push(msg.id);
+                }
+                metrics::removed("clear", dropped.len());
+                self.publish();
+                let _ = reply.send(dropped);
+            }
+            Command::Hold(held, reply) => {
+                self.held = held;
+                self.sync_clock();
+                self.publish();
+                let _ = reply.send(());
+            }
+            Command::Reshow(reply) => {
+                self.reshow = true;
+                let _ = reply.send(());
+            }
+            Command::Done(done) => self.done(done),
+        }
+    }
+
+    fn done(&mut self, done: Done) {
+        let busy = self.busy.take();
+        match (done, busy) {
+            (Done::Shown { id, shown }, Some(Busy::Display { reply })) => {
+                // removed or skipped while it was being pushed: nothing left to do
+                if let Some(display) = self.current.clone().filter(|d| d.id == id) {
+                    self.confirm(&display, shown);
+                }
+                self.publish();
+                if let Some(reply) = reply {
+                    let outcome = if shown == Shown::Confirmed {
+                        Enqueued::Switched
+                    } else {
+                        Enqueued::Queued
+                    };
+                    let _ = reply.send(outcome);
+                }
+            }
+            (Done::Reshown { id, shown }, _) => {
+                // if it didn't answer the target is marked offline again and we wait for the
+                // next change
+                if shown != Shown::Unreachable && self.state.targets[self.owner].online.get() {
+                    self.online = true;
+                    self.sync_clock();
+                }
+                if shown == Shown::Differs {
+                    if let Some(display) = self.current.clone().filter(|d| d.id == id) {
+                        self.confirm(&display, shown);
+                    }
+                }
+                self.publish();
+            }
+            (Done::Scene, _) | (Done::Shown { .. }, _) => {}
+        }
+    }
+
+    /// Runs device IO in a task of its own; it reports back with [`Command::Done`].
+    fn spawn_io(&mut self, busy: Busy, io: impl Future<Output = Done> + Send + 'static) {
+        self.busy = Some(busy);
+        let commands = self.commands.clone();
+        tokio::spawn(async move {
+            let _ = commands.send(Command::Done(io.await));
+        });
+    }
+
+    /// Starts `display` and pushes it to the curtain.
+    fn show(&mut self, display: CurrentDisplay, reply: Option<oneshot::Sender<Enqueued>>) {
+        self.current = Some(display.clone());
+        let (state, owner) = (self.state.clone(), self.owner);
+        self.spawn_io(Busy::Display { reply }, async move {
+            let shown = show_display(&state, owner, &display).await;
+            Done::Shown {
+                id: display.id,
+                shown,
+            }
+        });
+    }
+
+    fn sync_online(&mut self) {
+        // checked again once the IO is done
+        if self.busy.is_some() {
+            return;
+        }
+        let up = self.state.targets[self.owner].online.get();
+        if up == self.online {
+            return;
+        }
+        if up {
+            // Back online: the device may have rebooted, so push the current item again before
+            // its clock runs on.
+            if let Some(display) = self.current.clone() {
+                self.reshow(display);
+                return;
+            }
+        }
+        self.online = up;
+        self.sync_clock();
+    }
+
+    /// Pushes the current message again, as it may no longer be on the curtain.
+    fn reshow(&mut self, display: CurrentDisplay) {
+        let (state, owner) = (self.state.clone(), self.owner);
+        self.spawn_io(Busy::Reshow, async move {
+            let shown = show_display(&state, owner, &display).await;
+            Done::Reshown {
+                id: display.id,
+                shown,
+            }
+        });
+    }
+
+    /// Starts the next message when nothing shows or the current one had its time, and cycles
+    /// the idle scenes while there is nothing to show.
+    fn tick(&mut self) {
+        if self.reshow && self.online && self.busy.is_none() {
+            self.reshow = false;
+            match self.current.clone() {
+                // also while held: the message stays up, so it should be the one showing
+                Some(display) => return self.reshow(display),
+                None => self.next_scene = Instant::now(),
+            }
+        }
+        if !self.running() || self.busy.is_some() {
+            return;
+        }
+        let due = self
+            .current
+            .as_ref()
+            .is_none_or(|d| d.elapsed() >= self.display_time);
+        if due {
+            if let Some(next) = self.queue.pop() {
+                self.show(CurrentDisplay::start(&next), None);
+            }
+        }
+        if self.current.is_some() {
+            self.next_scene = Instant::now();
+        } else if Instant::now() >= self.next_scene {
+            let (state, owner, step) = (self.state.clone(), self.owner, self.scene_step);
+            self.spawn_io(Busy::Scene, async move {
+                show_scene(&state, owner, step).await;
+                Done::Scene
+            });
+            self.scene_step = self.scene_step.wrapping_add(1);
+            self.next_scene = Instant::now() + self.display_time;
+        }
+    }
+
+    /// Takes in how pushing a freshly started display went. If the curtain couldn't be reached,
+    /// the message goes back to the head of the queue and waits until it is reachable again. If
+    /// it answered without showing the text, the message is tried again, up to [`MAX_ATTEMPTS`]
+    /// times, and then dropped.
+    fn confirm(&mut self, display: &CurrentDisplay, shown: Shown) {
+        if shown == Shown::Confirmed {
+            let name = &self.state.targets[self.owner].cfg.name;
+            let waited = display.queued_at.map_or(Duration::ZERO, |t| t.elapsed());
+            metrics::displayed(name, waited);
+            self.state.audit.record(
+                Action::Display,
+                &audit::Actor::rotation(),
+                Some(display.id),
+                Some(name),
+                serde_json::json!({ "waited_seconds": waited.as_secs() }),
+            );
+            // nobody listening is fine
+            let _ = self.state.on_air.send(OnAir {
+                id: display.id,
+                owner: display.owner.clone(),
+                text: display.text.clone(),
+                target: self.state.targets[self.owner].cfg.name.clone(),
+            });
+            return;
+        }
+        self.current = None;
+        let mut msg = display.to_queued();
+        let id = msg.id;
+        if shown == Shown::Unreachable {
+            info!(id, "curtain unreachable; message requeued");
+            self.queue.push_front(msg);
+            return;
+        }
+        msg.attempts += 1;
+        if msg.attempts < MAX_ATTEMPTS {
+            info!(id, attempts = msg.attempts, "text not shown; trying again");
+            self.queue.push_front(msg);
+            return;
+        }
+        let name = &self.state.targets[self.owner].cfg.name;
+        warn!(id, target = %name, attempts = msg.attempts, "text never shown; message dropped");
+        metrics::removed("failed", 1);
+        self.state.audit.record(
+            Action::Remove,
+            &audit::Actor::rotation(),
+            Some(id),
+            Some(name),
+            serde_json::json!({ "via": "display_failed", "attempts": msg.attempts }),
+        );
+    }
+}
+
+/// Activates the `step`th idle scene on every target displaying `owner`'s rotation.
+async fn show_scene(state: &AppState, owner: usize, step: usize) {
+    for (_, target) in state.display_targets(owner) {
+        let scenes = target.settings.borrow().presets(&target.cfg.name).scenes;
+        if scenes.is_empty() {
+            continue;
+        }
+        let update = wled::StateUpdate {
+            ps: Some(i32::from(scenes[step % scenes.len()])),
+            ..Default::default()
+        };
+        if let Err(e) = target.wled.set_state(&update).await {
+            warn!(?e, target = %target.cfg.name, "idle scene failed");
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Order in which queued messages reach the curtain.
+//!
+//! Every message gets a virtual finish tag when it is queued and the queue is kept sorted by it.
+//! With FIFO the tag is just the arrival order. The fair policies give each sender its own
+//! virtual clock that advances by one slot per message (a fraction of a slot for weighted
+//! senders), so a table that submits ten messages at once only gets every other turn once
+//! someone else joins in.
+
+use std::{collections::HashMap, str::FromStr};
+
+use serde::Deserialize;
+
+use crate::rotation::QueuedMessage;
+
+/// Virtual time one message of weight 1 takes.
+const SLOT: u64 = 1_000_000;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Policy {
+    /// Strict arrival order.
+    Fifo,
+    /// Senders take turns.
+    #[default]
+    RoundRobin,
+    /// Senders take turns, but a sender of weight `w` gets `w` turns for everybody else's one.
+    Weighted,
+}
+
+impl Policy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Policy::Fifo => "fifo",
+            Policy::RoundRobin => "round-robin",
+            Policy::Weighted => "weighted",
+        }
+    }
+}
+
+impl FromStr for Policy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "fifo" => Ok(Policy::Fifo),
+            "round-robin" | "rr" | "fair" => Ok(Policy::RoundRobin),
+            "weighted" | "priority" => Ok(Policy::Weighted),
+            other => anyhow::bail!(
+                "unknown queue policy '{other}' (expected fifo, round-robin or weighted)"
+            ),
+        }
+    }
+}
+
+impl TryFrom<String> for Policy {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Parses `QUEUE_WEIGHTS`, e.g. `Anna=4, Tom=4, getuige=2`; names are matched case-insensitively.
+pub fn parse_weights(s: &str) -> anyhow::Result<HashMap<String, u32>> {
+    let mut weights = HashMap::new();
+    for entry in s.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (name, weight) = entry
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("queue weight '{entry}' is not name=weight"))?;
+        let weight: u32 = weight
+            .trim()
+            .parse()
+            .ok()
+            .filter(|w| *w > 0)
+            .ok_or_else(|| {
+                anyhow::anyhow!("queue weight for '{name}' must be a positive number")
+            })?;
+        weights.insert(name.trim().to_lowercase(), weight);
+    }
+    Ok(weights)
+}
+
+struct Entry {
+    tag: u64,
+    msg: QueuedMessage,
+}
+
+/// The waiting messages of one rotation, in display order.
+pub struct Schedule {
+    policy: Policy,
+    entries: Vec<Entry>,
+    // tag of the message taken last
+    clock: u64,
+    // per sender, the tag of their last queued message
+    finish: HashMap<String, u64>,
+}
+
+impl Schedule {
+    pub fn new(policy: Policy) -> Self {
+        Schedule {
+            policy,
+            entries: Vec::new(),
+            clock: 0,
+            finish: HashMap::new(),
+        }
+    }
+
+    fn cost(&self, msg: &QueuedMessage) -> u64 {
+        match self.policy {
+            Policy::Fifo | Policy::RoundRobin => SLOT,
+            Policy::Weighted => SLOT / u64::from(msg.weight.max(1)),
+        }
+    }
+
+    pub fn push(&mut self, msg: QueuedMessage) {
+        let tag = match self.policy {
+            Policy::Fifo => msg.id,
+            Policy::RoundRobin | Policy::Weighted => {
+                let last = self.finish.get(&msg.sender).copied().unwrap_or(0);
+                let tag = last.max(self.clock) + self.cost(&msg);
+                self.finish.insert(msg.sender.clone(), tag);
+                tag
+            }
+        };
+        let at = self
+            .entries
+            .partition_point(|e| (e.tag, e.msg.id) <= (tag, msg.id));
+        self.entries.insert(at, Entry { tag, msg });
+    }
+
+    /// Puts a message back at the head, e.g. when the curtain didn't take it.
+    pub fn push_front(&mut self, msg: QueuedMessage) {
+        let tag = self
+            .entries
+            .first()
+            .map_or(self.clock, |e| e.tag)
+            .saturating_sub(1);
+        self.entries.insert(0, Entry { tag, msg });
+    }
+
+    pub fn pop(&mut self) -> Option<QueuedMessage> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let entry = self.entries.remove(0);
+        self.clock = self.clock.max(entry.tag);
+        // senders without anything ahead of the clock start afresh next time
+        let clock = self.clock;
+        self.finish.retain(|_, tag| *tag > clock);
+        Some(entry.msg)
+    }
+
+    /// Drops a message; the sender's later messages move up into its slot.
+    pub fn remove(&mut self, id: u64) -> bool {
+        let Some(pos) = self.entries.iter().position(|e| e.msg.id == id) else {
+            return false;
+        };
+        let entry = self.entries.remove(pos);
+        if self.policy != Policy::Fifo {
+            let cost = self.cost(&entry.msg);
+            for e in &mut self.entries {
+                if e.msg.sender == entry.msg.sender && e.tag > entry.tag {
+                    e.tag -= cost;
+                }
+            }
+            if let Some(tag) = self.finish.get_mut(&entry.msg.sender) {
+                *tag = tag.saturating_sub(cost);
+            }
+            self.entries.sort_by_key(|e| (e.tag, e.msg.id));
+        }
+        true
+    }
+
+    pub fn get_mut(&mut self, id: u64) -> Option<&mut QueuedMessage> {
+        self.entries
+            .iter_mut()
+            .map(|e| &mut e.msg)
+            .find(|m| m.id == id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &QueuedMessage> {
+        self.entries.iter().map(|e| &e.msg)
+    }
+}
@@ -0,0 +1,42 @@
+//! Per-browser session cookie, so the queue can tell guests apart.
+
+use axum::http::{header, HeaderMap, HeaderValue};
+use rand::Rng;
+
+const COOKIE: &str = "gordijn_session";
+/// A wedding weekend, with margin.
+const MAX_AGE_SECS: u32 = 7 * 24 * 3600;
+
+/// The browser's session token, if it sent a well-formed one.
+pub fn token(headers: &HeaderMap) -> Option<String> {
+    cookie(headers, COOKIE).filter(|v| is_token(v))
+}
+
+/// Value of the request cookie `name`.
+pub fn cookie(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|c| c.trim().split_once('='))
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| v.to_string())
+}
+
+/// Whether `s` looks like a [`new_token`].
+pub fn is_token(s: &str) -> bool {
+    s.len() == 32 && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+pub fn new_token() -> String {
+    let bytes: [u8; 16] = rand::thread_rng().gen();
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub fn set_cookie(token: &str) -> HeaderValue {
+    HeaderValue::from_str(&format!(
+        "{COOKIE}={token}; Path=/; Max-Age={MAX_AGE_SECS}; SameSite=Lax; HttpOnly"
+    ))
+    .expect("token is hex")
+}
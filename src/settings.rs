@@ -0,0 +1,323 @@
+//! Settings that can change while the party is on: how long messages stay up, brightness,
+//! message limits, each target's text preset and idle scenes, and the guest page's theme.
+//!
+//! They start out from the config and are edited from the admin page. Changes reach the
+//! rotation and the displays right away, are kept in `settings.json` in the data directory (and
+//! win over the config from then on), and every change goes into the audit log with who made it.
+
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc, time::Duration};
+
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Form,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::{watch, Mutex};
+use tracing::{info, warn};
+
+use crate::{
+    audit::{self, Action, Actor, AuditLog, Client},
+    presets::PresetSettings,
+    rotation::DISPLAY_TIME,
+    theme::Theme,
+    wled, AppState,
+};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+    /// How long a message stays up when others are waiting.
+    pub display_seconds: u64,
+    /// WLED brightness while showing messages.
+    pub brightness: u8,
+    /// Longest message text, in bytes.
+    pub max_text_len: usize,
+    /// Longest signature, in bytes.
+    pub max_name_len: usize,
+    /// Per target name.
+    pub presets: BTreeMap<String, PresetSettings>,
+    /// The guest page's look.
+    #[serde(default)]
+    pub theme: Theme,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            display_seconds: DISPLAY_TIME.as_secs(),
+            brightness: 128,
+            max_text_len: wled::LONG_SEGMENT_NAME,
+            max_name_len: 32,
+            presets: BTreeMap::new(),
+            theme: Theme::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn display_time(&self) -> Duration {
+        Duration::from_secs(self.display_seconds)
+    }
+
+    pub fn presets(&self, target: &str) -> PresetSettings {
+        self.presets.get(target).cloned().unwrap_or_default()
+    }
+
+    /// What's wrong with these settings, if anything.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(5..=3600).contains(&self.display_seconds) {
+            return Err("display_seconds must be between 5 and 3600".into());
+        }
+        if self.brightness == 0 {
+            return Err("brightness must be between 1 and 255".into());
+        }
+        // WLED shows the text as the segment name, which it cuts off beyond this
+        if !(1..=wled::LONG_SEGMENT_NAME).contains(&self.max_text_len) {
+            return Err(format!(
+                "max_text_len must be between 1 and {}",
+                wled::LONG_SEGMENT_NAME
+            ));
+        }
+        if !(1..=64).contains(&self.max_name_len) {
+            return Err("max_name_len must be between 1 and 64".into());
+        }
+        if let Some((name, _)) = self
+            .presets
+            .iter()
+            .find(|(_, p)| p.text_preset_id.is_some_and(|id| id <= 0))
+        {
+            return Err(format!("target '{name}': text_preset_id must be positive"));
+        }
+        self.theme.validate()
+    }
+}
+
+/// One changed value.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Change {
+    /// Dotted path, e.g. `brightness` or `presets.bar.scenes`.
+    pub key: String,
+    pub old: Value,
+    pub new: Value,
+}
+
+pub struct SettingsStore {
+    path: PathBuf,
+    current: watch::Sender<Settings>,
+    audit: Arc<AuditLog>,
+    // serializes updates
+    saving: Mutex<()>,
+}
+
+impl SettingsStore {
+    /// Starts from `seed` (the config) with the stored settings, if any, on top.
+    pub fn load(dir: PathBuf, seed: Settings, audit: Arc<AuditLog>) -> anyhow::Result<Self> {
+        let path = dir.join("settings.json");
+        let settings = match std::fs::read(&path) {
+            Ok(bytes) => {
+                let stored: Value = serde_json::from_slice(&bytes)
+                    .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+                let mut merged = serde_json::to_value(&seed)?;
+                merge(&mut merged, stored);
+                let mut settings: Settings = serde_json::from_value(merged)
+                    .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+                // saved before texts were capped at what WLED keeps
+                settings.max_text_len = settings.max_text_len.min(wled::LONG_SEGMENT_NAME);
+                settings
+                    .validate()
+                    .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+                settings
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => seed,
+            Err(e) => return Err(anyhow::anyhow!("{}: {e}", path.display())),
+        };
+        Ok(SettingsStore {
+            path,
+            current: watch::channel(settings).0,
+            audit,
+            saving: Mutex::new(()),
+        })
+    }
+
+    pub fn get(&self) -> Settings {
+        self.current.borrow().clone()
+    }
+
+    /// Sees every change; the displays and rotations read their settings from here.
+    pub fn subscribe(&self) -> watch::Receiver<Settings> {
+        self.current.subscribe()
+    }
+
+    /// Changes the settings on behalf of `by`, stores them and tells everyone watching.
+    /// Returns what actually changed.
+    pub async fn update(
+        &self,
+        by: &Actor,
+        f: impl FnOnce(&mut Settings),
+    ) -> Result<Vec<Change>, String> {
+        let _saving = self.saving.lock().await;
+        let old = self.get();
+        let mut new = old.clone();
+        f(&mut new);
+        new.validate()?;
+        let mut changes = Vec::new();
+        diff(
+            "",
+            &serde_json::to_value(&old).expect("settings serialize"),
+            &serde_json::to_value(&new).expect("settings serialize"),
+            &mut |key, old, new| {
+                changes.push(Change {
+                    key,
+                    old: old.clone(),
+                    new: new.clone(),
+                })
+            },
+        );
+        if changes.is_empty() {
+            return Ok(changes);
+        }
+        if let Err(e) = self.save(&new).await {
+            return Err(format!("saving settings failed: {e}"));
+        }
+        for c in &changes {
+            info!(by = %by.name, key = %c.key, old = %c.old, new = %c.new, "setting changed");
+            self.audit.record(
+                Action::Settings,
+                by,
+                None,
+                None,
+                serde_json::to_value(c).expect("change serializes"),
+            );
+        }
+        self.current.send_replace(new);
+        Ok(changes)
+    }
+
+    async fn save(&self, settings: &Settings) -> anyhow::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, serde_json::to_vec_pretty(settings)?).await?;
+        tokio::fs::rename(&tmp, &self.path).await?;
+        Ok(())
+    }
+}
+
+/// Overlays `top` onto `base`, object by object.
+fn merge(base: &mut Value, top: Value) {
+    match (base, top) {
+        (Value::Object(base), Value::Object(top)) => {
+            for (k, v) in top {
+                match base.get_mut(&k) {
+                    Some(b) => merge(b, v),
+                    None => {
+                        base.insert(k, v);
+                    }
+                }
+            }
+        }
+        (base, top) => *base = top,
+    }
+}
+
+/// Calls `f` with the dotted path of every leaf value that differs.
+fn diff(prefix: &str, old: &Value, new: &Value, f: &mut impl FnMut(String, &Value, &Value)) {
+    let key = |k: &str| {
+        if prefix.is_empty() {
+            k.to_string()
+        } else {
+            format!("{prefix}.{k}")
+        }
+    };
+    match (old, new) {
+        (Value::Object(o), Value::Object(n)) => {
+            for (k, nv) in n {
+                diff(&key(k), o.get(k).unwrap_or(&Value::Null), nv, f);
+            }
+            for (k, ov) in o.iter().filter(|(k, _)| !n.contains_key(*k)) {
+                diff(&key(k), ov, &Value::Null, f);
+            }
+        }
+        (Value::Object(o), Value::Null) => {
+            for (k, ov) in o {
+                diff(&key(k), ov, &Value::Null, f);
+            }
+        }
+        (Value::Null, Value::Object(n)) => {
+            for (k, nv) in n {
+                diff(&key(k), &Value::Null, nv, f);
+            }
+        }
+        _ if old != new => f(prefix.to_string(), old, new),
+        _ => {}
+    }
+}
+
+/// `GET /api/admin/settings`: the current settings and the recent changes.
+pub async fn get(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CACHE_CONTROL, "no-store, max-age=0")],
+        axum::Json(serde_json::json!({
+            "settings": state.settings.get(),
+            "audit": state.audit.query(&audit::Filter {
+                action: Some(Action::Settings),
+                limit: Some(50),
+                ..Default::default()
+            }),
+        })),
+    )
+}
+
+#[derive(Deserialize)]
+pub struct UpdateForm {
+    display_seconds: Option<u64>,
+    brightness: Option<u8>,
+    max_text_len: Option<usize>,
+    max_name_len: Option<usize>,
+}
+
+/// `POST /api/admin/settings`: changes the given settings. A new brightness is pushed to the
+/// displays right away; the rest applies from the next message.
+pub async fn update(
+    State(state): State<AppState>,
+    client: Client,
+    Form(f): Form<UpdateForm>,
+) -> impl IntoResponse {
+    let changes = state
+        .settings
+        .update(&client.admin(), |s| {
+            s.display_seconds = f.display_seconds.unwrap_or(s.display_seconds);
+            s.brightness = f.brightness.unwrap_or(s.brightness);
+            s.max_text_len = f.max_text_len.unwrap_or(s.max_text_len);
+            s.max_name_len = f.max_name_len.unwrap_or(s.max_name_len);
+        })
+        .await;
+    let changes = match changes {
+        Ok(changes) => changes,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    if changes.iter().any(|c| c.key == "brightness") {
+        push_brightness(&state).await;
+    }
+    axum::Json(serde_json::json!({
+        "settings": state.settings.get(),
+        "changes": changes,
+    }))
+    .into_response()
+}
+
+/// Sets the new brightness on every reachable display.
+async fn push_brightness(state: &AppState) {
+    let update = wled::StateUpdate {
+        bri: Some(state.settings.get().brightness),
+        ..Default::default()
+    };
+    for target in state.targets.iter().filter(|t| t.online.get()) {
+        if let Err(e) = target.wled.set_state(&update).await {
+            warn!(?e, target = %target.cfg.name, "brightness update failed");
+        }
+    }
+}
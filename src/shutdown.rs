@@ -0,0 +1,162 @@
+//! Stopping cleanly on Ctrl-C or SIGTERM: the servers stop taking connections and finish the
+//! requests in flight, the queues are written to `queue.json` in the data directory (and queued
+//! again at the next start), the curtain gets its goodnight, and the SSH tunnels are closed.
+
+use std::{fmt, str::FromStr, time::Duration};
+
+use serde::Deserialize;
+use tokio::{sync::watch, time};
+use tracing::{info, warn};
+
+use crate::{apply_display, rotation, wled, AppState};
+
+/// How long the curtain gets to answer the goodnight.
+const GOODNIGHT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long the rotations get to stop and the queues to be written.
+const SAVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the curtain shows after the app stops.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Goodnight {
+    /// Turn the LEDs off.
+    Off,
+    /// Activate a preset stored on the device.
+    Preset(i32),
+    /// Leave a last message up.
+    Text(String),
+}
+
+impl FromStr for Goodnight {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("off") {
+            return Ok(Goodnight::Off);
+        }
+        if let Some(id) = s.strip_prefix("preset:") {
+            return match id.trim().parse() {
+                Ok(id) if id > 0 => Ok(Goodnight::Preset(id)),
+                _ => anyhow::bail!("'{id}' is not a preset id"),
+            };
+        }
+        match s.strip_prefix("text:").map(str::trim) {
+            Some(text) if !text.is_empty() => Ok(Goodnight::Text(text.to_string())),
+            _ => anyhow::bail!("expected off, preset:<id> or text:<message>, not '{s}'"),
+        }
+    }
+}
+
+impl TryFrom<String> for Goodnight {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Goodnight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Goodnight::Off => f.write_str("off"),
+            Goodnight::Preset(id) => write!(f, "preset:{id}"),
+            Goodnight::Text(text) => write!(f, "text:{text}"),
+        }
+    }
+}
+
+/// Resolves on Ctrl-C, or SIGTERM on Unix.
+async fn signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!(?e, "can't listen for Ctrl-C");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut s) => {
+                s.recv().await;
+            }
+            Err(e) => {
+                warn!(?e, "can't listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Turns true once a stop signal arrives; every server and the drain timeout watch it.
+pub fn listen() -> watch::Receiver<bool> {
+    let (tx, rx) = watch::channel(false);
+    tokio::spawn(async move {
+        signal().await;
+        info!("stopping");
+        let _ = tx.send(true);
+    });
+    rx
+}
+
+/// Resolves once `stopping` turns true.
+pub async fn stopped(mut stopping: watch::Receiver<bool>) {
+    // an error means the sender is gone, which only happens once it has fired
+    let _ = stopping.wait_for(|s| *s).await;
+}
+
+/// After the servers are done: saves the queues, shows the goodnight and closes the tunnels.
+pub async fn finish(state: &AppState, goodnight: Option<&Goodnight>) {
+    match time::timeout(SAVE_TIMEOUT, rotation::stop_and_save(state)).await {
+        Ok(Ok(0)) => {}
+        Ok(Ok(n)) => info!(messages = n, "queue saved"),
+        Ok(Err(e)) => warn!(?e, "saving the queue failed"),
+        Err(_) => warn!(timeout = ?SAVE_TIMEOUT, "saving the queue timed out"),
+    }
+    if let Some(goodnight) = goodnight {
+        for target in state.targets.iter().filter(|t| t.online.get()) {
+            let shown = time::timeout(GOODNIGHT_TIMEOUT, show_goodnight(state, target, goodnight));
+            match shown.await {
+                Ok(Ok(())) => info!(target = %target.cfg.name, %goodnight, "goodnight"),
+                Ok(Err(e)) => warn!(?e, target = %target.cfg.name, "goodnight failed"),
+                Err(_) => warn!(target = %target.cfg.name, "goodnight timed out"),
+            }
+        }
+    }
+    for target in state.targets.iter() {
+        // one the supervisor starts again in the meantime dies with the runtime (kill_on_drop)
+        if let Some(mut child) = target.tunnel.lock().await.take() {
+            if let Err(e) = child.kill().await {
+                warn!(?e, target = %target.cfg.name, "stopping the tunnel failed");
+            }
+            info!(target = %target.cfg.name, "tunnel closed");
+        }
+    }
+}
+
+async fn show_goodnight(
+    state: &AppState,
+    target: &crate::Target,
+    goodnight: &Goodnight,
+) -> wled::Result<()> {
+    let update = match goodnight {
+        Goodnight::Text(text) => return apply_display(target, text, None).await,
+        Goodnight::Off => wled::StateUpdate {
+            on: Some(false),
+            ..Default::default()
+        },
+        Goodnight::Preset(id) => wled::StateUpdate {
+            on: Some(true),
+            bri: Some(state.settings.get().brightness),
+            ps: Some(*id),
+            ..Default::default()
+        },
+    };
+    target.wled.set_state(&update).await.map(|_| ())
+}
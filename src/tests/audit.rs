@@ -0,0 +1,92 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::http::HeaderMap;
+use serde_json::json;
+
+use crate::{
+    audit::{Action, AuditLog, Client, Filter},
+    session,
+};
+
+fn peer(ip: &str) -> SocketAddr {
+    SocketAddr::new(ip.parse().unwrap(), 40000)
+}
+
+#[tokio::test]
+async fn log_survives_a_restart_in_order() {
+    let path = std::env::temp_dir()
+        .join(format!("trouw-gordijn-test-{}", session::new_token()))
+        .join("audit.jsonl");
+    let log = AuditLog::load(path.clone()).unwrap();
+    let client = Client::from_parts(
+        &HeaderMap::new(),
+        Some(peer("203.0.113.7")),
+        &[],
+        log.salt(),
+    );
+    let guest = client.guest(Some(&session::new_token()));
+    log.record(
+        Action::Login,
+        &guest,
+        None,
+        None,
+        json!({ "label": "tafels" }),
+    );
+    log.record(Action::Submit, &guest, Some(1), Some("curtain"), json!({}));
+    let ip = guest.ip.clone().unwrap();
+    assert!(!ip.contains("203.0.113.7"));
+    // a guest without a session yet is told apart by address alone
+    assert_eq!(client.guest(None).ip.as_deref(), Some(ip.as_str()));
+    log.export(&Filter::default()).await.unwrap();
+
+    let log = AuditLog::load(path).unwrap();
+    log.record(
+        Action::Display,
+        &crate::audit::Actor::rotation(),
+        Some(1),
+        None,
+        json!(null),
+    );
+    let seqs: Vec<_> = log
+        .query(&Filter::default())
+        .iter()
+        .map(|e| e.seq)
+        .collect();
+    assert_eq!(seqs, [3, 2, 1]);
+    let jsonl = log.export(&Filter::default()).await.unwrap();
+    assert_eq!(jsonl.lines().count(), 3);
+    let since = Filter {
+        message_id: Some(1),
+        limit: Some(1),
+        ..Default::default()
+    };
+    assert_eq!(log.query(&since)[0].action, Action::Display);
+}
+
+#[test]
+fn forwarded_address_only_counts_from_a_trusted_proxy() {
+    let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+    let mut headers = HeaderMap::new();
+    // the guest made up the first entry; our proxy added the second
+    headers.insert(
+        "x-forwarded-for",
+        "198.51.100.9, 203.0.113.7".parse().unwrap(),
+    );
+    let ip = |addr: &str, trusted: &[IpAddr]| {
+        Client::from_parts(&headers, Some(peer(addr)), trusted, "zout")
+            .guest(None)
+            .ip
+    };
+    let direct = |addr: &str| Client::from_parts(&HeaderMap::new(), Some(peer(addr)), &[], "zout");
+
+    assert_eq!(
+        ip("10.0.0.1", &[proxy]),
+        direct("203.0.113.7").guest(None).ip
+    );
+    // straight from a guest, or through a proxy nobody configured: the header is ignored
+    assert_eq!(
+        ip("203.0.113.7", &[proxy]),
+        direct("203.0.113.7").guest(None).ip
+    );
+    assert_eq!(ip("10.0.0.1", &[]), direct("10.0.0.1").guest(None).ip);
+}
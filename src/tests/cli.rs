@@ -0,0 +1,75 @@
+use clap::{CommandFactory, Parser};
+
+use super::{setup, ADMIN};
+use crate::{
+    cli::{format_queue, Cli, Command, QueueAction, Remote},
+    rotation, router,
+};
+
+#[test]
+fn no_subcommand_serves() {
+    Cli::command().debug_assert();
+    let cli = Cli::try_parse_from(["trouw-gordijn"]).unwrap();
+    assert!(cli.command.is_none());
+}
+
+#[test]
+fn queue_and_send_arguments() {
+    let cli = Cli::try_parse_from([
+        "trouw-gordijn",
+        "queue",
+        "remove",
+        "12",
+        "--url",
+        "http://curtain:8080",
+    ])
+    .unwrap();
+    assert_eq!(cli.url, "http://curtain:8080");
+    assert!(matches!(
+        cli.command,
+        Some(Command::Queue {
+            action: QueueAction::Remove { id: 12 }
+        })
+    ));
+
+    let cli =
+        Cli::try_parse_from(["trouw-gordijn", "send", "Proficiat!", "--color", "#ff0000"]).unwrap();
+    let Some(Command::Send { text, color, .. }) = cli.command else {
+        panic!("not send");
+    };
+    assert_eq!(
+        (text.as_str(), color.as_deref()),
+        ("Proficiat!", Some("#ff0000"))
+    );
+    assert!(Cli::try_parse_from(["trouw-gordijn", "queue", "remove", "twelve"]).is_err());
+}
+
+/// `send` and `queue` against a running app, over HTTP.
+#[tokio::test]
+async fn operates_a_running_instance() {
+    let (_mock, state) = setup().await;
+    tokio::spawn(rotation::run(state.clone(), 0));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router(state)).await });
+    let remote = Remote::new(&url, Some(ADMIN));
+
+    for text in ["een", "twee", "drie"] {
+        remote
+            .post("/api/admin/message", &[("text", text.to_string())])
+            .await
+            .unwrap();
+    }
+    let listing = format_queue(&remote.queue(None).await.unwrap());
+    assert!(listing.contains("now  #1 een"), "{listing}");
+    assert!(listing.contains("2.  "), "{listing}");
+
+    let err = remote
+        .post("/api/admin/remove", &[("id", "99".to_string())])
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("404"), "{err}");
+    remote.post("/api/admin/clear", &[]).await.unwrap();
+    let listing = format_queue(&remote.queue(None).await.unwrap());
+    assert!(listing.ends_with("nothing waiting\n"), "{listing}");
+}
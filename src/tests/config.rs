@@ -0,0 +1,326 @@
+use crate::{
+    agent::AgentConfig,
+    config::{resolve, FileConfig},
+    schedule::Policy,
+    shutdown::Goodnight,
+    tls::TlsMode,
+    transport::Transport,
+    TargetMode,
+};
+
+fn toml(text: &str) -> FileConfig {
+    toml::from_str(text).expect("parse")
+}
+
+#[test]
+fn full_file() {
+    let cfg = resolve(toml(
+        r#"
+        [server]
+        bind_host = "127.0.0.1"
+        bind_port = 9000
+        public_url = "https://gordijn.example"
+        trusted_proxies = ["10.0.0.1", "::1"]
+
+        [queue]
+        policy = "weighted"
+        weights = { Anna = 4 }
+
+        [display]
+        seconds = 45
+
+        [access.codes]
+        tafels = "tafel-code-1"
+
+        [shutdown]
+        goodnight = "preset:4"
+
+        [theme]
+        couple = "Anna & Bram"
+        date = "2026-06-20"
+
+        [[targets]]
+        name = "curtain"
+        transport = "direct"
+        wled_host = "10.0.0.5"
+        text_preset_id = 3
+
+        [[targets]]
+        name = "bar"
+        mode = "queue"
+        wled_host = "10.0.0.6"
+        "#,
+    ))
+    .expect("valid");
+    assert_eq!(cfg.bind_addr.to_string(), "127.0.0.1:9000");
+    assert_eq!(cfg.trusted_proxies.len(), 2);
+    assert_eq!(cfg.queue_policy, Policy::Weighted);
+    assert_eq!(cfg.queue_weights.get("anna"), Some(&4));
+    assert_eq!(cfg.access_codes, [("tafels".into(), "tafel-code-1".into())]);
+    assert_eq!(cfg.settings.display_seconds, 45);
+    assert_eq!(cfg.goodnight, Some(Goodnight::Preset(4)));
+    assert_eq!(cfg.settings.theme.couple, "Anna & Bram");
+    assert_eq!(cfg.settings.theme.accent, "#d4af37");
+    assert_eq!(cfg.settings.presets["curtain"].text_preset_id, Some(3));
+    assert_eq!(cfg.targets.len(), 2);
+    assert_eq!(cfg.targets[0].transport, Transport::Direct);
+    assert_eq!(cfg.targets[0].text_preset_id, Some(3));
+    // the second target inherits the main target's transport
+    assert_eq!(cfg.targets[1].transport, Transport::Direct);
+    assert_eq!(cfg.targets[1].mode, TargetMode::Queue);
+}
+
+#[test]
+fn unknown_keys_are_rejected() {
+    let err = toml::from_str::<FileConfig>("[server]\nbind_prot = 80\n").unwrap_err();
+    assert!(err.to_string().contains("bind_prot"), "{err}");
+}
+
+#[test]
+fn bad_values_name_their_key() {
+    let err = toml::from_str::<FileConfig>("[server]\nbind_host = \"everywhere\"\n").unwrap_err();
+    assert!(err.to_string().contains("bind_host"), "{err}");
+    let err = toml::from_str::<FileConfig>("[queue]\npolicy = \"lottery\"\n").unwrap_err();
+    assert!(err.to_string().contains("lottery"), "{err}");
+    let err = toml::from_str::<FileConfig>(
+        "[shutdown]
+goodnight = \"preset:none\"\n",
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("goodnight"), "{err}");
+}
+
+#[test]
+fn all_problems_are_reported() {
+    let err = resolve(toml(
+        r#"
+        [server]
+        public_url = "gordijn.example"
+
+        [theme]
+        accent = "gold"
+
+        [[targets]]
+        name = "curtain"
+        mode = "mirror"
+        local_tunnel_port = 18080
+
+        [[targets]]
+        name = "bar"
+        local_tunnel_port = 18080
+
+        [[targets]]
+        name = "bar"
+        wled_host = "10.0.0.7"
+        local_tunnel_port = 18081
+        "#,
+    ))
+    .unwrap_err()
+    .to_string();
+    for expected in [
+        "public_url",
+        "theme.accent",
+        "can't be a mirror",
+        "target 'bar': wled_host",
+        "port 18080 is already used",
+        "listed twice",
+    ] {
+        assert!(err.contains(expected), "missing {expected:?} in:\n{err}");
+    }
+}
+
+#[test]
+fn yaml_file() {
+    let file: FileConfig = serde_yaml::from_str(
+        "server:\n  bind_port: 8181\ntargets:\n  - name: curtain\n    transport: websocket\n",
+    )
+    .expect("parse");
+    let cfg = resolve(file).expect("valid");
+    assert_eq!(cfg.bind_addr.port(), 8181);
+    assert_eq!(cfg.targets[0].transport, Transport::WebSocket);
+}
+
+#[test]
+fn tls_modes() {
+    // a domain alone means ACME; both spellings of the domain setting add up
+    let acme = resolve(toml(
+        r#"
+        [tls]
+        https_port = 8443
+        http_port = 0
+
+        [acme]
+        domain = "gordijn.example.com"
+        domains = ["www.gordijn.example.com"]
+        staging = true
+        "#,
+    ));
+    if cfg!(feature = "acme") {
+        let tls = acme.expect("valid").tls;
+        assert_eq!(
+            tls.mode,
+            TlsMode::Acme {
+                domains: vec![
+                    "gordijn.example.com".into(),
+                    "www.gordijn.example.com".into()
+                ],
+                contact: None,
+                cache_dir: "./acme-cache".into(),
+                staging: true,
+            }
+        );
+        assert_eq!((tls.https_port, tls.http_port), (8443, None));
+    } else {
+        let err = acme.unwrap_err().to_string();
+        assert!(err.contains("--features acme"), "{err}");
+    }
+
+    let err = resolve(toml(
+        r#"
+        [tls]
+        mode = "static"
+        cert = "/nonexistent/cert.pem"
+        https_port = 80
+        "#,
+    ))
+    .unwrap_err()
+    .to_string();
+    assert!(err.contains("needs both tls.cert"), "{err}");
+    let err = resolve(toml("[tls]\nmode = \"on\"\n"))
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("expected off, static or acme"), "{err}");
+    let err = resolve(toml(
+        "[acme]\ndomains = [\"https://gordijn.example.com\"]\n",
+    ))
+    .unwrap_err()
+    .to_string();
+    assert!(err.contains("plain host name"), "{err}");
+
+    assert_eq!(resolve(toml("")).expect("valid").tls.mode, TlsMode::Off);
+}
+
+#[test]
+fn admins() {
+    let cfg = resolve(toml(
+        r#"
+        [admin]
+        password = "hoofd-wachtwoord"
+
+        [admin.users]
+        arthur = "getuige-arthur"
+        "#,
+    ))
+    .expect("valid");
+    assert_eq!(
+        cfg.admins,
+        [
+            ("arthur".into(), "getuige-arthur".into()),
+            ("admin".into(), "hoofd-wachtwoord".into()),
+        ]
+    );
+
+    let err = resolve(toml(
+        r#"
+        [admin]
+        password = "kort"
+
+        [admin.users]
+        "arthur bart" = "zelfde-wachtwoord"
+        bart = "zelfde-wachtwoord"
+        "#,
+    ))
+    .unwrap_err()
+    .to_string();
+    for expected in [
+        "at least 8 characters",
+        "'arthur bart': names",
+        "same password",
+    ] {
+        assert!(err.contains(expected), "{expected}: {err}");
+    }
+}
+
+#[test]
+fn transports() {
+    let cfg = resolve(toml(
+        r#"
+        [agent]
+        token = "agent-token-1"
+
+        [[targets]]
+        name = "curtain"
+        wled_host = "10.0.0.5"
+        local_tunnel_port = 18080
+
+        [[targets]]
+        name = "bar"
+        transport = "direct"
+        wled_host = "10.0.0.6"
+
+        [[targets]]
+        name = "hall"
+        transport = "websocket"
+        wled_host = "10.0.0.7"
+
+        [[targets]]
+        name = "garden"
+        transport = "http-proxy"
+        proxy = "http://proxy.lan:3128"
+        wled_host = "10.0.0.8"
+
+        [[targets]]
+        name = "tent"
+        transport = "socks5"
+        proxy = "127.0.0.1:1080"
+        wled_host = "10.0.0.9"
+
+        [[targets]]
+        name = "stage"
+        transport = "agent"
+        wled_host = "10.0.0.10"
+        "#,
+    ))
+    .expect("valid");
+    let transports: Vec<_> = cfg.targets.iter().map(|t| t.transport.clone()).collect();
+    assert_eq!(
+        transports,
+        [
+            Transport::SshTunnel,
+            Transport::Direct,
+            Transport::WebSocket,
+            Transport::HttpProxy("http://proxy.lan:3128".into()),
+            // a bare host:port gets the scheme that resolves names through the proxy
+            Transport::Socks5("socks5h://127.0.0.1:1080".into()),
+            Transport::Agent,
+        ]
+    );
+    assert_eq!(cfg.agent_token.as_deref(), Some("agent-token-1"));
+
+    let err = resolve(toml(
+        r#"
+        [[targets]]
+        name = "curtain"
+        transport = "socks5"
+        wled_host = "10.0.0.5"
+        "#,
+    ))
+    .unwrap_err()
+    .to_string();
+    assert!(err.contains("needs a proxy URL"), "{err}");
+}
+
+#[test]
+fn agent_port_must_be_a_port() {
+    let vars = |port: &'static str| {
+        move |k: &str| match k {
+            "AGENT_SERVER_URL" => Some("wss://gordijn.example/api/agent/ws".to_string()),
+            "AGENT_TOKEN" => Some("agent-token-1".to_string()),
+            "WLED_PORT" => Some(port.to_string()),
+            _ => None,
+        }
+    };
+    assert!(AgentConfig::from_vars(vars("8080")).is_ok());
+    let err = AgentConfig::from_vars(vars("80a")).unwrap_err().to_string();
+    assert!(err.contains("WLED_PORT=\"80a\""), "{err}");
+}
@@ -0,0 +1,171 @@
+use serde_json::json;
+
+use super::{setup, setup_with, shown_text};
+use crate::{
+    apply_display,
+    audit::Actor,
+    mock_wled::{Faults, MockConfig, MockWled},
+    wled::WledError,
+};
+
+#[tokio::test]
+async fn shows_text_in_colour_with_text_effect() {
+    let (mock, state) = setup().await;
+    apply_display(&state.targets[0], "Proficiat!", Some("#ff0000"))
+        .await
+        .expect("display confirmed");
+
+    let st = mock.state().await;
+    assert_eq!(st["on"], true);
+    assert_eq!(st["seg"][0]["n"], "Proficiat!");
+    assert_eq!(st["seg"][0]["col"][0], json!([255, 0, 0]));
+    assert_eq!(st["seg"][0]["fx"], 122);
+    assert_eq!(st["seg"][0]["pal"], 2);
+    let check = state.targets[0].last_check.lock().await.clone().unwrap();
+    assert!(check.mismatches.is_empty(), "{:?}", check.mismatches);
+}
+
+#[tokio::test]
+async fn defaults_to_gold() {
+    let (mock, state) = setup().await;
+    apply_display(&state.targets[0], "hoera", None)
+        .await
+        .unwrap();
+    assert_eq!(mock.state().await["seg"][0]["col"][0], json!([255, 215, 0]));
+}
+
+#[tokio::test]
+async fn text_preset_is_selected_first() {
+    let (mock, state) = setup().await;
+    state
+        .settings
+        .update(&Actor::admin(None), |s| {
+            s.presets
+                .entry("curtain".into())
+                .or_default()
+                .text_preset_id = Some(7)
+        })
+        .await
+        .unwrap();
+    apply_display(&state.targets[0], "met preset", None)
+        .await
+        .unwrap();
+
+    let received = mock.received().await;
+    assert_eq!(received[0]["ps"], 7);
+    // the effect comes from the preset, so it isn't forced
+    assert!(received[1]["seg"][0].get("fx").is_none());
+    assert_eq!(shown_text(&mock).await, "met preset");
+}
+
+#[tokio::test]
+async fn legacy_text_parameter_is_sent() {
+    let (mock, mut state) = setup().await;
+    std::sync::Arc::get_mut(&mut state.targets).unwrap()[0]
+        .cfg
+        .text_param_key = Some("TT".into());
+    apply_display(&state.targets[0], "a b", None).await.unwrap();
+    assert_eq!(mock.win_calls().await, ["TT=a%20b"]);
+}
+
+#[tokio::test]
+async fn missing_text_is_not_confirmed() {
+    let (mock, state) = setup().await;
+    mock.set_faults(Faults {
+        drop_text: true,
+        ..Default::default()
+    })
+    .await;
+    let err = apply_display(&state.targets[0], "kwijt", None)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, WledError::NotConfirmed), "{err}");
+}
+
+#[tokio::test]
+async fn device_errors_are_reported() {
+    let (mock, state) = setup().await;
+    mock.set_faults(Faults {
+        offline: true,
+        ..Default::default()
+    })
+    .await;
+    let err = apply_display(&state.targets[0], "weg", None)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err, WledError::Status { status: 503, .. }),
+        "{err}"
+    );
+}
+
+#[tokio::test]
+async fn long_text_is_cut_to_what_the_chip_keeps() {
+    let (mock, state) = setup_with(MockWled::new(MockConfig {
+        arch: "esp8266".into(),
+        ..Default::default()
+    }))
+    .await;
+    let text = "Lieve Anna en Bram, van harte gefeliciteerd met jullie trouwdag!";
+    apply_display(&state.targets[0], text, None)
+        .await
+        .expect("display confirmed");
+
+    assert_eq!(shown_text(&mock).await, &text[..32]);
+    assert_eq!(mock.received().await[0]["seg"][0]["n"], &text[..32]);
+    let check = state.targets[0].last_check.lock().await.clone().unwrap();
+    assert!(check.mismatches.is_empty(), "{:?}", check.mismatches);
+}
+
+#[tokio::test]
+async fn text_cut_shorter_by_the_device_still_counts() {
+    let (mock, state) = setup().await;
+    mock.set_faults(Faults {
+        name_len: 40,
+        ..Default::default()
+    })
+    .await;
+    let text = "Lieve Anna en Bram, van harte gefeliciteerd met jullie trouwdag!";
+    apply_display(&state.targets[0], text, None)
+        .await
+        .expect("a cut text is confirmed");
+    assert_eq!(shown_text(&mock).await, &text[..40]);
+
+    // but not a text cut to almost nothing
+    mock.set_faults(Faults {
+        name_len: 8,
+        ..Default::default()
+    })
+    .await;
+    let err = apply_display(&state.targets[0], text, None)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, WledError::NotConfirmed), "{err}");
+}
+
+#[tokio::test]
+async fn effect_list_that_fails_to_load_is_asked_again() {
+    let (mock, state) = setup().await;
+    let target = &state.targets[0];
+    mock.set_faults(Faults {
+        lists_status: 500,
+        ..Faults::default()
+    })
+    .await;
+    assert!(target.caps.discover(&target.wled).await.is_err());
+    assert!(target.caps.peek().await.is_none());
+
+    mock.set_faults(Faults::default()).await;
+    let caps = target.caps.get(&target.wled).await.unwrap();
+    assert_eq!(caps.text_effect, Some(122));
+
+    // a build without the lists is fine, and remembered as such
+    mock.set_faults(Faults {
+        lists_status: 404,
+        ..Faults::default()
+    })
+    .await;
+    let caps = target.caps.discover(&target.wled).await.unwrap();
+    assert_eq!(caps.text_effect, None);
+    assert_eq!(caps.color1_palette, None);
+}
@@ -0,0 +1,115 @@
+//! Tests against the bundled mock WLED device.
+
+mod audit;
+mod cli;
+mod config;
+mod display;
+mod rotation;
+mod router;
+mod schedule;
+mod settings;
+mod shutdown;
+mod theme;
+mod tls;
+mod wled_ws;
+
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+
+use crate::{
+    build_state,
+    mock_wled::MockWled,
+    schedule::Policy,
+    session,
+    settings::Settings,
+    transport::{Transport, WledClient},
+    AppConfig, AppState, TargetConfig, TargetMode,
+};
+
+/// The admin every test config has, as (name, password).
+const ADMIN: (&str, &str) = ("admin", "admin-password");
+/// A second admin, to tell who did what.
+const BART: (&str, &str) = ("Bart", "bart-password");
+
+/// `Authorization` header value signing in as [`ADMIN`].
+fn admin_login() -> String {
+    login_as(ADMIN)
+}
+
+fn login_as((user, password): (&str, &str)) -> String {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    format!("Basic {}", STANDARD.encode(format!("{user}:{password}")))
+}
+
+/// A mock device and an app state with one `direct` target pointing at it.
+async fn setup() -> (MockWled, AppState) {
+    setup_with(MockWled::default()).await
+}
+
+/// [`setup`] with a mock device of the test's own.
+async fn setup_with(mock: MockWled) -> (MockWled, AppState) {
+    let addr = mock
+        .spawn(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .await
+        .expect("bind mock WLED");
+    let state = build_state(&config(addr)).expect("build state");
+    state.targets[0].online.set(true);
+    (mock, state)
+}
+
+/// Like [`setup`], but the device is called in-process, so the test can run on tokio's paused
+/// clock without network I/O letting time jump ahead.
+fn setup_local() -> (MockWled, AppState) {
+    setup_local_with(&config(SocketAddr::from(([127, 0, 0, 1], 9))))
+}
+
+/// [`setup_local`] with a config of the test's own.
+fn setup_local_with(cfg: &AppConfig) -> (MockWled, AppState) {
+    let mock = MockWled::default();
+    let mut state = build_state(cfg).expect("build state");
+    let target = &mut Arc::get_mut(&mut state.targets).expect("state not shared yet")[0];
+    target.wled = WledClient::local(mock.router());
+    target.online.set(true);
+    (mock, state)
+}
+
+fn config(wled: SocketAddr) -> AppConfig {
+    AppConfig {
+        bind_addr: SocketAddr::from(([127, 0, 0, 1], 0)),
+        targets: vec![TargetConfig {
+            name: "curtain".into(),
+            ssh_host: String::new(),
+            ssh_user: None,
+            wled_host: wled.ip().to_string(),
+            wled_port: wled.port(),
+            local_tunnel_port: 0,
+            text_param_key: None,
+            text_preset_id: None,
+            mode: TargetMode::Queue,
+            transport: Transport::Direct,
+        }],
+        agent_token: None,
+        queue_policy: Policy::default(),
+        queue_weights: HashMap::new(),
+        // nothing is written there unless a test adds access codes
+        data_dir: std::env::temp_dir().join(format!("trouw-gordijn-test-{}", session::new_token())),
+        access_codes: Vec::new(),
+        admins: vec![
+            (ADMIN.0.into(), ADMIN.1.into()),
+            (BART.0.into(), BART.1.into()),
+        ],
+        public_url: Some("https://gordijn.test".into()),
+        trusted_proxies: Vec::new(),
+        settings: Settings::default(),
+        goodnight: None,
+        shutdown_timeout: std::time::Duration::from_secs(10),
+        tls: Default::default(),
+    }
+}
+
+/// Segment 0's name on the mock device.
+async fn shown_text(mock: &MockWled) -> String {
+    mock.state().await["seg"][0]["n"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string()
+}
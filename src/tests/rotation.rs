@@ -0,0 +1,138 @@
+use std::time::{Duration, Instant};
+
+use super::{setup, shown_text};
+use crate::{
+    audit::{Action, Filter},
+    mock_wled::Faults,
+    rotation::{self, Enqueued, QueuedMessage},
+};
+
+fn queued(id: u64, text: &str) -> QueuedMessage {
+    QueuedMessage {
+        id,
+        text: text.into(),
+        color: None,
+        ..Default::default()
+    }
+}
+
+/// Polls until `cond` holds, for at most five seconds.
+async fn wait_for<F, Fut>(mut cond: F) -> bool
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = bool>,
+{
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Instant::now() < deadline {
+        if cond().await {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    false
+}
+
+#[tokio::test]
+async fn actor_shows_queued_message() {
+    let (mock, state) = setup().await;
+    let rotation = state.rotation(0).clone();
+    tokio::spawn(rotation::run(state.clone(), 0));
+    rotation.enqueue(queued(1, "eerste")).await;
+
+    assert!(wait_for(|| async { shown_text(&mock).await == "eerste" }).await);
+    assert!(wait_for(|| async { rotation.snapshot().current.is_some_and(|c| c.id == 1) }).await);
+    assert!(rotation.snapshot().queue.is_empty());
+}
+
+#[tokio::test]
+async fn actor_holds_queue_while_offline() {
+    let (mock, state) = setup().await;
+    state.targets[0].online.set(false);
+    let rotation = state.rotation(0).clone();
+    tokio::spawn(rotation::run(state.clone(), 0));
+    assert_eq!(rotation.enqueue(queued(1, "wacht")).await, Enqueued::Queued);
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(mock.received().await.is_empty());
+    assert_eq!(rotation.snapshot().queue.len(), 1);
+
+    state.targets[0].online.set(true);
+    assert!(wait_for(|| async { shown_text(&mock).await == "wacht" }).await);
+}
+
+#[tokio::test]
+async fn unreachable_curtain_requeues_the_message() {
+    let (mock, state) = setup().await;
+    mock.set_faults(Faults {
+        offline: true,
+        ..Default::default()
+    })
+    .await;
+    let rotation = state.rotation(0).clone();
+    tokio::spawn(rotation::run(state.clone(), 0));
+    rotation.enqueue(queued(1, "terug")).await;
+
+    let offline = || async { !state.targets[0].online.get() };
+    assert!(wait_for(offline).await);
+    let requeued = || async {
+        let snapshot = rotation.snapshot();
+        snapshot.current.is_none() && snapshot.queue.first().map(|m| m.id) == Some(1)
+    };
+    assert!(wait_for(requeued).await);
+}
+
+#[tokio::test]
+async fn text_that_never_shows_is_given_up_on() {
+    let (mock, state) = setup().await;
+    mock.set_faults(Faults {
+        drop_text: true,
+        ..Default::default()
+    })
+    .await;
+    let rotation = state.rotation(0).clone();
+    tokio::spawn(rotation::run(state.clone(), 0));
+    rotation.enqueue(queued(1, "anders")).await;
+
+    let dropped = || async {
+        !state
+            .audit
+            .query(&Filter {
+                action: Some(Action::Remove),
+                ..Default::default()
+            })
+            .is_empty()
+    };
+    assert!(wait_for(dropped).await);
+    let snapshot = rotation.snapshot();
+    assert!(snapshot.current.is_none() && snapshot.queue.is_empty());
+    // it answered every time, so it stays online
+    assert!(state.targets[0].online.get());
+    let tries = mock
+        .received()
+        .await
+        .iter()
+        .filter(|r| r["seg"][0]["n"] == "anders")
+        .count();
+    assert_eq!(tries, rotation::MAX_ATTEMPTS as usize);
+}
+
+#[tokio::test]
+async fn actor_answers_while_the_curtain_is_slow() {
+    let (mock, state) = setup().await;
+    mock.set_faults(Faults {
+        latency_ms: 3000,
+        ..Default::default()
+    })
+    .await;
+    let rotation = state.rotation(0).clone();
+    tokio::spawn(rotation::run(state.clone(), 0));
+    rotation.enqueue(queued(1, "traag")).await;
+    assert!(wait_for(|| async { rotation.snapshot().current.is_some() }).await);
+
+    // the first message is still being pushed
+    let started = Instant::now();
+    assert_eq!(rotation.enqueue(queued(2, "snel")).await, Enqueued::Queued);
+    rotation.hold(true).await;
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert!(rotation.snapshot().held);
+}
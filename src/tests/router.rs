@@ -0,0 +1,930 @@
+//! The HTTP API and the rotation actor together, on tokio's paused clock.
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use futures_util::StreamExt;
+use serde_json::Value;
+use tokio::time;
+use tower::ServiceExt;
+
+use super::{admin_login, config, login_as, setup_local, setup_local_with, shown_text, BART};
+use crate::{
+    access::AccessCode, mock_wled::MockWled, rotation, router, transport::WledClient, AppState,
+    TargetConfig,
+};
+
+struct App {
+    mock: MockWled,
+    state: AppState,
+    router: Router,
+}
+
+/// The router from `main` with its rotation actor running.
+fn start() -> App {
+    let (mock, state) = setup_local();
+    tokio::spawn(rotation::run(state.clone(), 0));
+    App {
+        mock,
+        router: router(state.clone()),
+        state,
+    }
+}
+
+impl App {
+    /// Sends `req`, signed in as the admin unless it says otherwise; guest routes ignore that.
+    async fn call(&self, mut req: Request<Body>) -> (StatusCode, String) {
+        if !req.headers().contains_key(header::AUTHORIZATION) {
+            req.headers_mut()
+                .insert(header::AUTHORIZATION, admin_login().parse().unwrap());
+        }
+        let res = self.router.clone().oneshot(req).await.unwrap();
+        let status = res.status();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    async fn post(&self, uri: &str, form: &str) -> (StatusCode, String) {
+        self.post_as(None, uri, form).await
+    }
+
+    /// POSTs a form, with the `guest` browser's session cookie if given.
+    async fn post_as(&self, guest: Option<&str>, uri: &str, form: &str) -> (StatusCode, String) {
+        let mut req =
+            Request::post(uri).header(header::CONTENT_TYPE, "application/x-www-form-urlencoded");
+        if let Some(token) = guest {
+            req = req.header(header::COOKIE, format!("gordijn_session={token}"));
+        }
+        self.call(req.body(Body::from(form.to_string())).unwrap())
+            .await
+    }
+
+    async fn send(&self, text: &str) -> Value {
+        self.send_as(None, text).await
+    }
+
+    async fn send_as(&self, guest: Option<&str>, text: &str) -> Value {
+        let (status, body) = self
+            .post_as(guest, "/api/message", &format!("text={text}"))
+            .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        serde_json::from_str(&body).unwrap()
+    }
+
+    async fn mine(&self, guest: &str) -> Vec<Value> {
+        let req = Request::get("/api/my/messages")
+            .header(header::COOKIE, format!("gordijn_session={guest}"))
+            .body(Body::empty())
+            .unwrap();
+        let (status, body) = self.call(req).await;
+        assert_eq!(status, StatusCode::OK);
+        let body: Value = serde_json::from_str(&body).unwrap();
+        body["items"].as_array().unwrap().clone()
+    }
+
+    async fn queue(&self) -> Value {
+        let (status, body) = self
+            .call(Request::get("/api/queue").body(Body::empty()).unwrap())
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        serde_json::from_str(&body).unwrap()
+    }
+
+    /// `GET /api/admin/audit?{query}`: the entries, newest first.
+    async fn audit(&self, query: &str) -> Vec<Value> {
+        let (status, body) = self
+            .call(
+                Request::get(format!("/api/admin/audit?{query}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let body: Value = serde_json::from_str(&body).unwrap();
+        body["entries"].as_array().unwrap().clone()
+    }
+
+    async fn current_text(&self) -> Option<String> {
+        self.queue().await["current"]["text"]
+            .as_str()
+            .map(str::to_string)
+    }
+
+    async fn queued_texts(&self) -> Vec<String> {
+        self.queue().await["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["text"].as_str().unwrap().to_string())
+            .collect()
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn enqueued_message_is_shown() {
+    let app = start();
+    assert_eq!(app.send("eerste").await["status"], "queued");
+
+    time::sleep(Duration::from_secs(1)).await;
+    assert_eq!(app.current_text().await.as_deref(), Some("eerste"));
+    assert!(app.queued_texts().await.is_empty());
+    assert_eq!(shown_text(&app.mock).await, "eerste");
+}
+
+#[tokio::test(start_paused = true)]
+async fn messages_rotate_every_minute() {
+    let app = start();
+    app.send("een").await;
+    app.send("twee").await;
+    app.send("drie").await;
+
+    time::sleep(Duration::from_secs(1)).await;
+    assert_eq!(app.current_text().await.as_deref(), Some("een"));
+    assert_eq!(app.queued_texts().await, ["twee", "drie"]);
+
+    time::sleep(Duration::from_secs(55)).await;
+    assert_eq!(app.current_text().await.as_deref(), Some("een"));
+
+    time::sleep(Duration::from_secs(5)).await;
+    assert_eq!(app.current_text().await.as_deref(), Some("twee"));
+    assert_eq!(app.queued_texts().await, ["drie"]);
+
+    time::sleep(Duration::from_secs(61)).await;
+    assert_eq!(app.current_text().await.as_deref(), Some("drie"));
+    assert_eq!(shown_text(&app.mock).await, "drie");
+}
+
+#[tokio::test(start_paused = true)]
+async fn switches_immediately_after_a_minute_alone() {
+    let app = start();
+    app.send("oud").await;
+    time::sleep(Duration::from_secs(30)).await;
+    // still within its minute: the new one waits
+    assert_eq!(app.send("te vroeg").await["status"], "queued");
+    app.post("/api/admin/remove", "id=2").await;
+
+    time::sleep(Duration::from_secs(31)).await;
+    assert_eq!(app.send("nieuw").await["status"], "switched");
+    assert_eq!(app.current_text().await.as_deref(), Some("nieuw"));
+    assert!(app.queued_texts().await.is_empty());
+    assert_eq!(shown_text(&app.mock).await, "nieuw");
+}
+
+#[tokio::test(start_paused = true)]
+async fn removing_current_starts_next() {
+    let app = start();
+    app.send("weg").await;
+    app.send("volgende").await;
+    time::sleep(Duration::from_secs(1)).await;
+    assert_eq!(app.current_text().await.as_deref(), Some("weg"));
+
+    let (status, _) = app.post("/api/admin/remove", "id=1").await;
+    assert_eq!(status, StatusCode::OK);
+    time::sleep(Duration::from_secs(1)).await;
+    assert_eq!(app.current_text().await.as_deref(), Some("volgende"));
+    assert_eq!(shown_text(&app.mock).await, "volgende");
+}
+
+#[tokio::test(start_paused = true)]
+async fn removing_queued_message() {
+    let app = start();
+    app.send("eerst").await;
+    app.send("blijft").await;
+    app.send("weg").await;
+    app.post("/api/admin/remove", "id=3").await;
+    // the actor shows the first message right away; the removal is visible at once
+    assert_eq!(app.current_text().await.as_deref(), Some("eerst"));
+    assert_eq!(app.queued_texts().await, ["blijft"]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn empty_queue_keeps_last_message() {
+    let app = start();
+    let q = app.queue().await;
+    assert!(q["current"].is_null());
+    assert_eq!(q["items"].as_array().map(Vec::len), Some(0));
+
+    app.send("laatste").await;
+    time::sleep(Duration::from_secs(300)).await;
+    assert_eq!(app.current_text().await.as_deref(), Some("laatste"));
+    assert_eq!(shown_text(&app.mock).await, "laatste");
+    // one update, not one per minute
+    assert_eq!(app.mock.received().await.len(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn display_clock_stops_while_offline() {
+    let app = start();
+    app.send("pauze").await;
+    app.send("daarna").await;
+    time::sleep(Duration::from_secs(30)).await;
+
+    app.state.targets[0].online.set(false);
+    time::sleep(Duration::from_secs(120)).await;
+    assert_eq!(app.queue().await["paused"], true);
+    assert_eq!(app.current_text().await.as_deref(), Some("pauze"));
+
+    app.state.targets[0].online.set(true);
+    time::sleep(Duration::from_secs(20)).await;
+    assert_eq!(app.current_text().await.as_deref(), Some("pauze"));
+    time::sleep(Duration::from_secs(12)).await;
+    assert_eq!(app.current_text().await.as_deref(), Some("daarna"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn skip_starts_next_message() {
+    let app = start();
+    app.send("lang").await;
+    time::sleep(Duration::from_secs(1)).await;
+    // nothing waiting: the current message stays
+    let (status, _) = app.post("/api/admin/skip", "").await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    app.send("kort").await;
+    let (status, _) = app.post("/api/admin/skip", "").await;
+    assert_eq!(status, StatusCode::OK);
+    time::sleep(Duration::from_secs(1)).await;
+    assert_eq!(app.current_text().await.as_deref(), Some("kort"));
+    assert_eq!(shown_text(&app.mock).await, "kort");
+}
+
+#[tokio::test(start_paused = true)]
+async fn hold_freezes_rotation() {
+    let app = start();
+    app.send("vast").await;
+    app.send("later").await;
+    time::sleep(Duration::from_secs(1)).await;
+
+    app.post("/api/admin/hold", "held=true").await;
+    let q = app.queue().await;
+    assert_eq!(q["held"], true);
+    assert_eq!(q["paused"], true);
+    time::sleep(Duration::from_secs(600)).await;
+    assert_eq!(app.current_text().await.as_deref(), Some("vast"));
+
+    app.post("/api/admin/hold", "held=false").await;
+    time::sleep(Duration::from_secs(60)).await;
+    assert_eq!(app.current_text().await.as_deref(), Some("later"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn queue_reports_estimated_display_time() {
+    let app = start();
+    app.send("een").await;
+    app.send("twee").await;
+    app.send("drie").await;
+    time::sleep(Duration::from_secs(10)).await;
+
+    let etas: Vec<_> = app.queue().await["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m["eta_seconds"].as_u64())
+        .collect();
+    assert_eq!(etas, [Some(50), Some(110)]);
+
+    app.post("/api/admin/hold", "held=true").await;
+    assert!(app.queue().await["items"][0]["eta_seconds"].is_null());
+}
+
+const ANNA: &str = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+const BERT: &str = "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+
+#[tokio::test(start_paused = true)]
+async fn send_reports_position_and_eta() {
+    let app = start();
+    app.send("een").await;
+    time::sleep(Duration::from_secs(20)).await;
+    let res = app.send("twee").await;
+    assert_eq!(res["position"], 1);
+    assert_eq!(res["eta_seconds"], 40);
+    let res = app.send("drie").await;
+    assert_eq!(res["position"], 2);
+    assert_eq!(res["eta_seconds"], 100);
+}
+
+#[tokio::test(start_paused = true)]
+async fn first_message_sets_session_cookie() {
+    let app = start();
+    let req = Request::post("/api/message")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from("text=hoi"))
+        .unwrap();
+    let res = app.router.clone().oneshot(req).await.unwrap();
+    let cookie = res.headers()[header::SET_COOKIE].to_str().unwrap();
+    assert!(cookie.starts_with("gordijn_session="), "{cookie}");
+
+    // a known browser keeps its token
+    let req = Request::post("/api/message")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .header(header::COOKIE, format!("gordijn_session={ANNA}"))
+        .body(Body::from("text=weer"))
+        .unwrap();
+    let res = app.router.clone().oneshot(req).await.unwrap();
+    assert!(res.headers().get(header::SET_COOKIE).is_none());
+}
+
+#[tokio::test(start_paused = true)]
+async fn guests_see_only_their_own_messages() {
+    let app = start();
+    app.send_as(Some(ANNA), "van anna").await;
+    app.send_as(Some(BERT), "van bert").await;
+    app.send_as(Some(ANNA), "nog een").await;
+    time::sleep(Duration::from_secs(1)).await;
+
+    let mine = app.mine(ANNA).await;
+    let texts: Vec<_> = mine.iter().map(|m| m["text"].as_str().unwrap()).collect();
+    assert_eq!(texts, ["van anna", "nog een"]);
+    assert_eq!(mine[0]["status"], "showing");
+    assert_eq!(mine[1]["status"], "waiting");
+    assert_eq!(mine[1]["position"], 2);
+    assert_eq!(app.mine(BERT).await.len(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn guest_can_edit_and_cancel_waiting_message() {
+    let app = start();
+    app.send_as(Some(ANNA), "eerst").await;
+    let id = app.send_as(Some(ANNA), "tikfuot").await["id"]
+        .as_u64()
+        .unwrap();
+    time::sleep(Duration::from_secs(1)).await;
+
+    // someone else's browser can't touch it
+    let (status, _) = app
+        .post_as(
+            Some(BERT),
+            "/api/my/messages/edit",
+            &format!("id={id}&text=weg"),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = app
+        .post_as(
+            Some(ANNA),
+            "/api/my/messages/edit",
+            &format!("id={id}&text=tikfout"),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.queued_texts().await, ["tikfout"]);
+
+    // the one on screen can't be withdrawn any more
+    let (status, _) = app
+        .post_as(Some(ANNA), "/api/my/messages/cancel", "id=1")
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app
+        .post_as(Some(ANNA), "/api/my/messages/cancel", &format!("id={id}"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(app.queued_texts().await.is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn guest_is_told_when_their_message_goes_live() {
+    let app = start();
+    let req = Request::get("/api/my/events")
+        .header(header::COOKIE, format!("gordijn_session={ANNA}"))
+        .body(Body::empty())
+        .unwrap();
+    let res = app.router.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let mut events = res.into_body().into_data_stream();
+
+    app.send_as(Some(BERT), "eerst bert").await;
+    let id = app.send_as(Some(ANNA), "dan anna").await["id"].clone();
+    time::sleep(Duration::from_secs(61)).await;
+
+    let frame = events.next().await.unwrap().unwrap();
+    let frame = String::from_utf8(frame.to_vec()).unwrap();
+    assert!(frame.starts_with("event: live\n"), "{frame}");
+    let data: Value = serde_json::from_str(
+        frame
+            .lines()
+            .find_map(|l| l.strip_prefix("data: "))
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(data["id"], id);
+    assert_eq!(data["text"], "dan anna");
+}
+
+#[tokio::test(start_paused = true)]
+async fn access_codes_guard_the_guest_page() {
+    let app = start();
+    // open until the first code exists
+    let (status, _) = app
+        .call(Request::get("/").body(Body::empty()).unwrap())
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = app
+        .post("/api/admin/access", "label=tafels&code=tafel-code-1")
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let (status, _) = app
+        .call(Request::get("/").body(Body::empty()).unwrap())
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = app.post("/api/message", "text=stiekem").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = app
+        .call(Request::get("/api/preview/ws").body(Body::empty()).unwrap())
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // the code from the QR code becomes a cookie
+    let req = Request::get("/?code=tafel-code-1")
+        .body(Body::empty())
+        .unwrap();
+    let res = app.router.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::SEE_OTHER);
+    let cookie = res.headers()[header::SET_COOKIE].to_str().unwrap();
+    let cookie = cookie.split(';').next().unwrap().to_string();
+    assert_eq!(cookie, "gordijn_access=tafel-code-1");
+
+    let message = || {
+        Request::post("/api/message")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .header(header::COOKIE, cookie.clone())
+            .body(Body::from("text=hallo"))
+            .unwrap()
+    };
+    assert_eq!(app.call(message()).await.0, StatusCode::OK);
+
+    app.post("/api/admin/access/revoke", "code=tafel-code-1")
+        .await;
+    assert_eq!(app.call(message()).await.0, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test(start_paused = true)]
+async fn expired_access_code_is_refused() {
+    let app = start();
+    app.state
+        .access
+        .add(AccessCode {
+            code: "verlopen-code".into(),
+            label: "gisteren".into(),
+            created: 0,
+            expires: Some(1),
+            revoked: false,
+        })
+        .await
+        .unwrap();
+    let req = Request::get("/?code=verlopen-code")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(app.call(req).await.0, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test(start_paused = true)]
+async fn access_code_qr_is_svg() {
+    let app = start();
+    app.post("/api/admin/access", "code=tafel-code-1").await;
+    let req = Request::get("/api/admin/access/qr?code=tafel-code-1")
+        .body(Body::empty())
+        .unwrap();
+    let (status, body) = app.call(req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("<svg"), "{body}");
+}
+
+#[tokio::test(start_paused = true)]
+async fn access_code_qr_as_png() {
+    let app = start();
+    app.post("/api/admin/access", "code=tafel-code-1").await;
+    let req = Request::get("/api/admin/access/qr?code=tafel-code-1&format=png")
+        .header(header::AUTHORIZATION, admin_login())
+        .body(Body::empty())
+        .unwrap();
+    let res = app.router.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CONTENT_TYPE], "image/png");
+    let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert!(body.starts_with(b"\x89PNG\r\n\x1a\n"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn no_cards_for_codes_that_dont_let_guests_in() {
+    let app = start();
+    let get = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+    app.post("/api/admin/access", "code=tafel-code-1").await;
+    app.post("/api/admin/access/revoke", "code=tafel-code-1")
+        .await;
+    for uri in [
+        "/api/admin/access/qr?code=tafel-code-2",
+        "/admin/access/cards?code=tafel-code-2",
+    ] {
+        assert_eq!(app.call(get(uri)).await.0, StatusCode::NOT_FOUND, "{uri}");
+    }
+    for uri in [
+        "/api/admin/access/qr?code=tafel-code-1",
+        "/admin/access/cards?code=tafel-code-1",
+    ] {
+        assert_eq!(app.call(get(uri)).await.0, StatusCode::GONE, "{uri}");
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn access_code_lifetime_that_overflows_is_refused() {
+    let app = start();
+    let (status, _) = app
+        .post(
+            "/api/admin/access",
+            &format!("code=tafel-code-1&hours={}", u64::MAX / 1000),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(app.state.access.all().await.is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn display_time_change_applies_to_running_rotation() {
+    let app = start();
+    app.send("een").await;
+    app.send("twee").await;
+    time::sleep(Duration::from_secs(10)).await;
+
+    let (status, body) = app.post("/api/admin/settings", "display_seconds=20").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    time::sleep(Duration::from_secs(1)).await;
+    assert_eq!(app.queue().await["items"][0]["eta_seconds"], 9);
+    time::sleep(Duration::from_secs(10)).await;
+    assert_eq!(app.current_text().await.as_deref(), Some("twee"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn settings_changes_are_audited_and_kept() {
+    let app = start();
+    let (status, body) = app
+        .post("/api/admin/settings", "brightness=200&max_text_len=4")
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    // pushed to the device right away
+    assert_eq!(app.mock.state().await["bri"], 200);
+
+    let (status, _) = app.post("/api/message", "text=te+lang").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, body) = app.post("/api/admin/settings", "brightness=0").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+
+    let (_, body) = app
+        .call(
+            Request::get("/api/admin/settings")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    let body: Value = serde_json::from_str(&body).unwrap();
+    let audit = body["audit"].as_array().unwrap();
+    assert_eq!(audit.len(), 2);
+    assert!(audit
+        .iter()
+        .all(|c| c["actor"] == "admin:admin" && c["detail"]["old"] != c["detail"]["new"]));
+    assert_eq!(audit[0]["detail"]["key"], "max_text_len");
+    assert_eq!(audit[0]["detail"]["new"], 4);
+}
+
+#[tokio::test(start_paused = true)]
+async fn clear_keeps_message_on_screen() {
+    let app = start();
+    app.send("vast").await;
+    app.send("weg").await;
+    app.send("ook weg").await;
+    let (status, body) = app.post("/api/admin/clear", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(serde_json::from_str::<Value>(&body).unwrap()["removed"], 2);
+    assert_eq!(app.current_text().await.as_deref(), Some("vast"));
+    assert!(app.queued_texts().await.is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn export_leaves_out_session_tokens() {
+    let app = start();
+    app.send_as(Some(ANNA), "een").await;
+    app.send_as(Some(ANNA), "twee").await;
+    let (status, body) = app
+        .call(
+            Request::get("/api/admin/export")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!body.contains(ANNA), "{body}");
+    let export: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(export["rotations"][0]["current"]["text"], "een");
+    assert_eq!(export["rotations"][0]["queue"][0]["text"], "twee");
+    assert_eq!(export["settings"]["display_seconds"], 60);
+}
+
+impl App {
+    /// A sample's value from `/metrics`, 0 when it hasn't been recorded yet.
+    async fn metric(&self, sample: &str) -> f64 {
+        let (status, body) = self
+            .call(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        body.lines()
+            .find_map(|l| l.strip_prefix(sample)?.strip_prefix(' '))
+            .map_or(0.0, |v| v.parse().unwrap())
+    }
+}
+
+// Other tests count into the same registry, so only increases are checked.
+#[tokio::test(start_paused = true)]
+async fn metrics_count_submissions_and_displays() {
+    let app = start();
+    let submitted = r#"gordijn_messages_submitted_total{source="guest"}"#;
+    let rejected = r#"gordijn_messages_rejected_total{reason="invalid_text"}"#;
+    let displayed = r#"gordijn_messages_displayed_total{rotation="curtain"}"#;
+    let waits = r#"gordijn_queue_wait_seconds_count{rotation="curtain"}"#;
+    let before = [
+        app.metric(submitted).await,
+        app.metric(rejected).await,
+        app.metric(displayed).await,
+        app.metric(waits).await,
+    ];
+
+    app.send("een").await;
+    app.send("twee").await;
+    let (status, _) = app.post("/api/message", "text=+").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    time::sleep(Duration::from_secs(61)).await;
+
+    assert!(app.metric(submitted).await >= before[0] + 2.0);
+    assert!(app.metric(rejected).await >= before[1] + 1.0);
+    assert!(app.metric(displayed).await >= before[2] + 2.0);
+    assert!(app.metric(waits).await >= before[3] + 2.0);
+    assert!(app.metric("gordijn_uptime_seconds").await >= 0.0);
+    let (_, body) = app
+        .call(Request::get("/metrics").body(Body::empty()).unwrap())
+        .await;
+    assert!(
+        body.contains("gordijn_wled_request_duration_seconds_bucket{"),
+        "{body}"
+    );
+    assert!(body.ends_with("# EOF\n"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn audit_log_tells_who_removed_a_message() {
+    let app = start();
+    let guest = crate::session::new_token();
+    let shown = app.send_as(Some(&guest), "een").await["id"]
+        .as_u64()
+        .unwrap();
+    let removed = app.send_as(Some(&guest), "twee").await["id"]
+        .as_u64()
+        .unwrap();
+    time::sleep(Duration::from_secs(1)).await;
+    let (status, _) = app.post("/api/message", "text=").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let req = Request::post("/api/admin/remove")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .header(header::AUTHORIZATION, login_as(BART))
+        // a name the client claims is not taken for who signed in
+        .header("x-admin-name", "Arthur")
+        .body(Body::from(format!("id={removed}")))
+        .unwrap();
+    assert_eq!(app.call(req).await.0, StatusCode::OK);
+
+    let trail = app.audit(&format!("message_id={removed}")).await;
+    let actions: Vec<_> = trail.iter().map(|e| e["action"].clone()).collect();
+    assert_eq!(actions, ["remove", "submit"]);
+    assert_eq!(trail[0]["actor"], "admin:Bart");
+    let submitter = trail[1]["actor"].as_str().unwrap();
+    assert!(submitter.starts_with("guest:"));
+    // the session token itself stays out of the log
+    assert!(!submitter.contains(&guest));
+
+    let shown_trail = app
+        .audit(&format!("message_id={shown}&action=display"))
+        .await;
+    assert_eq!(shown_trail.len(), 1);
+    assert_eq!(shown_trail[0]["actor"], "rotation");
+    let rejected = app.audit("action=reject").await;
+    assert_eq!(rejected[0]["detail"]["reason"], "invalid_text");
+    // the removal, and Bart's and the test admin's sign-ins
+    assert_eq!(app.audit("actor=admin").await.len(), 3);
+
+    let (status, jsonl) = app
+        .call(
+            Request::get("/api/admin/audit/export?action=submit")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let lines: Vec<Value> = jsonl
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["message_id"], shown);
+}
+
+#[tokio::test(start_paused = true)]
+async fn admin_routes_need_an_admin() {
+    let app = start();
+    let anonymous = |method: &str, uri: &str| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .header(header::AUTHORIZATION, "Bearer not-the-password")
+            .body(Body::empty())
+            .unwrap()
+    };
+    for (method, uri) in [
+        ("GET", "/admin"),
+        ("GET", "/api/admin/access"),
+        ("POST", "/api/admin/access/revoke"),
+        ("GET", "/api/admin/access/qr?code=tafel-code-1"),
+        ("GET", "/admin/access/cards"),
+        ("GET", "/api/admin/preview/ws"),
+        ("POST", "/api/admin/settings"),
+        ("POST", "/api/admin/presets/activate"),
+        ("POST", "/api/admin/clear"),
+        ("POST", "/api/admin/hold"),
+        ("POST", "/api/admin/skip"),
+        ("GET", "/api/admin/export"),
+        ("POST", "/api/admin/theme"),
+        ("POST", "/api/admin/theme/photo"),
+    ] {
+        let res = app
+            .router
+            .clone()
+            .oneshot(anonymous(method, uri))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED, "{method} {uri}");
+        assert!(res.headers().contains_key(header::WWW_AUTHENTICATE));
+    }
+    let req = Request::post("/api/admin/hold")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from("held=true"))
+        .unwrap();
+    let res = app.router.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert!(!app.state.rotation(0).snapshot().held);
+
+    // the password alone, as scripts send it
+    let req = Request::post("/api/admin/hold")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .header(header::AUTHORIZATION, format!("Bearer {}", super::ADMIN.1))
+        .body(Body::from("held=true"))
+        .unwrap();
+    let (status, _) = app.call(req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(app.state.rotation(0).snapshot().held);
+}
+
+#[tokio::test(start_paused = true)]
+async fn admin_changes_from_other_sites_are_refused() {
+    let app = start();
+    let clear = |name: &str, value: &str| {
+        Request::post("/api/admin/clear")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .header(name, value)
+            .body(Body::empty())
+            .unwrap()
+    };
+    for (name, value) in [
+        ("origin", "https://evil.example"),
+        ("origin", "null"),
+        ("sec-fetch-site", "cross-site"),
+        ("sec-fetch-site", "same-site"),
+    ] {
+        let (status, _) = app.call(clear(name, value)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{name}: {value}");
+    }
+    for (name, value) in [
+        ("origin", "https://gordijn.test"),
+        ("sec-fetch-site", "same-origin"),
+    ] {
+        let (status, _) = app.call(clear(name, value)).await;
+        assert_eq!(status, StatusCode::OK, "{name}: {value}");
+    }
+    // reading is harmless
+    let req = Request::get("/api/admin/settings")
+        .header("sec-fetch-site", "cross-site")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(app.call(req).await.0, StatusCode::OK);
+}
+
+#[tokio::test(start_paused = true)]
+async fn message_goes_to_the_target_it_names() {
+    let mut cfg = config(SocketAddr::from(([127, 0, 0, 1], 9)));
+    cfg.targets.push(TargetConfig {
+        name: "bar".into(),
+        ..cfg.targets[0].clone()
+    });
+    let (curtain, mut state) = setup_local_with(&cfg);
+    let bar = MockWled::default();
+    let target = &mut Arc::get_mut(&mut state.targets).expect("state not shared yet")[1];
+    target.wled = WledClient::local(bar.router());
+    target.online.set(true);
+    for idx in 0..2 {
+        tokio::spawn(rotation::run(state.clone(), idx));
+    }
+    let app = App {
+        mock: curtain,
+        router: router(state.clone()),
+        state,
+    };
+
+    let (status, body) = app.post("/api/message", "text=proost&target=bar").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    time::sleep(Duration::from_secs(1)).await;
+    assert_eq!(shown_text(&bar).await, "proost");
+    assert_eq!(shown_text(&app.mock).await, "");
+    let (_, body) = app
+        .call(
+            Request::get("/api/queue?target=bar")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    let bar_queue: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(bar_queue["current"]["text"], "proost");
+    assert!(app.queue().await["current"].is_null());
+
+    let (status, body) = app.post("/api/message", "text=proost&target=tuin").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+}
+
+#[tokio::test(start_paused = true)]
+async fn preview_hands_the_curtain_back_to_the_rotation() {
+    let app = start();
+    app.send("hallo").await;
+    time::sleep(Duration::from_secs(1)).await;
+
+    let (status, _) = app
+        .post("/api/admin/presets/preview", "id=5&seconds=5")
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    time::sleep(Duration::from_secs(3)).await;
+    let (status, _) = app
+        .post("/api/admin/presets/preview", "id=6&seconds=10")
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let since_second = |received: Vec<Value>| -> Vec<Value> {
+        received
+            .into_iter()
+            .skip_while(|u| u["ps"] != 6)
+            .skip(1)
+            .collect()
+    };
+
+    // the first preview's time is up, but the second one still runs
+    time::sleep(Duration::from_secs(3)).await;
+    assert!(since_second(app.mock.received().await).is_empty());
+
+    time::sleep(Duration::from_secs(8)).await;
+    let restored = since_second(app.mock.received().await);
+    assert!(
+        restored.iter().any(|u| u["seg"][0]["n"] == "hallo"),
+        "{restored:?}"
+    );
+    assert_eq!(app.current_text().await.as_deref(), Some("hallo"));
+
+    let previews = app.audit("action=preset").await;
+    assert_eq!(previews.len(), 2);
+    assert_eq!(previews[0]["actor"], "admin:admin");
+    assert_eq!(previews[0]["detail"]["id"], 6);
+}
+
+#[tokio::test(start_paused = true)]
+async fn admin_sign_ins_are_audited() {
+    let app = start();
+    for _ in 0..3 {
+        app.queue().await;
+        app.audit("").await;
+    }
+    let req = Request::get("/api/admin/settings")
+        .header(header::AUTHORIZATION, login_as((BART.0, "geraden")))
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(app.call(req).await.0, StatusCode::UNAUTHORIZED);
+
+    let logins = app.audit("action=admin_login").await;
+    assert_eq!(logins.len(), 2, "{logins:?}");
+    assert_eq!(logins[0]["actor"], "guest");
+    assert_eq!(logins[0]["detail"]["ok"], false);
+    assert_eq!(logins[0]["detail"]["user"], BART.0);
+    // signed in once, however many requests followed
+    assert_eq!(logins[1]["actor"], "admin:admin");
+    assert_eq!(logins[1]["detail"]["ok"], true);
+}
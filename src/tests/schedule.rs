@@ -0,0 +1,88 @@
+use crate::{
+    rotation::QueuedMessage,
+    schedule::{Policy, Schedule},
+};
+
+fn msg(id: u64, sender: &str, weight: u32) -> QueuedMessage {
+    QueuedMessage {
+        id,
+        text: format!("{sender}{id}"),
+        sender: sender.into(),
+        weight,
+        ..Default::default()
+    }
+}
+
+fn order(schedule: &Schedule) -> Vec<u64> {
+    schedule.iter().map(|m| m.id).collect()
+}
+
+#[test]
+fn fifo_keeps_arrival_order() {
+    let mut s = Schedule::new(Policy::Fifo);
+    for id in 1..=3 {
+        s.push(msg(id, "tafel1", 1));
+    }
+    s.push(msg(4, "tafel2", 1));
+    assert_eq!(order(&s), [1, 2, 3, 4]);
+}
+
+#[test]
+fn round_robin_interleaves_senders() {
+    let mut s = Schedule::new(Policy::RoundRobin);
+    for id in 1..=4 {
+        s.push(msg(id, "tafel1", 1));
+    }
+    s.push(msg(5, "tafel2", 1));
+    s.push(msg(6, "tafel2", 1));
+    assert_eq!(order(&s), [1, 5, 2, 6, 3, 4]);
+}
+
+#[test]
+fn late_sender_is_not_pushed_to_the_back() {
+    let mut s = Schedule::new(Policy::RoundRobin);
+    for id in 1..=5 {
+        s.push(msg(id, "tafel1", 1));
+    }
+    assert_eq!(s.pop().map(|m| m.id), Some(1));
+    assert_eq!(s.pop().map(|m| m.id), Some(2));
+    // a newcomer goes right after the next turn, not behind tafel1's backlog
+    s.push(msg(6, "tafel2", 1));
+    assert_eq!(order(&s), [3, 6, 4, 5]);
+}
+
+#[test]
+fn weighted_sender_gets_more_turns() {
+    let mut s = Schedule::new(Policy::Weighted);
+    for id in 1..=4 {
+        s.push(msg(id, "gast", 1));
+    }
+    for id in 5..=8 {
+        s.push(msg(id, "getuige", 2));
+    }
+    assert_eq!(order(&s), [5, 1, 6, 7, 2, 8, 3, 4]);
+}
+
+#[test]
+fn removal_moves_senders_later_messages_up() {
+    let mut s = Schedule::new(Policy::RoundRobin);
+    for id in 1..=3 {
+        s.push(msg(id, "tafel1", 1));
+    }
+    s.push(msg(4, "tafel2", 1));
+    s.push(msg(5, "tafel2", 1));
+    assert_eq!(order(&s), [1, 4, 2, 5, 3]);
+    assert!(s.remove(1));
+    assert_eq!(order(&s), [2, 4, 3, 5]);
+    assert!(!s.remove(1));
+}
+
+#[test]
+fn requeued_message_goes_first() {
+    let mut s = Schedule::new(Policy::RoundRobin);
+    s.push(msg(1, "a", 1));
+    s.push(msg(2, "b", 1));
+    let first = s.pop().unwrap();
+    s.push_front(first);
+    assert_eq!(order(&s), [1, 2]);
+}
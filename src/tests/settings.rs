@@ -0,0 +1,59 @@
+use std::sync::Arc;
+
+use crate::{
+    audit::{Action, Actor, AuditLog, Filter},
+    session,
+    settings::{Settings, SettingsStore},
+};
+
+fn temp_dir() -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("trouw-gordijn-test-{}", session::new_token()))
+}
+
+#[tokio::test]
+async fn stored_settings_win_over_the_config() {
+    let dir = temp_dir();
+    let audit = Arc::new(AuditLog::load(dir.join("audit.jsonl")).unwrap());
+    let store = SettingsStore::load(dir.clone(), Settings::default(), audit.clone()).unwrap();
+    let changes = store
+        .update(&Actor::admin(Some("Arthur")), |s| {
+            s.brightness = 200;
+            s.presets.entry("bar".into()).or_default().scenes = vec![3, 5];
+        })
+        .await
+        .unwrap();
+    let keys: Vec<_> = changes.iter().map(|c| c.key.as_str()).collect();
+    assert_eq!(keys, ["brightness", "presets.bar.scenes"]);
+
+    // after a restart with a changed config, what was changed at runtime stays
+    let seed = Settings {
+        brightness: 50,
+        max_text_len: 48,
+        ..Settings::default()
+    };
+    // on disk before the "restart"
+    audit.export(&Filter::default()).await.unwrap();
+    let audit = Arc::new(AuditLog::load(dir.join("audit.jsonl")).unwrap());
+    let store = SettingsStore::load(dir, seed, audit.clone()).unwrap();
+    let settings = store.get();
+    assert_eq!(settings.brightness, 200);
+    assert_eq!(settings.max_text_len, 64);
+    assert_eq!(settings.presets("bar").scenes, [3, 5]);
+    let logged = audit.query(&Filter::default());
+    assert_eq!(logged.len(), 2);
+    assert!(logged
+        .iter()
+        .all(|e| e.action == Action::Settings && e.actor == "admin:Arthur"));
+}
+
+#[tokio::test]
+async fn invalid_and_empty_updates_change_nothing() {
+    let dir = temp_dir();
+    let audit = Arc::new(AuditLog::load(dir.join("audit.jsonl")).unwrap());
+    let store = SettingsStore::load(dir, Settings::default(), audit.clone()).unwrap();
+    let by = Actor::admin(None);
+    assert!(store.update(&by, |s| s.display_seconds = 1).await.is_err());
+    assert!(store.update(&by, |_| {}).await.unwrap().is_empty());
+    assert_eq!(store.get(), Settings::default());
+    assert!(audit.query(&Filter::default()).is_empty());
+}
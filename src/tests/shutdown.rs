@@ -0,0 +1,82 @@
+use std::{net::SocketAddr, sync::atomic::Ordering, time::Duration};
+
+use tokio::time;
+
+use super::{config, setup_local_with, shown_text};
+use crate::{
+    rotation::{self, QueuedMessage},
+    shutdown::{self, Goodnight},
+};
+
+fn message(id: u64, text: &str) -> QueuedMessage {
+    QueuedMessage {
+        id,
+        text: text.into(),
+        sender: format!("session:{id}"),
+        owner: format!("owner-{id}"),
+        weight: 1,
+        ..Default::default()
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn queue_survives_a_restart_and_curtain_says_goodnight() {
+    let cfg = config(SocketAddr::from(([127, 0, 0, 1], 9)));
+    let (mock, state) = setup_local_with(&cfg);
+    tokio::spawn(rotation::run(state.clone(), 0));
+    for (id, text) in [(3, "een"), (4, "twee"), (5, "drie")] {
+        state.rotation(0).enqueue(message(id, text)).await;
+    }
+    time::sleep(Duration::from_secs(1)).await;
+    assert_eq!(shown_text(&mock).await, "een");
+
+    let goodnight = Goodnight::Text("Welterusten".into());
+    shutdown::finish(&state, Some(&goodnight)).await;
+    assert_eq!(shown_text(&mock).await, "Welterusten");
+    // stopped: the next message doesn't replace the goodnight
+    time::sleep(Duration::from_secs(120)).await;
+    assert_eq!(shown_text(&mock).await, "Welterusten");
+
+    let (mock, state) = setup_local_with(&cfg);
+    tokio::spawn(rotation::run(state.clone(), 0));
+    assert_eq!(rotation::restore(&state).await.unwrap(), 3);
+    time::sleep(Duration::from_secs(1)).await;
+    let snapshot = state.rotation(0).snapshot();
+    assert_eq!(snapshot.current.as_ref().unwrap().text, "een");
+    assert_eq!(snapshot.current.as_ref().unwrap().owner, "owner-3");
+    let waiting: Vec<_> = snapshot.queue.iter().map(|m| m.id).collect();
+    assert_eq!(waiting, [4, 5]);
+    assert!(!snapshot.held);
+    assert_eq!(shown_text(&mock).await, "een");
+    // new messages don't reuse restored ids
+    assert_eq!(state.next_id.load(Ordering::Relaxed), 6);
+    // restored once only
+    assert_eq!(rotation::restore(&state).await.unwrap(), 0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn stuck_rotation_does_not_hold_up_shutdown() {
+    let cfg = config(SocketAddr::from(([127, 0, 0, 1], 9)));
+    // no actor running, so nothing answers the rotation's commands
+    let (mock, state) = setup_local_with(&cfg);
+    let started = time::Instant::now();
+    shutdown::finish(&state, Some(&Goodnight::Text("Welterusten".into()))).await;
+    assert!(started.elapsed() < Duration::from_secs(30));
+    assert_eq!(shown_text(&mock).await, "Welterusten");
+}
+
+#[test]
+fn goodnight_parses() {
+    assert_eq!("off".parse::<Goodnight>().unwrap(), Goodnight::Off);
+    assert_eq!(
+        "preset:12".parse::<Goodnight>().unwrap(),
+        Goodnight::Preset(12)
+    );
+    assert_eq!(
+        "text: Tot morgen!".parse::<Goodnight>().unwrap(),
+        Goodnight::Text("Tot morgen!".into())
+    );
+    for bad in ["preset:0", "text:", "dim"] {
+        assert!(bad.parse::<Goodnight>().is_err(), "{bad}");
+    }
+}
@@ -0,0 +1,128 @@
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+};
+use tower::ServiceExt;
+
+use super::{admin_login, setup_local};
+use crate::{
+    router,
+    theme::{render_index, Theme},
+};
+
+#[test]
+fn page_shows_the_theme() {
+    let plain = render_index(&Theme::default()).unwrap();
+    assert!(plain.contains("<title>Trouw Gordijn</title>"));
+    assert!(plain.contains(r#"data-i18n="subtitle""#));
+    assert!(plain.contains("--gold:#d4af37"));
+    assert!(!plain.contains("<img class=\"photo\""));
+
+    let themed = render_index(&Theme {
+        couple: "Anna & <Bram>".into(),
+        date: "2026-06-20".into(),
+        accent: "#aa3355".into(),
+        photo: "us.jpg".into(),
+        welcome: "Welkom!".into(),
+        ..Theme::default()
+    })
+    .unwrap();
+    // names are text, not markup
+    assert!(themed.contains("<h1 class=\"accent\">Anna &amp; &lt;Bram&gt;</h1>"));
+    assert!(themed.contains(r#"<time datetime="2026-06-20">20 juni 2026</time>"#));
+    assert!(themed.contains("--gold:#aa3355"));
+    assert!(themed.contains(r#"src="/theme/us.jpg""#));
+    assert!(themed.contains("<p class=\"sub\">Welkom!</p>"));
+    assert!(!themed.contains(r#"data-i18n="subtitle""#));
+    assert!(themed.contains(r#"data-i18n="footer""#));
+}
+
+#[test]
+fn bad_themes_are_rejected() {
+    let bad = [
+        Theme {
+            accent: "gold".into(),
+            ..Theme::default()
+        },
+        Theme {
+            date: "2026-02-30".into(),
+            ..Theme::default()
+        },
+        Theme {
+            photo: "../settings.json".into(),
+            ..Theme::default()
+        },
+        Theme {
+            couple: "x".repeat(81),
+            ..Theme::default()
+        },
+    ];
+    for theme in bad {
+        assert!(theme.validate().is_err(), "{theme:?}");
+    }
+    assert!(Theme::default().validate().is_ok());
+}
+
+fn upload(bytes: &[u8]) -> Request<Body> {
+    let mut body =
+        b"--x\r\nContent-Disposition: form-data; name=\"photo\"; filename=\"p\"\r\n\r\n".to_vec();
+    body.extend_from_slice(bytes);
+    body.extend_from_slice(b"\r\n--x--\r\n");
+    Request::post("/api/admin/theme/photo")
+        .header(header::CONTENT_TYPE, "multipart/form-data; boundary=x")
+        .header(header::AUTHORIZATION, admin_login())
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn uploaded_photo_replaces_the_last_one() {
+    let (_mock, state) = setup_local();
+    let app = router(state.clone());
+    let png = b"\x89PNG\r\n\x1a\nnot really a picture";
+
+    let res = app.clone().oneshot(upload(b"GIF89a")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = app.clone().oneshot(upload(png)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let first = state.settings.get().theme.photo;
+    assert!(first.ends_with(".png"), "{first}");
+
+    let res = app
+        .clone()
+        .oneshot(
+            Request::get(format!("/theme/{first}"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let served = axum::body::to_bytes(res.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(&served[..], png);
+    let page = app
+        .clone()
+        .oneshot(Request::get("/").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let page = axum::body::to_bytes(page.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert!(String::from_utf8_lossy(&page).contains(&format!("/theme/{first}")));
+
+    let res = app.clone().oneshot(upload(png)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let second = state.settings.get().theme.photo;
+    assert_ne!(first, second);
+    assert!(!state.data_dir.join("theme").join(&first).exists());
+
+    let changes = state.audit.query(&crate::audit::Filter {
+        action: Some(crate::audit::Action::Settings),
+        ..Default::default()
+    });
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[0].actor, "admin:admin");
+}
@@ -0,0 +1,42 @@
+use axum::http::Uri;
+
+use crate::tls::{https_location, TlsConfig, TlsMode, TlsStatus};
+
+#[test]
+fn redirect_keeps_host_and_path() {
+    let uri: Uri = "/admin?target=bar".parse().unwrap();
+    assert_eq!(
+        https_location("gordijn.example.com:80", 443, &uri),
+        "https://gordijn.example.com/admin?target=bar"
+    );
+    assert_eq!(
+        https_location("[::1]:8080", 8443, &uri),
+        "https://[::1]:8443/admin?target=bar"
+    );
+    assert_eq!(
+        https_location("[::1]", 443, &"/".parse().unwrap()),
+        "https://[::1]/"
+    );
+}
+
+#[test]
+fn status_reports_the_mode() {
+    let off = TlsStatus::new(TlsConfig::default()).to_json();
+    assert_eq!(off, serde_json::json!({ "mode": "off" }));
+
+    let acme = TlsStatus::new(TlsConfig {
+        mode: TlsMode::Acme {
+            domains: vec!["gordijn.example.com".into()],
+            contact: Some("bruid@example.com".into()),
+            cache_dir: "./acme-cache".into(),
+            staging: true,
+        },
+        https_port: 443,
+        http_port: Some(80),
+    })
+    .to_json();
+    assert_eq!(acme["mode"], "acme");
+    assert_eq!(acme["directory"], "staging");
+    assert_eq!(acme["domains"][0], "gordijn.example.com");
+    assert!(acme["last_event"].is_null());
+}
@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::json;
+use tokio::{net::TcpListener, time};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::wled_ws::WledSocket;
+
+/// A device socket that greets, swallows the first command and answers the rest with the
+/// state they asked for.
+async fn forgetful_device() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        let (tcp, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+        let greeting = json!({ "state": { "on": true, "bri": 128 } });
+        ws.send(Message::Text(greeting.to_string())).await.unwrap();
+        let mut first = true;
+        while let Some(Ok(Message::Text(text))) = ws.next().await {
+            if std::mem::take(&mut first) {
+                continue;
+            }
+            let command: serde_json::Value = serde_json::from_str(&text).unwrap();
+            let reply = json!({ "state": command });
+            ws.send(Message::Text(reply.to_string())).await.unwrap();
+        }
+    });
+    port
+}
+
+#[tokio::test]
+async fn lost_reply_does_not_shift_later_ones() {
+    let port = forgetful_device().await;
+    let socket = WledSocket::with_reply_timeout("127.0.0.1", port, Duration::from_millis(200));
+    tokio::spawn(socket.clone().run());
+    while !socket.is_connected().await {
+        time::sleep(Duration::from_millis(10)).await;
+    }
+
+    assert!(socket.send_state(&json!({ "bri": 1 })).await.is_err());
+    for bri in [2, 3] {
+        let state = socket.send_state(&json!({ "bri": bri })).await.unwrap();
+        assert_eq!(state["bri"], bri);
+    }
+}
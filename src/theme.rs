@@ -0,0 +1,291 @@
+//! The guest page's look: the couple's names, the wedding date, accent colours, a photo and the
+//! welcome and footer texts.
+//!
+//! The theme is part of the runtime settings, so it starts out from the config, is edited from
+//! the admin page (changes go into the audit log) and shows from the next page load. The page
+//! is `assets/index.html` rendered with minijinja; uploaded photos are kept in `theme/` in the
+//! data directory and served from `/theme/`.
+
+use std::{path::Path, sync::OnceLock};
+
+use axum::{
+    extract::{Multipart, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Form,
+};
+use minijinja::{context, Environment};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{audit::Client, session, AppState};
+
+/// Largest photo upload.
+pub const MAX_PHOTO_BYTES: usize = 10 * 1024 * 1024;
+/// Uploaded photos are named `photo-<random>.<ext>`; only those are deleted when replaced.
+const UPLOAD_PREFIX: &str = "photo-";
+const MONTHS: [&str; 12] = [
+    "januari",
+    "februari",
+    "maart",
+    "april",
+    "mei",
+    "juni",
+    "juli",
+    "augustus",
+    "september",
+    "oktober",
+    "november",
+    "december",
+];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    /// E.g. "Anna & Bram"; the page says "Trouw Gordijn" while empty.
+    pub couple: String,
+    /// `YYYY-MM-DD`, or empty.
+    pub date: String,
+    /// `#rrggbb` for headings, buttons and highlights.
+    pub accent: String,
+    /// `#rrggbb` behind the page.
+    pub background: String,
+    /// File name in the data directory's `theme/`, or empty for no photo. Checked to be safe
+    /// in a URL path as it is.
+    pub photo: String,
+    /// Replaces the translated subtitle when set.
+    pub welcome: String,
+    /// Replaces the translated footer when set.
+    pub footer: String,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            couple: String::new(),
+            date: String::new(),
+            accent: "#d4af37".into(),
+            background: "#101014".into(),
+            photo: String::new(),
+            welcome: String::new(),
+            footer: String::new(),
+        }
+    }
+}
+
+impl Theme {
+    /// What's wrong with this theme, if anything.
+    pub fn validate(&self) -> Result<(), String> {
+        for (key, value, max) in [
+            ("couple", &self.couple, 80),
+            ("welcome", &self.welcome, 280),
+            ("footer", &self.footer, 200),
+        ] {
+            if value.chars().count() > max {
+                return Err(format!("theme.{key} must be at most {max} characters"));
+            }
+        }
+        for (key, value) in [("accent", &self.accent), ("background", &self.background)] {
+            if !is_color(value) {
+                return Err(format!(
+                    "theme.{key} must be a colour like #d4af37, not '{value}'"
+                ));
+            }
+        }
+        if !self.date.is_empty() && parse_date(&self.date).is_none() {
+            return Err(format!(
+                "theme.date must be a date like 2026-06-20, not '{}'",
+                self.date
+            ));
+        }
+        if !self.photo.is_empty() && !is_file_name(&self.photo) {
+            return Err(format!(
+                "theme.photo must be a file name in the data directory's theme/, not '{}'",
+                self.photo
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn is_color(s: &str) -> bool {
+    s.len() == 7 && s.starts_with('#') && s[1..].bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn parse_date(s: &str) -> Option<time::Date> {
+    let mut parts = s.splitn(3, '-');
+    let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+    let month = time::Month::try_from(month.parse::<u8>().ok()?).ok()?;
+    time::Date::from_calendar_date(year.parse().ok()?, month, day.parse().ok()?).ok()
+}
+
+/// A plain name that can't leave the photo directory.
+fn is_file_name(s: &str) -> bool {
+    s.len() <= 100
+        && !s.starts_with('.')
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+}
+
+fn templates() -> &'static Environment<'static> {
+    static ENV: OnceLock<Environment<'static>> = OnceLock::new();
+    ENV.get_or_init(|| {
+        let mut env = Environment::new();
+        // `.html` templates escape everything they print
+        env.add_template(
+            "index.html",
+            include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/index.html")),
+        )
+        .expect("index.html is a valid template");
+        env
+    })
+}
+
+/// The guest page with `theme` filled in.
+pub fn render_index(theme: &Theme) -> Result<String, minijinja::Error> {
+    // in Dutch like the rest of the page; app.js redoes it in the guest's language
+    let date_text = parse_date(&theme.date).map(|d| {
+        format!(
+            "{} {} {}",
+            d.day(),
+            MONTHS[d.month() as usize - 1],
+            d.year()
+        )
+    });
+    templates().get_template("index.html")?.render(context! {
+        theme,
+        date_text,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct UpdateForm {
+    couple: Option<String>,
+    date: Option<String>,
+    accent: Option<String>,
+    background: Option<String>,
+    welcome: Option<String>,
+    footer: Option<String>,
+    /// Only to clear it; new photos are uploaded.
+    photo: Option<String>,
+}
+
+/// `POST /api/admin/theme`: changes the given theme fields; an empty one clears it.
+pub async fn update(
+    State(state): State<AppState>,
+    client: Client,
+    Form(f): Form<UpdateForm>,
+) -> impl IntoResponse {
+    let old_photo = state.settings.get().theme.photo;
+    let changes = state
+        .settings
+        .update(&client.admin(), |s| {
+            let t = &mut s.theme;
+            for (field, value) in [
+                (&mut t.couple, f.couple),
+                (&mut t.date, f.date),
+                (&mut t.accent, f.accent),
+                (&mut t.background, f.background),
+                (&mut t.welcome, f.welcome),
+                (&mut t.footer, f.footer),
+            ] {
+                if let Some(v) = value {
+                    *field = v.trim().to_string();
+                }
+            }
+            if f.photo.as_deref().is_some_and(|p| p.trim().is_empty()) {
+                t.photo.clear();
+            }
+        })
+        .await;
+    let changes = match changes {
+        Ok(changes) => changes,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let theme = state.settings.get().theme;
+    if theme.photo != old_photo {
+        remove_upload(&state.data_dir, &old_photo).await;
+    }
+    axum::Json(serde_json::json!({ "theme": theme, "changes": changes })).into_response()
+}
+
+/// `POST /api/admin/theme/photo`: a JPEG, PNG or WebP in the multipart field `photo` becomes
+/// the page's photo.
+pub async fn upload_photo(
+    State(state): State<AppState>,
+    client: Client,
+    mut form: Multipart,
+) -> impl IntoResponse {
+    let mut photo = None;
+    loop {
+        match form.next_field().await {
+            Ok(Some(field)) if field.name() == Some("photo") => match field.bytes().await {
+                Ok(bytes) => photo = Some(bytes),
+                Err(e) => return (e.status(), e.body_text()).into_response(),
+            },
+            Ok(Some(_)) => {}
+            Ok(None) => break,
+            Err(e) => return (e.status(), e.body_text()).into_response(),
+        }
+    }
+    let Some(photo) = photo else {
+        return (StatusCode::BAD_REQUEST, "no photo field").into_response();
+    };
+    let Some(ext) = image_extension(&photo) else {
+        return (StatusCode::BAD_REQUEST, "only JPEG, PNG or WebP images").into_response();
+    };
+
+    let dir = state.data_dir.join("theme");
+    let name = format!("{UPLOAD_PREFIX}{}.{ext}", &session::new_token()[..12]);
+    let stored = async {
+        tokio::fs::create_dir_all(&dir).await?;
+        tokio::fs::write(dir.join(&name), &photo).await
+    };
+    if let Err(e) = stored.await {
+        warn!(?e, "storing the theme photo failed");
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "storing the photo failed",
+        )
+            .into_response();
+    }
+    let old_photo = state.settings.get().theme.photo;
+    let by = client.admin();
+    if let Err(e) = state
+        .settings
+        .update(&by, |s| s.theme.photo = name.clone())
+        .await
+    {
+        remove_upload(&state.data_dir, &name).await;
+        return (StatusCode::BAD_REQUEST, e).into_response();
+    }
+    info!(by = %by.name, photo = %name, bytes = photo.len(), "theme photo uploaded");
+    remove_upload(&state.data_dir, &old_photo).await;
+    axum::Json(serde_json::json!({ "theme": state.settings.get().theme })).into_response()
+}
+
+/// The file extension for a supported image, judged by its first bytes.
+fn image_extension(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some("jpg")
+    } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("png")
+    } else if bytes.len() > 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("webp")
+    } else {
+        None
+    }
+}
+
+/// Deletes a replaced photo, if it was uploaded rather than put there by hand.
+async fn remove_upload(data_dir: &Path, name: &str) {
+    if !name.starts_with(UPLOAD_PREFIX) || !is_file_name(name) {
+        return;
+    }
+    if let Err(e) = tokio::fs::remove_file(data_dir.join("theme").join(name)).await {
+        warn!(?e, photo = %name, "removing the old theme photo failed");
+    }
+}
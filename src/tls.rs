@@ -0,0 +1,358 @@
+//! HTTPS served by the app itself, with certificate files of your own (picked up again when they
+//! change on disk) or with certificates from Let's Encrypt (`acme` feature). HTTP on the redirect
+//! port sends browsers to HTTPS. Without TLS the app serves plain HTTP, e.g. behind a proxy.
+
+use std::{net::IpAddr, path::PathBuf, sync::Mutex as StdMutex};
+
+#[cfg(feature = "tls")]
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+#[cfg(feature = "tls")]
+use axum::Router;
+use serde_json::json;
+#[cfg(feature = "tls")]
+use tokio::sync::watch;
+use tokio::time::Instant;
+#[cfg(feature = "tls")]
+use tracing::{info, warn};
+
+/// How often static certificate files are checked for changes.
+#[cfg(feature = "tls")]
+const RELOAD_CHECK: Duration = Duration::from_secs(15);
+
+/// Where the certificate comes from.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum TlsMode {
+    /// Plain HTTP on `BIND_HOST:BIND_PORT`.
+    #[default]
+    Off,
+    /// PEM files, e.g. from certbot or a company CA.
+    Static { cert: PathBuf, key: PathBuf },
+    /// Let's Encrypt via TLS-ALPN-01; the HTTPS port must be reachable as 443 from outside.
+    Acme {
+        domains: Vec<String>,
+        contact: Option<String>,
+        cache_dir: PathBuf,
+        // the staging directory issues untrusted certificates but has generous rate limits
+        staging: bool,
+    },
+}
+
+impl TlsMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TlsMode::Off => "off",
+            TlsMode::Static { .. } => "static",
+            TlsMode::Acme { .. } => "acme",
+        }
+    }
+
+    /// The cargo feature this mode needs, if the build lacks it.
+    pub fn missing_feature(&self) -> Option<&'static str> {
+        match self {
+            TlsMode::Off => None,
+            TlsMode::Static { .. } => cfg!(not(feature = "tls")).then_some("tls"),
+            TlsMode::Acme { .. } => cfg!(not(feature = "acme")).then_some("acme"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TlsConfig {
+    pub mode: TlsMode,
+    pub https_port: u16,
+    // None: no redirect listener
+    pub http_port: Option<u16>,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            mode: TlsMode::Off,
+            https_port: 443,
+            http_port: Some(80),
+        }
+    }
+}
+
+/// The configured mode and the last thing that happened to the certificate, for `/api/status`.
+#[derive(Debug)]
+pub struct TlsStatus {
+    cfg: TlsConfig,
+    last: StdMutex<Option<Event>>,
+}
+
+#[derive(Debug)]
+struct Event {
+    at: Instant,
+    ok: bool,
+    message: String,
+}
+
+impl TlsStatus {
+    pub fn new(cfg: TlsConfig) -> Self {
+        TlsStatus {
+            cfg,
+            last: StdMutex::new(None),
+        }
+    }
+
+    #[cfg_attr(not(feature = "tls"), allow(dead_code))]
+    pub fn config(&self) -> &TlsConfig {
+        &self.cfg
+    }
+
+    #[cfg_attr(not(feature = "tls"), allow(dead_code))]
+    fn event(&self, ok: bool, message: impl Into<String>) {
+        *self.last.lock().unwrap() = Some(Event {
+            at: Instant::now(),
+            ok,
+            message: message.into(),
+        });
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut v = json!({ "mode": self.cfg.mode.as_str() });
+        match &self.cfg.mode {
+            TlsMode::Off => return v,
+            TlsMode::Static { cert, key } => {
+                v["cert"] = cert.display().to_string().into();
+                v["key"] = key.display().to_string().into();
+            }
+            TlsMode::Acme {
+                domains, staging, ..
+            } => {
+                v["domains"] = json!(domains);
+                v["directory"] = if *staging { "staging" } else { "production" }.into();
+            }
+        }
+        v["https_port"] = self.cfg.https_port.into();
+        v["http_port"] = json!(self.cfg.http_port);
+        v["last_event"] = json!(self.last.lock().unwrap().as_ref().map(|e| json!({
+            "seconds_ago": e.at.elapsed().as_secs(),
+            "ok": e.ok,
+            "message": e.message,
+        })));
+        v
+    }
+}
+
+/// Serves `app` over HTTPS on `host`, plus the redirect from HTTP, until `stopping` turns true;
+/// open requests then get up to `timeout` to finish.
+#[cfg(feature = "tls")]
+pub async fn serve(
+    app: Router,
+    host: IpAddr,
+    status: Arc<TlsStatus>,
+    stopping: watch::Receiver<bool>,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let handle = axum_server::Handle::new();
+    let stop = handle.clone();
+    tokio::spawn(async move {
+        crate::shutdown::stopped(stopping).await;
+        stop.graceful_shutdown(Some(timeout));
+    });
+
+    let cfg = status.config().clone();
+    let addr = SocketAddr::new(host, cfg.https_port);
+    let https = async {
+        match &cfg.mode {
+            TlsMode::Off => anyhow::bail!("TLS is off"),
+            TlsMode::Static { cert, key } => {
+                serve_static(app, addr, cert, key, status.clone(), handle.clone()).await
+            }
+            #[cfg(feature = "acme")]
+            TlsMode::Acme {
+                domains,
+                contact,
+                cache_dir,
+                staging,
+            } => {
+                let acme = AcmeSettings {
+                    domains,
+                    contact: contact.as_deref(),
+                    cache_dir,
+                    staging: *staging,
+                };
+                serve_acme(app, addr, acme, status.clone(), handle.clone()).await
+            }
+            #[cfg(not(feature = "acme"))]
+            TlsMode::Acme { .. } => anyhow::bail!("this build lacks the acme feature"),
+        }
+    };
+    let http = async {
+        match cfg.http_port {
+            Some(port) => {
+                redirect(SocketAddr::new(host, port), cfg.https_port, handle.clone()).await
+            }
+            None => Ok(()),
+        }
+    };
+    tokio::try_join!(https, http)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "tls"))]
+pub async fn serve(
+    _app: axum::Router,
+    _host: IpAddr,
+    _status: std::sync::Arc<TlsStatus>,
+    _stopping: tokio::sync::watch::Receiver<bool>,
+    _timeout: std::time::Duration,
+) -> anyhow::Result<()> {
+    anyhow::bail!("this build lacks the tls feature")
+}
+
+#[cfg(feature = "tls")]
+async fn serve_static(
+    app: Router,
+    addr: SocketAddr,
+    cert: &std::path::Path,
+    key: &std::path::Path,
+    status: Arc<TlsStatus>,
+    handle: axum_server::Handle,
+) -> anyhow::Result<()> {
+    use anyhow::Context;
+    use axum_server::tls_rustls::RustlsConfig;
+
+    let config = RustlsConfig::from_pem_file(cert, key)
+        .await
+        .with_context(|| format!("loading {} / {}", cert.display(), key.display()))?;
+    status.event(true, "certificate loaded");
+    info!(%addr, cert = %cert.display(), "serving HTTPS");
+
+    // certbot and friends replace the files in place; the old certificate stays on a bad read
+    let (cert, key, reloaded) = (cert.to_path_buf(), key.to_path_buf(), config.clone());
+    tokio::spawn(async move {
+        let mut seen = modified(&cert, &key).await;
+        loop {
+            tokio::time::sleep(RELOAD_CHECK).await;
+            let now = modified(&cert, &key).await;
+            if now == seen {
+                continue;
+            }
+            seen = now;
+            match reloaded.reload_from_pem_file(&cert, &key).await {
+                Ok(()) => {
+                    info!(cert = %cert.display(), "certificate reloaded");
+                    status.event(true, "certificate reloaded");
+                }
+                Err(e) => {
+                    warn!(?e, cert = %cert.display(), "certificate reload failed");
+                    status.event(false, format!("reload failed, keeping the old one: {e}"));
+                }
+            }
+        }
+    });
+
+    axum_server::bind_rustls(addr, config)
+        .handle(handle)
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .await?;
+    Ok(())
+}
+
+/// Modification times of the certificate and key; None while one can't be read.
+#[cfg(feature = "tls")]
+async fn modified(
+    cert: &std::path::Path,
+    key: &std::path::Path,
+) -> Option<(std::time::SystemTime, std::time::SystemTime)> {
+    let cert = tokio::fs::metadata(cert).await.ok()?.modified().ok()?;
+    let key = tokio::fs::metadata(key).await.ok()?.modified().ok()?;
+    Some((cert, key))
+}
+
+#[cfg(feature = "acme")]
+struct AcmeSettings<'a> {
+    domains: &'a [String],
+    contact: Option<&'a str>,
+    cache_dir: &'a std::path::Path,
+    staging: bool,
+}
+
+#[cfg(feature = "acme")]
+async fn serve_acme(
+    app: Router,
+    addr: SocketAddr,
+    acme: AcmeSettings<'_>,
+    status: Arc<TlsStatus>,
+    handle: axum_server::Handle,
+) -> anyhow::Result<()> {
+    use futures_util::StreamExt;
+    use rustls_acme::{caches::DirCache, AcmeConfig};
+
+    let mut state = AcmeConfig::new(acme.domains)
+        .contact(acme.contact.map(|c| format!("mailto:{c}")))
+        .cache(DirCache::new(acme.cache_dir.to_path_buf()))
+        .directory_lets_encrypt(!acme.staging)
+        .state();
+    let acceptor = state.axum_acceptor(state.default_rustls_config());
+    // the state orders and renews the certificate as it is polled
+    tokio::spawn(async move {
+        while let Some(event) = state.next().await {
+            match event {
+                Ok(ok) => {
+                    info!(event = ?ok, "acme");
+                    status.event(true, format!("{ok:?}"));
+                }
+                Err(e) => {
+                    warn!(%e, "acme");
+                    status.event(false, e.to_string());
+                }
+            }
+        }
+    });
+    info!(%addr, domains = ?acme.domains, staging = acme.staging, "serving HTTPS (ACME)");
+
+    axum_server::bind(addr)
+        .acceptor(acceptor)
+        .handle(handle)
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .await?;
+    Ok(())
+}
+
+/// Sends every plain HTTP request to the same host and path over HTTPS.
+#[cfg(feature = "tls")]
+async fn redirect(
+    addr: SocketAddr,
+    https_port: u16,
+    handle: axum_server::Handle,
+) -> anyhow::Result<()> {
+    use axum::{
+        extract::Host,
+        http::{header, StatusCode, Uri},
+    };
+
+    let app = Router::new().fallback(move |Host(host): Host, uri: Uri| async move {
+        let location = https_location(&host, https_port, &uri);
+        (
+            StatusCode::MOVED_PERMANENTLY,
+            [(header::LOCATION, location)],
+        )
+    });
+    info!(%addr, "redirecting HTTP to HTTPS");
+    axum_server::bind(addr)
+        .handle(handle)
+        .serve(app.into_make_service())
+        .await?;
+    Ok(())
+}
+
+/// `https://host[:port]/path?query` for a request that came in as `host[:port]` over HTTP.
+#[cfg_attr(not(feature = "tls"), allow(dead_code))]
+pub fn https_location(host: &str, https_port: u16, uri: &axum::http::Uri) -> String {
+    // keep IPv6 brackets, drop the HTTP port
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if !port.is_empty() && port.bytes().all(|b| b.is_ascii_digit()) => name,
+        _ => host,
+    };
+    let path = uri.path_and_query().map_or("/", |p| p.as_str());
+    if https_port == 443 {
+        format!("https://{name}{path}")
+    } else {
+        format!("https://{name}:{https_port}{path}")
+    }
+}
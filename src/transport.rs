@@ -0,0 +1,304 @@
+//! How the app reaches a WLED device: through the SSH tunnel, directly, over the device's
+//! WebSocket, via a proxy, or relayed by an onsite agent.
+//!
+//! Every call to WLED goes through [`WledClient`], which hides the configured transport.
+
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    agent::{AgentLink, RelayMethod},
+    metrics,
+    wled::WledError,
+    wled_ws::WledSocket,
+};
+
+/// Longest a request to the device may take, start to finish; a hung device must not hold up
+/// the rotation.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest to wait for the TCP connection (or the proxy's).
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Network path to a WLED device.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Transport {
+    /// Local port forward over `ssh -L` (the default).
+    SshTunnel,
+    /// Plain HTTP to `wled_host:wled_port`, e.g. when running on the onsite laptop.
+    Direct,
+    /// Direct, with state updates sent over a persistent `/ws` WebSocket that also reports
+    /// changes made from the WLED app.
+    WebSocket,
+    /// Direct URL, but sent through an HTTP proxy.
+    HttpProxy(String),
+    /// Direct URL, but sent through a SOCKS5 proxy (e.g. `ssh -D` or Tailscale's userspace proxy).
+    Socks5(String),
+    /// Relayed over the WebSocket of a connected `trouw-gordijn agent`.
+    Agent,
+}
+
+impl Transport {
+    /// Builds the transport from a `WLED_TRANSPORT` value and an optional `WLED_PROXY` URL.
+    pub fn from_parts(kind: Option<&str>, proxy: Option<String>) -> anyhow::Result<Self> {
+        let kind: TransportKind = kind.unwrap_or("ssh-tunnel").parse()?;
+        Ok(match kind {
+            TransportKind::SshTunnel => Transport::SshTunnel,
+            TransportKind::Direct => Transport::Direct,
+            TransportKind::WebSocket => Transport::WebSocket,
+            TransportKind::Agent => Transport::Agent,
+            TransportKind::HttpProxy => Transport::HttpProxy(
+                proxy.ok_or_else(|| anyhow::anyhow!("http-proxy transport needs a proxy URL"))?,
+            ),
+            TransportKind::Socks5 => {
+                let url =
+                    proxy.ok_or_else(|| anyhow::anyhow!("socks5 transport needs a proxy URL"))?;
+                // let bare host:port work too
+                if url.contains("://") {
+                    Transport::Socks5(url)
+                } else {
+                    Transport::Socks5(format!("socks5h://{url}"))
+                }
+            }
+        })
+    }
+
+    pub fn uses_tunnel(&self) -> bool {
+        matches!(self, Transport::SshTunnel)
+    }
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transport::SshTunnel => f.write_str("ssh-tunnel"),
+            Transport::Direct => f.write_str("direct"),
+            Transport::WebSocket => f.write_str("websocket"),
+            Transport::HttpProxy(_) => f.write_str("http-proxy"),
+            Transport::Socks5(_) => f.write_str("socks5"),
+            Transport::Agent => f.write_str("agent"),
+        }
+    }
+}
+
+enum TransportKind {
+    SshTunnel,
+    Direct,
+    WebSocket,
+    HttpProxy,
+    Socks5,
+    Agent,
+}
+
+impl FromStr for TransportKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "ssh-tunnel" | "ssh" => Ok(TransportKind::SshTunnel),
+            "direct" => Ok(TransportKind::Direct),
+            "websocket" | "ws" => Ok(TransportKind::WebSocket),
+            "http-proxy" => Ok(TransportKind::HttpProxy),
+            "socks5" => Ok(TransportKind::Socks5),
+            "agent" => Ok(TransportKind::Agent),
+            other => anyhow::bail!(
+                "unknown transport '{other}' (expected ssh-tunnel, direct, websocket, http-proxy, socks5 or agent)"
+            ),
+        }
+    }
+}
+
+/// Client bound to one WLED device.
+#[derive(Clone)]
+pub struct WledClient {
+    inner: ClientInner,
+}
+
+#[derive(Clone)]
+enum ClientInner {
+    Http {
+        http: reqwest::Client,
+        base: String,
+    },
+    /// HTTP for reads, the socket for state updates while it is connected.
+    Socket {
+        http: reqwest::Client,
+        base: String,
+        socket: WledSocket,
+    },
+    Agent(AgentLink),
+    /// A router called in-process, e.g. the mock device in tests; no sockets involved.
+    #[cfg(test)]
+    Local(axum::Router),
+}
+
+impl WledClient {
+    pub fn new(
+        transport: &Transport,
+        wled_host: &str,
+        wled_port: u16,
+        local_tunnel_port: u16,
+    ) -> anyhow::Result<Self> {
+        let builder = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .connect_timeout(CONNECT_TIMEOUT);
+        let (builder, base) = match transport {
+            Transport::SshTunnel => (builder, format!("http://127.0.0.1:{local_tunnel_port}")),
+            Transport::Direct => (builder, format!("http://{wled_host}:{wled_port}")),
+            Transport::HttpProxy(proxy) | Transport::Socks5(proxy) => (
+                builder.proxy(reqwest::Proxy::all(proxy)?),
+                format!("http://{wled_host}:{wled_port}"),
+            ),
+            Transport::WebSocket => {
+                return Ok(WledClient {
+                    inner: ClientInner::Socket {
+                        http: builder.build()?,
+                        base: format!("http://{wled_host}:{wled_port}"),
+                        socket: WledSocket::new(wled_host, wled_port),
+                    },
+                })
+            }
+            Transport::Agent => {
+                return Ok(WledClient {
+                    inner: ClientInner::Agent(AgentLink::default()),
+                })
+            }
+        };
+        Ok(WledClient {
+            inner: ClientInner::Http {
+                http: builder.build()?,
+                base,
+            },
+        })
+    }
+
+    /// Client that calls `router` directly instead of going over the network.
+    #[cfg(test)]
+    pub fn local(router: axum::Router) -> Self {
+        WledClient {
+            inner: ClientInner::Local(router),
+        }
+    }
+
+    /// Where requests end up, for logging.
+    pub fn base(&self) -> &str {
+        match &self.inner {
+            ClientInner::Http { base, .. } | ClientInner::Socket { base, .. } => base,
+            ClientInner::Agent(_) => "agent relay",
+            #[cfg(test)]
+            ClientInner::Local(_) => "in-process",
+        }
+    }
+
+    /// The agent link, when this device is reached through an onsite agent.
+    pub fn agent_link(&self) -> Option<&AgentLink> {
+        match &self.inner {
+            ClientInner::Agent(link) => Some(link),
+            _ => None,
+        }
+    }
+
+    /// The device socket, when state updates go over `/ws`.
+    pub fn socket(&self) -> Option<&WledSocket> {
+        match &self.inner {
+            ClientInner::Socket { socket, .. } => Some(socket),
+            _ => None,
+        }
+    }
+
+    pub async fn get(&self, path: &str) -> Result<reqwest::Response, WledError> {
+        let started = Instant::now();
+        let res = self.get_inner(path).await;
+        metrics::wled_request(path, started.elapsed(), ok(&res));
+        res
+    }
+
+    async fn get_inner(&self, path: &str) -> Result<reqwest::Response, WledError> {
+        match &self.inner {
+            ClientInner::Http { http, base } | ClientInner::Socket { http, base, .. } => {
+                Ok(http.get(format!("{base}{path}")).send().await?)
+            }
+            ClientInner::Agent(link) => link.request(RelayMethod::Get, path, None).await,
+            #[cfg(test)]
+            ClientInner::Local(router) => local_request(router, "GET", path, None).await,
+        }
+    }
+
+    pub async fn post_json(
+        &self,
+        path: &str,
+        body: &serde_json::Value,
+    ) -> Result<reqwest::Response, WledError> {
+        let started = Instant::now();
+        let res = self.post_json_inner(path, body).await;
+        metrics::wled_request(path, started.elapsed(), ok(&res));
+        res
+    }
+
+    async fn post_json_inner(
+        &self,
+        path: &str,
+        body: &serde_json::Value,
+    ) -> Result<reqwest::Response, WledError> {
+        match &self.inner {
+            ClientInner::Http { http, base } => {
+                Ok(http.post(format!("{base}{path}")).json(body).send().await?)
+            }
+            ClientInner::Socket { http, base, socket } => {
+                // the socket only carries state updates that ask for the new state back
+                // (`"v": true`); everything else, and a down socket, uses HTTP
+                let wants_state = body.get("v") == Some(&serde_json::Value::Bool(true));
+                if path == "/json/state" && wants_state && socket.is_connected().await {
+                    let state = socket.send_state(body).await?;
+                    let res = axum::http::Response::builder()
+                        .body(state.to_string())
+                        .map_err(|e| WledError::Socket(e.to_string()))?;
+                    return Ok(reqwest::Response::from(res));
+                }
+                Ok(http.post(format!("{base}{path}")).json(body).send().await?)
+            }
+            ClientInner::Agent(link) => link.request(RelayMethod::Post, path, Some(body)).await,
+            #[cfg(test)]
+            ClientInner::Local(router) => local_request(router, "POST", path, Some(body)).await,
+        }
+    }
+}
+
+/// Whether a request got a successful answer.
+fn ok(res: &Result<reqwest::Response, WledError>) -> bool {
+    res.as_ref().is_ok_and(|r| r.status().is_success())
+}
+
+#[cfg(test)]
+async fn local_request(
+    router: &axum::Router,
+    method: &str,
+    path: &str,
+    body: Option<&serde_json::Value>,
+) -> Result<reqwest::Response, WledError> {
+    use tower::ServiceExt;
+
+    let local_err = |e: &dyn std::fmt::Display| WledError::Relay(e.to_string());
+    let req = axum::http::Request::builder()
+        .method(method)
+        .uri(path)
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .body(axum::body::Body::from(
+            body.map(|b| b.to_string()).unwrap_or_default(),
+        ))
+        .map_err(|e| local_err(&e))?;
+    let res = router
+        .clone()
+        .oneshot(req)
+        .await
+        .map_err(|e| local_err(&e))?;
+    let (parts, body) = res.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .map_err(|e| local_err(&e))?;
+    Ok(reqwest::Response::from(axum::http::Response::from_parts(
+        parts, bytes,
+    )))
+}
@@ -0,0 +1,539 @@
+//! Typed client for WLED's JSON API.
+//!
+//! Covers `/json/state`, `/json/info`, `/json/effects`, `/json/palettes`, `/json/fxdata` and
+//! `/presets.json` on top of [`WledClient`], which takes care of the transport.
+
+use std::collections::BTreeMap;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::transport::WledClient;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WledError {
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("agent relay: {0}")]
+    Relay(String),
+    #[error("WLED socket: {0}")]
+    Socket(String),
+    #[error("{path} returned HTTP {status}")]
+    Status { path: String, status: u16 },
+    #[error("could not decode {path}: {source}")]
+    Decode {
+        path: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("could not encode request: {0}")]
+    Encode(#[source] serde_json::Error),
+    #[error("device did not confirm the update")]
+    NotConfirmed,
+}
+
+impl WledError {
+    /// The device (or the way to it) didn't answer, as opposed to answering with something
+    /// other than asked for.
+    pub fn is_unreachable(&self) -> bool {
+        matches!(
+            self,
+            WledError::Http(_)
+                | WledError::Relay(_)
+                | WledError::Socket(_)
+                | WledError::Status { .. }
+        )
+    }
+}
+
+pub type Result<T, E = WledError> = std::result::Result<T, E>;
+
+/// Firmware version as reported in `info.ver`, e.g. `0.14.0-b1`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl Version {
+    /// First release that takes segment options as one `o` array instead of `o1`.
+    pub const OPTIONS_ARRAY: Version = Version::new(0, 14, 0);
+
+    pub const fn new(major: u16, minor: u16, patch: u16) -> Self {
+        Version {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        let core = s.trim().trim_start_matches('v');
+        let core = core.split(['-', '+', ' ']).next()?;
+        let mut parts = core.split('.').map(|p| p.parse::<u16>());
+        Some(Version::new(
+            parts.next()?.ok()?,
+            parts.next().unwrap_or(Ok(0)).ok()?,
+            parts.next().unwrap_or(Ok(0)).ok()?,
+        ))
+    }
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// `state` object as returned by `/json/state` (or by a POST with `"v": true`).
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct State {
+    #[serde(default)]
+    pub on: bool,
+    pub bri: Option<u8>,
+    /// Active preset, -1 when none.
+    pub ps: Option<i32>,
+    #[serde(default)]
+    pub seg: Vec<Segment>,
+}
+
+impl State {
+    pub fn segment(&self, id: u8) -> Option<&Segment> {
+        self.seg.iter().find(|s| s.id == id)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Segment {
+    #[serde(default)]
+    pub id: u8,
+    /// Segment name; the scrolling text effect shows it.
+    pub n: Option<String>,
+    #[serde(default)]
+    pub col: Vec<Color>,
+    pub fx: Option<u16>,
+    pub pal: Option<u16>,
+    pub c1: Option<u8>,
+    pub c2: Option<u8>,
+    pub o1: Option<bool>,
+}
+
+/// A segment colour; WLED reports `[r, g, b(, w)]` or, on some builds, a hex string.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Color {
+    Channels(Vec<u8>),
+    Hex(String),
+}
+
+impl Color {
+    pub fn rgb(&self) -> Option<(u8, u8, u8)> {
+        match self {
+            Color::Channels(c) if c.len() >= 3 => Some((c[0], c[1], c[2])),
+            Color::Channels(_) => None,
+            Color::Hex(h) => crate::parse_hex_color(h),
+        }
+    }
+}
+
+/// Partial state sent to `/json/state`; unset fields are left alone by WLED.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct StateUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bri: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ps: Option<i32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub seg: Vec<SegmentUpdate>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SegmentUpdate {
+    pub id: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub col: Option<Vec<[u8; 3]>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fx: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pal: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub c1: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub c2: Option<u8>,
+    /// Effect options; set through [`SegmentUpdate::with_options`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub o: Option<Vec<u8>>,
+    /// Legacy first effect option; set through [`SegmentUpdate::with_options`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub o1: Option<u8>,
+}
+
+impl SegmentUpdate {
+    /// Sets the effect options (`o1`, `o2`, ...) in the encoding the firmware understands:
+    /// the `o` array from 0.14 on, the legacy `o1` field before that, and both when the
+    /// version is unknown.
+    pub fn with_options(mut self, values: &[u8], firmware: Option<Version>) -> Self {
+        let new_style = firmware.map(|v| v >= Version::OPTIONS_ARRAY);
+        self.o = (new_style != Some(false)).then(|| values.to_vec());
+        self.o1 = if new_style != Some(true) {
+            values.first().copied()
+        } else {
+            None
+        };
+        self
+    }
+}
+
+/// `/json/info`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Info {
+    #[serde(default)]
+    pub ver: String,
+    /// Build id, changes with every flash.
+    #[serde(default)]
+    pub vid: u64,
+    #[serde(default)]
+    pub name: String,
+    /// Chip family, e.g. `esp32` or `esp8266`.
+    #[serde(default)]
+    pub arch: String,
+    #[serde(default)]
+    pub leds: Leds,
+    #[serde(default)]
+    pub fxcount: u16,
+    #[serde(default)]
+    pub palcount: u16,
+    /// Usermod info, keyed by usermod; the shape varies per usermod.
+    #[serde(default)]
+    pub u: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Info {
+    pub fn version(&self) -> Option<Version> {
+        Version::parse(&self.ver)
+    }
+
+    /// How many bytes of a segment name this build keeps.
+    pub fn segment_name_len(&self) -> usize {
+        if self.arch.starts_with("esp32") {
+            LONG_SEGMENT_NAME
+        } else {
+            SHORT_SEGMENT_NAME
+        }
+    }
+}
+
+/// ESP8266 builds (and those that don't report their chip) keep this many bytes of a segment
+/// name and drop the rest.
+pub const SHORT_SEGMENT_NAME: usize = 32;
+/// ESP32 builds keep this many; no build shows a longer text.
+pub const LONG_SEGMENT_NAME: usize = 64;
+
+/// The longest start of `text` that fits in `max` bytes without splitting a character.
+pub fn truncate_name(text: &str, max: usize) -> &str {
+    if text.len() <= max {
+        return text;
+    }
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// Whether a segment name the device reports is `requested`, allowing for a build that cut it
+/// shorter than expected (never shorter than [`SHORT_SEGMENT_NAME`]).
+fn name_shows(reported: &str, requested: &str) -> bool {
+    reported == requested
+        || (reported.len() >= SHORT_SEGMENT_NAME.min(requested.len())
+            && requested.starts_with(reported))
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Leds {
+    #[serde(default)]
+    pub count: u32,
+    /// Present on 2D (matrix) builds.
+    pub matrix: Option<Matrix>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct Matrix {
+    pub w: u16,
+    pub h: u16,
+}
+
+/// `/json/effects`: effect names by index.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Effects(pub Vec<String>);
+
+impl Effects {
+    /// Index of the scrolling text effect, falling back to anything with "text" in its name.
+    pub fn scrolling_text(&self) -> Option<u16> {
+        let mut candidate = None;
+        for (i, name) in self.0.iter().enumerate() {
+            let lc = name.to_lowercase();
+            if lc.contains("scroll") && lc.contains("text") {
+                return u16::try_from(i).ok();
+            }
+            if candidate.is_none() && lc.contains("text") {
+                candidate = u16::try_from(i).ok();
+            }
+        }
+        candidate
+    }
+}
+
+/// `/json/palettes`: palette names by index.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Palettes(pub Vec<String>);
+
+impl Palettes {
+    /// Index of a palette that renders Color 1 as-is.
+    pub fn color1(&self) -> Option<u16> {
+        self.0
+            .iter()
+            .position(|name| {
+                let lc = name.to_lowercase();
+                lc.contains("primary")
+                    || lc.contains("color 1")
+                    || lc.contains("single")
+                    || lc.contains("solid")
+            })
+            .and_then(|i| u16::try_from(i).ok())
+    }
+}
+
+/// One entry of `/json/fxdata`, e.g. `@!,!;!,!;!;2`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct FxData {
+    /// Labels of the speed/intensity/custom sliders and option checkboxes; `!` means default.
+    pub controls: Vec<String>,
+    /// Whether the effect needs a 2D matrix.
+    pub is_2d: bool,
+}
+
+impl FxData {
+    pub fn parse(raw: &str) -> Self {
+        let spec = raw.split_once('@').map_or("", |(_, spec)| spec);
+        let mut sections = spec.split(';');
+        let controls = sections
+            .next()
+            .filter(|s| !s.is_empty())
+            .map(|s| s.split(',').map(str::to_string).collect())
+            .unwrap_or_default();
+        let flags = sections.nth(2).unwrap_or("");
+        FxData {
+            controls,
+            is_2d: flags.contains('2'),
+        }
+    }
+}
+
+/// A snapshot of the LED colours from `/json/live`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Live {
+    /// `RRGGBB` per (possibly downsampled) LED.
+    pub leds: Vec<String>,
+    /// Downsampling factor for long strips and large matrices.
+    #[serde(default = "one")]
+    pub n: u32,
+    /// Matrix size after downsampling, on 2D builds.
+    pub w: Option<u32>,
+    pub h: Option<u32>,
+}
+
+fn one() -> u32 {
+    1
+}
+
+/// One stored preset from `/presets.json`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Preset {
+    pub id: u16,
+    pub name: Option<String>,
+    /// Quick-load label shown in the WLED UI.
+    pub quick_label: Option<String>,
+    /// The stored state (or playlist) body.
+    pub body: serde_json::Map<String, serde_json::Value>,
+}
+
+impl WledClient {
+    async fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let res = self.get(path).await?;
+        decode(path, res).await
+    }
+
+    pub async fn state(&self) -> Result<State> {
+        self.get_json("/json/state").await
+    }
+
+    /// Applies `update` and returns the state WLED reports afterwards.
+    pub async fn set_state(&self, update: &StateUpdate) -> Result<State> {
+        let mut body = serde_json::to_value(update).map_err(WledError::Encode)?;
+        body["v"] = serde_json::Value::Bool(true);
+        let res = self.post_json("/json/state", &body).await?;
+        decode("/json/state", res).await
+    }
+
+    pub async fn info(&self) -> Result<Info> {
+        self.get_json("/json/info").await
+    }
+
+    pub async fn effects(&self) -> Result<Effects> {
+        self.get_json("/json/effects").await
+    }
+
+    pub async fn palettes(&self) -> Result<Palettes> {
+        self.get_json("/json/palettes").await
+    }
+
+    pub async fn fxdata(&self) -> Result<Vec<FxData>> {
+        let raw: Vec<String> = self.get_json("/json/fxdata").await?;
+        Ok(raw.iter().map(|r| FxData::parse(r)).collect())
+    }
+
+    pub async fn live(&self) -> Result<Live> {
+        self.get_json("/json/live").await
+    }
+
+    pub async fn presets(&self) -> Result<Vec<Preset>> {
+        let raw: BTreeMap<String, serde_json::Value> = self.get_json("/presets.json").await?;
+        let mut presets: Vec<Preset> = raw
+            .into_iter()
+            .filter_map(|(id, v)| {
+                let id = id.parse().ok().filter(|&id| id > 0)?;
+                let mut body = match v {
+                    serde_json::Value::Object(m) if !m.is_empty() => m,
+                    _ => return None,
+                };
+                let take_str = |body: &mut serde_json::Map<_, _>, k: &str| match body.remove(k) {
+                    Some(serde_json::Value::String(s)) if !s.is_empty() => Some(s),
+                    _ => None,
+                };
+                Some(Preset {
+                    id,
+                    name: take_str(&mut body, "n"),
+                    quick_label: take_str(&mut body, "ql"),
+                    body,
+                })
+            })
+            .collect();
+        presets.sort_by_key(|p| p.id);
+        Ok(presets)
+    }
+
+    /// Stores the device's current state (brightness and segments included) as preset `id`.
+    pub async fn save_preset(&self, id: u16, name: &str) -> Result<()> {
+        let body = serde_json::json!({ "psave": id, "n": name, "ib": true, "sb": true });
+        let res = self.post_json("/json/state", &body).await?;
+        check_status("/json/state", &res)
+    }
+
+    /// Legacy `/win` HTTP API, e.g. `TT=<text>` for text usermods.
+    pub async fn win(&self, key: &str, value: &str) -> Result<()> {
+        let path = format!("/win?{}={}", key, urlencoding::encode(value));
+        let res = self.get(&path).await?;
+        check_status(&path, &res)
+    }
+}
+
+fn check_status(path: &str, res: &reqwest::Response) -> Result<()> {
+    if res.status().is_success() {
+        Ok(())
+    } else {
+        Err(WledError::Status {
+            path: path.to_string(),
+            status: res.status().as_u16(),
+        })
+    }
+}
+
+async fn decode<T: DeserializeOwned>(path: &str, res: reqwest::Response) -> Result<T> {
+    check_status(path, &res)?;
+    let bytes = res.bytes().await?;
+    serde_json::from_slice(&bytes).map_err(|source| WledError::Decode {
+        path: path.to_string(),
+        source,
+    })
+}
+
+/// What we asked the device (and its segment 0) to look like.
+#[derive(Clone, Debug)]
+pub struct ExpectedDisplay<'a> {
+    pub bri: u8,
+    pub text: &'a str,
+    pub rgb: (u8, u8, u8),
+    pub fx: Option<u16>,
+    pub pal: Option<u16>,
+    pub c1: u8,
+    pub c2: u8,
+    pub o1: bool,
+}
+
+impl ExpectedDisplay<'_> {
+    /// Compares the device state with the request. Fields the device doesn't report (older
+    /// firmware) are listed as unsupported rather than silently passing.
+    pub fn mismatches(&self, state: &State) -> Vec<String> {
+        let mut out = Vec::new();
+        if !state.on {
+            out.push("on: requested true, device reports false".into());
+        }
+        check(&mut out, "bri", Some(self.bri), state.bri);
+        let Some(seg) = state.segment(0) else {
+            out.push("seg 0: missing from device state".into());
+            return out;
+        };
+        match seg.n.as_deref() {
+            Some(n) if name_shows(n, self.text) => {}
+            Some(n) => out.push(format!(
+                "n: requested {:?}, device reports {:?}",
+                self.text, n
+            )),
+            None => out.push("n: unsupported by firmware".into()),
+        }
+        match seg.col.first().and_then(Color::rgb) {
+            Some(rgb) if rgb == self.rgb => {}
+            Some(rgb) => out.push(format!(
+                "col: requested {:?}, device reports {:?}",
+                self.rgb, rgb
+            )),
+            None => out.push("col: not reported".into()),
+        }
+        check(&mut out, "fx", self.fx, seg.fx);
+        check(&mut out, "pal", self.pal, seg.pal);
+        check(&mut out, "c1", Some(self.c1), seg.c1);
+        check(&mut out, "c2", Some(self.c2), seg.c2);
+        check(&mut out, "o1", Some(self.o1), seg.o1);
+        out
+    }
+
+    /// Whether the text itself made it; anything else is reported but not fatal.
+    pub fn text_shown(&self, state: &State) -> bool {
+        state.on
+            && state
+                .segment(0)
+                .and_then(|s| s.n.as_deref())
+                .is_some_and(|n| name_shows(n, self.text))
+    }
+}
+
+fn check<T: PartialEq + std::fmt::Debug>(
+    out: &mut Vec<String>,
+    field: &str,
+    requested: Option<T>,
+    reported: Option<T>,
+) {
+    match (requested, reported) {
+        (None, _) => {}
+        (Some(r), Some(d)) if r == d => {}
+        (Some(r), Some(d)) => out.push(format!("{field}: requested {r:?}, device reports {d:?}")),
+        (Some(_), None) => out.push(format!("{field}: unsupported by firmware")),
+    }
+}
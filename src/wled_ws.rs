@@ -0,0 +1,239 @@
+//! Persistent WebSocket to a WLED device's `/ws` endpoint.
+//!
+//! State updates are sent over the socket and answered by the device's state push, and pushes
+//! that don't follow one of our own commands are recorded as changes made from elsewhere (the
+//! WLED app, a remote, a button on the controller).
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::{
+    sync::{mpsc, oneshot, Mutex},
+    time,
+};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{error, info, warn};
+
+use crate::wled::{self, WledError};
+
+/// How long a command waits for the device to push the resulting state.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to wait for the WebSocket handshake.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Pushes this soon after our own command are treated as its echo (presets apply asynchronously).
+const ECHO_GRACE: Duration = Duration::from_secs(2);
+
+/// A state change on the device that the app didn't ask for.
+#[derive(Clone, Debug)]
+pub struct ExternalChange {
+    pub at: Instant,
+    pub state: wled::State,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SocketStatus {
+    pub connected: bool,
+    pub last_push: Option<Instant>,
+    pub external_change: Option<ExternalChange>,
+}
+
+impl SocketStatus {
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "connected": self.connected,
+            "last_push_seconds": self.last_push.map(|t| t.elapsed().as_secs()),
+            "external_change": self.external_change.as_ref().map(|c| {
+                let seg = c.state.segment(0);
+                serde_json::json!({
+                    "seconds_ago": c.at.elapsed().as_secs(),
+                    "on": c.state.on,
+                    "bri": c.state.bri,
+                    "ps": c.state.ps,
+                    "fx": seg.and_then(|s| s.fx),
+                    "text": seg.and_then(|s| s.n.clone()),
+                })
+            }),
+        })
+    }
+}
+
+/// Handle to the socket of one device; cheap to clone.
+#[derive(Clone)]
+pub struct WledSocket {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    url: String,
+    conn: Mutex<Option<mpsc::UnboundedSender<Message>>>,
+    reply_timeout: Duration,
+    // commands waiting for the next state push, oldest first, by command number
+    waiters: Mutex<VecDeque<(u64, oneshot::Sender<serde_json::Value>)>>,
+    sent: AtomicU64,
+    last_command: Mutex<Option<Instant>>,
+    status: Mutex<SocketStatus>,
+}
+
+impl WledSocket {
+    pub fn new(wled_host: &str, wled_port: u16) -> Self {
+        Self::with_reply_timeout(wled_host, wled_port, REPLY_TIMEOUT)
+    }
+
+    /// [`WledSocket::new`], waiting `reply_timeout` for each state push.
+    pub fn with_reply_timeout(wled_host: &str, wled_port: u16, reply_timeout: Duration) -> Self {
+        WledSocket {
+            inner: Arc::new(Inner {
+                url: format!("ws://{wled_host}:{wled_port}/ws"),
+                conn: Mutex::new(None),
+                reply_timeout,
+                waiters: Mutex::new(VecDeque::new()),
+                sent: AtomicU64::new(0),
+                last_command: Mutex::new(None),
+                status: Mutex::new(SocketStatus::default()),
+            }),
+        }
+    }
+
+    pub async fn status(&self) -> SocketStatus {
+        self.inner.status.lock().await.clone()
+    }
+
+    pub async fn is_connected(&self) -> bool {
+        self.inner.conn.lock().await.is_some()
+    }
+
+    /// Sends a state update and returns the state the device pushes back.
+    pub async fn send_state(
+        &self,
+        body: &serde_json::Value,
+    ) -> Result<serde_json::Value, WledError> {
+        let text = serde_json::to_string(body).map_err(WledError::Encode)?;
+        let (tx, rx) = oneshot::channel();
+        let id = self.inner.sent.fetch_add(1, Ordering::Relaxed);
+        {
+            // hold the connection lock so waiters stay in the order commands were sent
+            let conn = self.inner.conn.lock().await;
+            let Some(conn) = conn.as_ref() else {
+                return Err(WledError::Socket("not connected".into()));
+            };
+            self.inner.waiters.lock().await.push_back((id, tx));
+            *self.inner.last_command.lock().await = Some(Instant::now());
+            if conn.send(Message::Text(text)).is_err() {
+                return Err(WledError::Socket("connection closed".into()));
+            }
+        }
+        match time::timeout(self.inner.reply_timeout, rx).await {
+            Ok(Ok(state)) => Ok(state),
+            Ok(Err(_)) => Err(WledError::Socket("connection closed".into())),
+            Err(_) => {
+                // a lost reply must not shift every later command onto the previous one's push
+                self.inner.waiters.lock().await.retain(|(w, _)| *w != id);
+                Err(WledError::Socket("no state push in time".into()))
+            }
+        }
+    }
+
+    /// Keeps the socket connected forever, reconnecting with backoff.
+    pub async fn run(self) {
+        let mut backoff = Duration::from_secs(1);
+        loop {
+            let started = Instant::now();
+            match self.run_once().await {
+                Ok(()) => info!(url = %self.inner.url, "WLED socket closed"),
+                Err(e) => warn!(?e, url = %self.inner.url, "WLED socket failed"),
+            }
+            *self.inner.conn.lock().await = None;
+            // dropping the senders fails every command still waiting
+            self.inner.waiters.lock().await.clear();
+            self.inner.status.lock().await.connected = false;
+            if started.elapsed() > Duration::from_secs(60) {
+                backoff = Duration::from_secs(1);
+            }
+            time::sleep(backoff).await;
+            backoff = (backoff * 2).min(Duration::from_secs(30));
+        }
+    }
+
+    async fn run_once(&self) -> anyhow::Result<()> {
+        let connect = tokio_tungstenite::connect_async(self.inner.url.as_str());
+        let (ws, _) = time::timeout(CONNECT_TIMEOUT, connect).await??;
+        info!(url = %self.inner.url, "WLED socket connected");
+        let (mut sink, mut stream) = ws.split();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        // the device greets every client with its full state; that is our baseline, not a
+        // change, and commands only go out once it has arrived
+        let mut greeted = false;
+        loop {
+            tokio::select! {
+                msg = stream.next() => {
+                    let text = match msg {
+                        Some(Ok(Message::Text(t))) => t,
+                        Some(Ok(Message::Close(_))) | None => return Ok(()),
+                        Some(Ok(_)) => continue,
+                        Some(Err(e)) => return Err(e.into()),
+                    };
+                    let mut msg: serde_json::Value = match serde_json::from_str(&text) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            warn!(?e, "bad WLED socket message");
+                            continue;
+                        }
+                    };
+                    let Some(state) = msg.get_mut("state").map(serde_json::Value::take) else {
+                        continue;
+                    };
+                    if greeted {
+                        self.on_push(state).await;
+                    } else {
+                        greeted = true;
+                        *self.inner.conn.lock().await = Some(tx.clone());
+                        let mut status = self.inner.status.lock().await;
+                        status.connected = true;
+                        status.last_push = Some(Instant::now());
+                    }
+                }
+                Some(out) = rx.recv() => sink.send(out).await?,
+            }
+        }
+    }
+
+    async fn on_push(&self, state: serde_json::Value) {
+        self.inner.status.lock().await.last_push = Some(Instant::now());
+        {
+            let mut waiters = self.inner.waiters.lock().await;
+            // skip commands that gave up waiting but haven't taken themselves out yet
+            while let Some((_, waiter)) = waiters.pop_front() {
+                if !waiter.is_closed() {
+                    let _ = waiter.send(state);
+                    return;
+                }
+            }
+        }
+        let echo = self
+            .inner
+            .last_command
+            .lock()
+            .await
+            .is_some_and(|t| t.elapsed() < ECHO_GRACE);
+        if echo {
+            return;
+        }
+        match serde_json::from_value::<wled::State>(state) {
+            Ok(state) => {
+                info!(url = %self.inner.url, "WLED state changed from elsewhere");
+                self.inner.status.lock().await.external_change = Some(ExternalChange {
+                    at: Instant::now(),
+                    state,
+                });
+            }
+            Err(e) => error!(?e, "could not decode pushed WLED state"),
+        }
+    }
+}